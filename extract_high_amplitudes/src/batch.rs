@@ -0,0 +1,232 @@
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize,Ordering};
+use std::time::Instant;
+use clap::{Args,Command,FromArgMatches};
+use serde::Serialize;
+use anyhow::{Context,bail};
+use crate::{CommonArgs,InputOrder,expand_inputs};
+use crate::extract::ExtractArgs;
+use shared::progress::NoProgress;
+use shared::error::GprError;
+
+
+
+#[derive(Debug , Args)]
+#[command(about = "Run extract on every file in folder or matching glob.", long_about = "Run extract on every file in folder or matching glob.
+--input is a folder (every .txt file in it) or glob like \"survey/*/*.txt\".
+Output of each file comes from --output-template with {dir}, {stem} and {name} of input replaced.
+Files with existing output are skipped unless --overwrite.
+--output-params and --mesh-output from --config or extract options are templates like --output-template
+and need {stem} or {name} when there is more than one input, so files don't write over each other.
+Extraction options come from --config and/or extract options after --, e.g.
+	batch -i survey --jobs 4 -- --amplitude-threshold 12000 --geom-type multipolygon-z
+Prints summary table of file, traces, clusters, features, bad lines, runtime and errors at end. Also written to --summary csv.
+")]
+pub struct BatchArgs {
+	#[command(flatten)]
+	pub common: CommonArgs,
+	
+	#[arg(long, default_value_t = String::from("{dir}/{stem}_features.csv"))]
+	pub output_template: String,
+	
+	//0 for number of cpus
+	#[arg(short, long, default_value_t = 0)]
+	pub jobs: usize,
+	
+	#[arg(long)]
+	pub config: Option<String>,
+	
+	#[arg(long)]
+	pub summary: Option<String>,
+	
+	#[arg(last = true)]
+	pub extract_args: Vec<String>,
+}
+
+
+
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct BatchRow{
+	pub file: String,
+	pub status: String,//done, skipped or failed
+	pub traces: Option<usize>,
+	pub clusters: Option<usize>,
+	pub features: Option<usize>,
+	pub bad_lines: Option<usize>,
+	pub seconds: f64,
+	pub error: String,
+}
+
+
+
+//true when template gives different name for each input
+fn is_per_file(template:&str) -> bool{
+	return template.contains("{stem}") || template.contains("{name}");
+}
+
+
+//replaces {dir}, {stem} and {name}
+pub fn output_name(template:&str, input:&Path) -> String{
+	let dir = input.parent().map(|d| d.to_string_lossy().into_owned()).filter(|d| d != "").unwrap_or(String::from("."));
+	let stem = input.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+	let name = input.file_name().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+	return template.replace("{dir}", &dir).replace("{stem}", &stem).replace("{name}", &name);
+}
+
+
+
+impl BatchArgs{
+	
+	
+	//same parsing as extract subcommand so options behave identically. other outputs of extract are still templates.
+	fn parse_extract_args(&self, input:&str, output:&str) -> anyhow::Result<ExtractArgs>{
+		let mut argv:Vec<String> = vec!["extract".into(), "--input".into(), input.into(), "--output".into(), output.into()];
+		if self.common.overwrite{
+			argv.push("--overwrite".into());
+		}
+		if self.common.strict{
+			argv.push("--strict".into());
+		}
+		if self.common.trace_order == InputOrder::LineMajor{
+			argv.push("--trace-order=line-major".into());
+		}
+		if self.common.reverse_longitudinal{
+			argv.push("--reverse-longitudinal".into());
+		}
+		if self.common.reverse_transverse{
+			argv.push("--reverse-transverse".into());
+		}
+		if let Some(config) = &self.config{
+			argv.push("--config".into());
+			argv.push(config.clone());
+		}
+		argv.extend(self.extract_args.iter().cloned());
+		let matches = <ExtractArgs as clap::Args>::augment_args(Command::new("extract")).try_get_matches_from(argv).map_err(|e| GprError::config(e.to_string()))?;
+		return ExtractArgs::from_arg_matches(&matches).map_err(|e| GprError::config(e.to_string()))?.with_config(&matches);
+	}
+	
+	
+	//extract options for input with every output from templates
+	fn extract_args(&self, input:&str, output:&str) -> anyhow::Result<ExtractArgs>{
+		let mut args = self.parse_extract_args(input, output)?;
+		args.output_params = output_name(&args.output_params, Path::new(input));
+		args.mesh_output = args.mesh_output.map(|m| output_name(&m, Path::new(input)));
+		return Ok(args);
+	}
+	
+	
+	fn process(&self, input:&Path) -> BatchRow{
+		let start = Instant::now();
+		let file = input.to_string_lossy().into_owned();
+		let output = output_name(&self.output_template, input);
+		let mut row = BatchRow{file: file.clone(), ..Default::default()};
+		if Path::new(&output).exists() && !self.common.overwrite{
+			row.status = String::from("skipped");
+			return row;
+		}
+		match self.extract_args(&file, &output).and_then(|a| a.extract_with_progress(&NoProgress)){
+			Ok(summary) => {
+				row.status = String::from("done");
+				row.traces = Some(summary.traces);
+				row.clusters = Some(summary.clusters);
+				row.features = Some(summary.features);
+				row.bad_lines = Some(summary.bad_lines);
+			}
+			Err(e) => {
+				row.status = String::from("failed");
+				row.error = format!("{:#}", e);
+			}
+		}
+		row.seconds = start.elapsed().as_secs_f64();
+		return row;
+	}
+	
+	
+	pub fn batch(&self) -> anyhow::Result<()>{
+		let inputs = expand_inputs(&self.common.input, &["txt"])?;
+		if inputs.len() == 0{
+			bail!("No input files in '{}'", self.common.input);
+		}
+		//fail before starting on bad options
+		let check = self.parse_extract_args(&inputs[0].to_string_lossy(), "check").with_context(|| "Invalid extract options")?;
+		if inputs.len() > 1{
+			if check.output_params != "" && !is_per_file(&check.output_params){
+				bail!(GprError::config(format!("--output-params '{}' would be written by every file. Use {{stem}} or {{name}} in it", check.output_params)));
+			}
+			if let Some(mesh_output) = check.mesh_output.as_ref().filter(|m| !is_per_file(m)){
+				bail!(GprError::config(format!("--mesh-output '{}' would be written by every file. Use {{stem}} or {{name}} in it", mesh_output)));
+			}
+		}
+		if let Some(summary) = &self.summary{
+			crate::check_output(summary, self.common.overwrite)?;
+		}
+		
+		let jobs = match self.jobs{
+			0 => std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+			j => j,
+		}.min(inputs.len());
+		eprintln!("Processing {} files with {} workers", inputs.len(), jobs);
+		
+		let next = AtomicUsize::new(0);
+		let rows:Mutex<Vec<Option<BatchRow>>> = Mutex::new(vec![None; inputs.len()]);
+		std::thread::scope(|s| {
+			for _ in 0..jobs{
+				s.spawn(|| {
+					loop{
+						let i = next.fetch_add(1, Ordering::SeqCst);
+						if i >= inputs.len(){
+							break;
+						}
+						let row = self.process(&inputs[i]);
+						eprintln!("{} {}", row.status, row.file);
+						rows.lock().unwrap()[i] = Some(row);
+					}
+				});
+			}
+		});
+		let rows:Vec<BatchRow> = rows.into_inner().unwrap().into_iter().flatten().collect();
+		
+		print_summary(&rows);
+		if let Some(summary) = &self.summary{
+			let mut wtr = csv::Writer::from_path(summary).with_context(|| format!("Error writing '{}'", summary))?;
+			for row in rows.iter(){
+				wtr.serialize(row)?;
+			}
+			wtr.flush()?;
+		}
+		
+		let failed = rows.iter().filter(|r| r.status == "failed").count();
+		if failed > 0{
+			bail!("{} of {} files failed", failed, rows.len());
+		}
+		return Ok(());
+	}
+}
+
+
+
+fn print_summary(rows:&[BatchRow]){
+	let count = |v:Option<usize>| v.map(|v| v.to_string()).unwrap_or_default();
+	let width = rows.iter().map(|r| r.file.len()).max().unwrap_or(0).max(4);
+	println!("{:<width$} {:>8} {:>10} {:>9} {:>9} {:>9} {:>8} error", "file", "status", "traces", "clusters", "features", "bad lines", "seconds");
+	for r in rows.iter(){
+		println!("{:<width$} {:>8} {:>10} {:>9} {:>9} {:>9} {:>8.1} {}", r.file, r.status, count(r.traces), count(r.clusters), count(r.features), count(r.bad_lines), r.seconds, r.error);
+	}
+}
+
+
+
+
+#[cfg(test)]
+mod batch_tests{
+	use super::*;
+	
+	#[test]
+	fn test_output_name(){
+		assert_eq!(output_name("{dir}/{stem}_features.csv", Path::new("survey/lane 1.txt")), "survey/lane 1_features.csv");
+		assert_eq!(output_name("out/{name}.geojson", Path::new("lane.txt")), "out/lane.txt.geojson");
+		assert_eq!(output_name("{dir}/{stem}.csv", Path::new("lane.txt")), "./lane.csv");
+		assert!(is_per_file("{dir}/{stem}_params.json") && !is_per_file("{dir}/params.json"));
+	}
+}
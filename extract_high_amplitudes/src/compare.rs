@@ -0,0 +1,125 @@
+use std::path::Path;
+use clap::Args;
+use shared::amplitude_data::AmplitudeData;
+use shared::change::ChangeDetector;
+use shared::clustering::ClusterSettings;
+use shared::core::Amplitude;
+use shared::progress::Progress;
+use shared::position_data::Layer;
+use shared::geojson::{is_geojson,write_feature_collection};
+use anyhow::Context;
+use crate::config::parse_layers;
+use crate::{CommonArgs,DEFAULT_LAYERS,check_output,report_diagnostics};
+
+
+
+#[derive(Debug , Args)]
+#[command(about = "Compare 2 surveys of same area.", long_about = "Compare 2 surveys of same area.
+--input is the earlier survey and --after the later one. Both are text files exported by examiner.
+The later survey is resampled onto traces of the earlier one using trace positions.
+With --search-radius > 0 the later survey is also shifted by up to that many meters for best match of amplitudes.
+Clusters of both are found as in extract and compared within each layer.
+Output lists new, vanished, grown, shrunk and unchanged clusters with WKT geometry. GeoJSON for .geojson or .json.
+--difference writes later - earlier amplitudes as text file in examiner format which the viewer can open.
+")]
+pub struct CompareArgs {
+	#[command(flatten)]
+	pub common: CommonArgs,
+	
+	#[arg(long)]
+	pub after: String,
+	
+	#[arg(short,long)]
+    pub output: String,
+	
+	#[arg(long)]
+	pub difference: Option<String>,
+	
+	#[arg(long, default_value_t = 10000)]
+    pub amplitude_threshold: Amplitude,
+	
+	#[arg(long, default_value_t = 50)]
+    pub size_threshold: usize,
+	
+	#[arg(long, default_value_t = 5)]
+    pub max_gap: usize,
+	
+	//meters
+	#[arg(long, default_value_t = 0.072)]
+	pub max_distance: f64,
+	
+	//meters
+	#[arg(long, default_value_t = 0.0)]
+	pub search_radius: f64,
+	
+	//meters
+	#[arg(long, default_value_t = 0.036)]
+	pub search_step: f64,
+	
+	//fraction of area
+	#[arg(long, default_value_t = 0.2)]
+	pub growth: f64,
+	
+	#[arg(long, default_value_t = String::from(DEFAULT_LAYERS))]
+	pub layers: String,
+}
+
+
+
+fn read(file:&str, common:&CommonArgs, progress:&dyn Progress) -> anyhow::Result<AmplitudeData>{
+	eprintln!("Reading {}", file);
+	let (data,diagnostics) = AmplitudeData::read_traces(common.trace_parser(file)?, progress).with_context(|| format!("Error reading \"{}\"", file))?;
+	report_diagnostics(file, &diagnostics, None)?;
+	return Ok(data);
+}
+
+
+
+impl CompareArgs{
+	
+	
+	pub fn compare(&self) -> anyhow::Result<()>{
+		let layers: Vec<Layer> = parse_layers(&self.layers)?;
+		check_output(&self.output, self.common.overwrite)?;
+		if let Some(difference) = &self.difference{
+			check_output(difference, self.common.overwrite)?;
+		}
+		let detector = ChangeDetector{
+			max_distance: self.max_distance,
+			search_radius: self.search_radius,
+			search_step: self.search_step,
+			clusters: ClusterSettings{amplitude_threshold: self.amplitude_threshold, size_threshold: self.size_threshold, max_gap: self.max_gap},
+			growth: self.growth,
+		};
+		let progress = self.common.progress();
+		let before = read(&self.common.input, &self.common, &*progress)?;
+		let after = read(&self.after, &self.common, &*progress)?;
+		
+		let min_depth = layers.iter().map(|l| l.min_depth).min().unwrap_or(0);
+		let max_depth = layers.iter().map(|l| l.max_depth).max().unwrap_or(0);
+		let registration = detector.register(&before, &after, min_depth, max_depth);
+		eprintln!("Registration: shift {:.3},{:.3} correlation {:?}. {} of {} traces matched", registration.dx, registration.dy, registration.correlation,
+			registration.matched, before.longitudinal_size * before.transverse_size);
+		let resampled = detector.resample(&before, &after, &registration);
+		
+		if let Some(difference) = &self.difference{
+			eprintln!("Writing difference");
+			let header = format!("Difference {} - {}\nShift {} {}", self.after, self.common.input, registration.dx, registration.dy);
+			ChangeDetector::difference(&before, &resampled).write_text_file(Path::new(difference), &header)
+				.with_context(|| format!("Error writing '{}'", difference))?;
+		}
+		
+		eprintln!("Finding changes");
+		let changes = detector.changes(&before, &resampled, &layers)?;
+		eprintln!("{} changes", changes.len());
+		if is_geojson(&self.output){
+			return write_feature_collection(&changes, &self.output, Some(&serde_json::to_value(registration)?)).with_context(|| format!("Error writing '{}'", self.output));
+		}
+		let mut wtr = csv::Writer::from_path(&self.output).with_context(|| format!("Error writing '{}'", self.output))?;
+		for c in changes.iter(){
+			wtr.serialize(c)?;
+		}
+		wtr.flush()?;
+		return Ok(());
+	}
+}
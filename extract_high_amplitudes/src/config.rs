@@ -0,0 +1,158 @@
+use std::fs::File;
+use std::io::{Read,BufReader};
+use std::path::Path;
+use clap::ArgMatches;
+use clap::parser::ValueSource;
+use serde::{Serialize};
+use serde::de::DeserializeOwned;
+use serde_json::{Value,Map};
+use sha2::{Sha256,Digest};
+use shared::error::GprError;
+use shared::position_data::Layer;
+use anyhow::{Context,bail};
+
+
+/*
+config files for reproducible runs.
+
+--config accepts TOML or JSON with the same keys as the command line options (with _ instead of -).
+layers can be table array instead of JSON string.
+run metadata written by extract (effective config, tool version and input hash) can also be used as config.
+options given on command line override config file.
+*/
+
+
+#[derive(Serialize,Debug)]
+pub struct RunMetadata<T:Serialize>{
+	pub tool: String,
+	pub version: String,
+	pub input: String,
+	pub input_sha256: String,
+	pub config: T,
+}
+
+
+impl<T:Serialize> RunMetadata<T>{
+	
+	pub fn new(input:&str, config:T) -> anyhow::Result<RunMetadata<T>>{
+		Ok(RunMetadata{
+			tool: env!("CARGO_PKG_NAME").to_string(),
+			version: env!("CARGO_PKG_VERSION").to_string(),
+			input: input.to_string(),
+			input_sha256: file_hash(Path::new(input)).with_context(|| format!("Error hashing '{}'", input))?,
+			config: config,
+		})
+	}
+}
+
+
+
+pub fn file_hash(file:&Path) -> anyhow::Result<String>{
+	let mut reader = BufReader::new(File::open(file)?);
+	let mut hasher = Sha256::new();
+	let mut buffer = vec![0u8; 1 << 16];
+	loop{
+		let n = reader.read(&mut buffer)?;
+		if n == 0{
+			break;
+		}
+		hasher.update(&buffer[..n]);
+	}
+	return Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect());
+}
+
+
+
+//TOML when extension is .toml. JSON otherwise.
+pub fn read_config(file:&str) -> anyhow::Result<Map<String,Value>>{
+	let text = std::fs::read_to_string(file).with_context(|| format!("Error reading config '{}'", file))?;
+	let is_toml = Path::new(file).extension().map_or(false, |e| e.eq_ignore_ascii_case("toml"));
+	let value:Value = if is_toml{
+		toml::from_str(&text).map_err(|e| GprError::config(format!("Invalid TOML in '{}': {}", file, e)))?
+	}
+	else{
+		serde_json::from_str(&text).map_err(|e| GprError::config(format!("Invalid JSON in '{}': {}", file, e)))?
+	};
+	let mut map = match value{
+		Value::Object(map) => map,
+		_ => bail!(GprError::config(format!("Config '{}' is not a table", file))),
+	};
+	//run metadata
+	if let Some(Value::Object(config)) = map.remove("config"){
+		return Ok(config);
+	}
+	return Ok(map);
+}
+
+
+
+/*
+args with values from config where not given on command line.
+keys are serde names of args which are same as clap ids.
+*/
+pub fn apply_config<T:Serialize+DeserializeOwned>(args:&T, config:&Map<String,Value>, matches:&ArgMatches) -> anyhow::Result<T>{
+	let mut merged = match serde_json::to_value(args)?{
+		Value::Object(map) => map,
+		_ => bail!(GprError::config("args are not a table")),
+	};
+	for (key,value) in config.iter(){
+		if !merged.contains_key(key){
+			bail!(GprError::config(format!("Unknown config key '{}'", key)));
+		}
+		if matches.value_source(key) == Some(ValueSource::CommandLine){
+			continue;
+		}
+		let value = match (key.as_str(), value){
+			//layers arg is JSON string
+			("layers", Value::Array(_)) => Value::String(value.to_string()),
+			_ => value.clone(),
+		};
+		merged.insert(key.clone(), value);
+	}
+	return Ok(serde_json::from_value(Value::Object(merged)).map_err(|e| GprError::config(format!("Invalid config: {}", e)))?);
+}
+
+
+
+//--layers JSON. at least one layer.
+pub fn parse_layers(layers:&str) -> anyhow::Result<Vec<Layer>>{
+	let layers:Vec<Layer> = serde_json::from_str(layers).map_err(|e| GprError::config(format!("Invalid layers: {}", e)))?;
+	if layers.len() == 0{
+		bail!(GprError::config("No layers"));
+	}
+	return Ok(layers);
+}
+
+
+
+
+#[cfg(test)]
+mod config_tests{
+	use super::*;
+	use clap::{Parser,CommandFactory,FromArgMatches};
+	use serde::Deserialize;
+	
+	#[derive(Parser,Serialize,Deserialize,Debug)]
+	struct TestArgs{
+		#[arg(long, default_value_t = 1)]
+		a: usize,
+		#[arg(long, default_value_t = 2)]
+		b: usize,
+		#[arg(long, default_value_t = String::from("[]"))]
+		layers: String,
+	}
+	
+	#[test]
+	fn test_apply_config(){
+		let matches = TestArgs::command().get_matches_from(["test", "--a", "5"]);
+		let args = TestArgs::from_arg_matches(&matches).unwrap();
+		let config:Map<String,Value> = toml::from_str("a = 10\nb = 20\n[[layers]]\nmin_depth = 1\n").unwrap();
+		let merged = apply_config(&args, &config, &matches).unwrap();
+		assert_eq!((merged.a, merged.b), (5, 20), "command line overrides config");
+		assert_eq!(merged.layers, "[{\"min_depth\":1}]");
+		
+		let unknown:Map<String,Value> = toml::from_str("c = 1").unwrap();
+		let error = apply_config(&args, &unknown, &matches).unwrap_err();
+		assert_eq!(error.downcast_ref::<GprError>().map(|e| e.exit_code()), Some(2), "configuration exit code");
+	}
+}
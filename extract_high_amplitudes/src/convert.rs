@@ -0,0 +1,56 @@
+use std::path::Path;
+use clap::Args;
+use shared::position_data::ClusterFeature;
+use shared::error::GprError;
+use anyhow::{Context,bail};
+use crate::{CommonArgs,check_output};
+
+
+
+#[derive(Debug , Args)]
+#[command(about = "Convert feature file between csv with WKT and GeoJSON. Format from extensions.")]
+pub struct ConvertArgs {
+	#[command(flatten)]
+	pub common: CommonArgs,
+	
+	#[arg(short,long)]
+    pub output: String,
+}
+
+
+
+#[derive(Debug, PartialEq)]
+enum Format{
+	Csv,
+	GeoJson,
+}
+
+
+fn format(file:&str) -> anyhow::Result<Format>{
+	match Path::new(file).extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase()).as_deref(){
+		Some("csv") => Ok(Format::Csv),
+		Some("geojson") | Some("json") => Ok(Format::GeoJson),
+		_ => bail!(GprError::config(format!("Unknown format '{}'. Use .csv, .geojson or .json", file))),
+	}
+}
+
+
+
+impl ConvertArgs{
+	
+	
+	pub fn convert(&self) -> anyhow::Result<()>{
+		let input = &self.common.input;
+		check_output(&self.output, self.common.overwrite)?;
+		
+		let features = match format(input)?{
+			Format::Csv => ClusterFeature::read_csv(input),
+			Format::GeoJson => ClusterFeature::read_geojson(input),
+		}.with_context(|| format!("Error reading '{}'", input))?;
+		
+		match format(&self.output)?{
+			Format::Csv => ClusterFeature::write_csv(features, &self.output),
+			Format::GeoJson => ClusterFeature::write_geojson(&features, &self.output, None),
+		}.with_context(|| format!("Error writing '{}'", self.output))
+	}
+}
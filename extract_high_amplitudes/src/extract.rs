@@ -0,0 +1,272 @@
+use std::path::Path;
+use std::fs::File;
+use clap::{Args,ArgMatches};
+use shared::trace::MAX_LINES;
+use shared::position_data::{ClusterFeature,Layer};
+use shared::clustering::ClusterSettings;
+use shared::core::Amplitude;
+use shared::geometry_z::ZModel;
+use shared::mesh::Mesh;
+use shared::geojson::is_geojson;
+use shared::progress::Progress;
+use shared::amplitude_data::Z_SPACING;
+use shared::error::GprError;
+use serde::{Serialize,Deserialize};
+use geo::Point;
+use std::io::Write;
+use anyhow::{Context,bail};
+use crate::config::{RunMetadata,read_config,apply_config,parse_layers};
+use crate::{CommonArgs,PositionArgs,DEFAULT_LAYERS,check_output,output_next_to_input,report_diagnostics};
+
+
+
+#[derive(clap::ValueEnum, Clone, Default, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GeomType {
+	#[default]
+	Multipolygon,
+    Multipoint,
+	MultipointZ,
+	MultipolygonZ,
+	PolyhedralSurfaceZ,
+}
+
+
+
+#[derive(Debug , Args , Serialize , Deserialize , Clone)]
+#[command(about = "Find clusters of high amplitudes and write their geometry.", long_about = "Reads text file exported by examiner. Finds clusters of amplitudes > amplitude-threshold.
+filters out clusters containing < size-threshold amplitudes. 
+for depth ranges (0-50mm...450-500mm) gets geometry from parts of clusters within range.
+Outputs csv with WKT geometry and label for depth range. GeoJSON when output ends with .geojson or .json.
+Also outputs all points to csv to [input]_points.csv
+Run metadata (effective options, tool version and SHA-256 of input) is embedded in GeoJSON output
+or written to [output]_run.json next to csv output. It can be given to --config to repeat the run.
+
+The tool makes a list of clusters (a cluster is a group of connected samples) from all samples >= amplitude threshold.
+Samples count as connected when their x and y and z coordinates are within max_gap (5 sample units) of each other.
+for each cluster containing >= size_threshold(50) samples
+	it finds parts of the cluster within each depth range and outputs geometry and mean amplitude.
+
+Parameters:
+	--config: TOML (.toml) or JSON file of options. Keys are option names with _ instead of -, e.g.
+	amplitude_threshold = 12000
+	geom_type = \"multipolygon-z\"
+	[[layers]]
+	min_depth = 51
+	max_depth = 53
+	label = \"0-50mm\"
+	Options given on the command line override the file.
+	--amplitude-threshold: Integer from -32768 to 32768
+	--output-params: JSON file to write these arguments to for future reference.
+	--max-gap: Integer >=1 . Higher max_gap makes clusters more likely to merge.
+	Points considered connected if x,y and z within max_gap sample units of each other.
+	--size-threshold: Integer >=0 .Ignore clusters containing < size-threshold samples.
+	--pause: pause after running.
+	--geom-type: type of geometry to export as. multipoint, multipolygon or 3D types:
+	multipoint-z (point per sample), multipolygon-z (polygon at top of each depth range),
+	polyhedral-surface-z (prism from top to bottom of each depth range).
+	--zero-depth: sample index of surface. Z of 3D geometry is depth below this in meters.
+	--surface-level: elevation of surface. Z of 3D geometry is elevation instead of depth when given.
+	--start-chainage: chainage at start of centreline. Features get start/end chainage and min/max offset from centreline.
+	--route: centreline file (WKT LINESTRING or csv of x,y). Line through middle of traces when not given.
+	--position-report: csv to write missing, duplicate and outlier trace positions to.
+	--repair-positions: none, interpolate (linear along channel) or smooth (interpolate then moving average).
+	Bad positions are repaired before geometry is made.
+	--expected-spacing: meters between traces along channel for finding outliers. Median of positions when not given.
+	--reconstruct-positions: fill positions still missing from array geometry (channel spacing and heading from nearby traces).
+	--epsg: EPSG code of trace positions. Recorded in run metadata so merge can check lanes match.
+	--mesh-output: also write isosurface mesh of clusters to .obj, .ply or .glb. group per cluster, coloured by amplitude.
+	--strict: stop with error at first line that can't be parsed. Otherwise bad lines are skipped and counted
+	and rows with too few amplitudes are kept with the rest missing.
+	--diagnostics: csv to write bad lines to (line, column, kind, reason).
+	--trace-order: channel-major (every trace of 1st channel then next channel, as examiner exports) or
+	line-major (every channel of 1st trace then next trace).
+	--reverse-longitudinal, --reverse-transverse: file starts at last trace along survey / last channel.
+	"
+)]
+pub struct ExtractArgs {
+	#[command(flatten)]
+	#[serde(flatten)]
+	pub common: CommonArgs,
+	
+	#[arg(long)]
+	#[serde(skip)]
+	pub config: Option<String>,
+	
+	#[arg(short, long, default_value_t = String::from(""), required_unless_present = "config")]
+    pub output: String,
+
+	#[arg(long, default_value_t = String::from(""))]
+    pub output_params: String,
+	
+	#[clap(short, long, default_value_t, value_enum)]
+    pub geom_type: GeomType,
+	
+	#[arg(long, default_value_t = 10000)]
+    pub amplitude_threshold: Amplitude,
+	
+	#[arg(long, default_value_t = 50)]
+    pub size_threshold: usize,
+	
+	#[arg(long, default_value_t = 5)]
+    pub max_gap: usize,
+	
+	#[arg(long, default_value_t = 51)]
+    pub zero_depth: usize,
+	
+	#[arg(long)]
+    pub surface_level: Option<f64>,
+	
+	#[arg(long)]
+    pub mesh_output: Option<String>,
+	
+	#[arg(long)]
+	#[serde(default)]
+    pub diagnostics: Option<String>,
+	
+	//coordinate system of trace positions. only recorded in run metadata.
+	#[arg(long)]
+	#[serde(default)]
+	pub epsg: Option<u16>,
+	
+	#[command(flatten)]
+	#[serde(flatten)]
+	pub positions: PositionArgs,
+	
+	#[arg(long, default_value_t = String::from(DEFAULT_LAYERS))]
+	pub layers: String,
+}
+
+
+
+//counts for batch summary
+#[derive(Debug, Clone, Default)]
+pub struct ExtractSummary{
+	pub traces: usize,
+	pub clusters: usize,//clusters >= size_threshold
+	pub features: usize,
+	pub bad_lines: usize,
+}
+
+
+
+
+
+
+impl ExtractArgs{
+	
+	
+	fn cluster_settings(&self) -> ClusterSettings{
+		ClusterSettings{amplitude_threshold: self.amplitude_threshold, size_threshold: self.size_threshold, max_gap: self.max_gap}
+	}
+	
+	
+	fn z_model(&self) -> ZModel{
+		ZModel{zero_depth: self.zero_depth, z_spacing: Z_SPACING, surface_level: self.surface_level}
+	}
+	
+	
+	//args with values from --config file where not given on command line
+	pub fn with_config(&self, matches:&ArgMatches) -> anyhow::Result<ExtractArgs>{
+		let mut args = match &self.config{
+			Some(file) => apply_config(self, &read_config(file)?, matches)?,
+			None => return Ok(self.clone()),
+		};
+		args.config = self.config.clone();
+		if args.output == ""{
+			bail!(GprError::config("No output in command line or config"));
+		}
+		return Ok(args);
+	}
+	
+	
+	//metadata embedded in GeoJSON. [output]_run.json for csv.
+	fn write_features(&self, features:Vec<ClusterFeature>, metadata:&serde_json::Value) -> anyhow::Result<()>{
+		if is_geojson(&self.output){
+			return ClusterFeature::write_geojson(&features, &self.output, Some(metadata)).with_context(|| format!("Error writing '{}'", self.output));
+		}
+		ClusterFeature::write_csv(features, &self.output)?;
+		let run_file = output_next_to_input(&self.output, "run", "json");
+		std::fs::write(&run_file, serde_json::to_string_pretty(metadata)?).with_context(|| format!("Error writing '{}'", run_file))?;
+		return Ok(());
+	}
+	
+	
+	pub fn extract_high_amplitudes(&self) -> anyhow::Result<ExtractSummary>{
+		return self.extract_with_progress(&*self.common.progress());
+	}
+	
+	
+	pub fn extract_with_progress(&self, progress:&dyn Progress) -> anyhow::Result<ExtractSummary>{
+		
+		let layers: Vec<Layer> = parse_layers(&self.layers)?;
+		
+		let settings = self.cluster_settings();
+		let mut clusterer = settings.clusterer();
+		
+		check_output(&self.output, self.common.overwrite)?;
+
+		let max_depth:usize = layers.iter().max_by_key(|layer| layer.max_depth).with_context(|| "No layers ")?.max_depth;
+		let min_depth:usize = layers.iter().min_by_key(|layer| layer.min_depth).with_context(|| "No layers ")?.min_depth;
+
+		let mut parser = self.common.trace_parser(&self.common.input)?;
+		
+		let mut positions = self.positions.position_data(parser.x_lines , parser.in_lines)?;
+		
+		let mut summary = ExtractSummary::default();
+		let total = std::cmp::min(parser.x_lines,MAX_LINES) * parser.in_lines;
+		let interval:usize = 5000;
+		let stage = "Reading input and clustering";
+		
+		for (i,t) in parser.by_ref().enumerate(){
+			if i % interval ==0 {
+				progress.update(stage, i, Some(total));
+				progress.check()?;
+			}
+			
+			let tc = t?;
+			summary.traces += 1;
+			positions.add_point(tc.longitudinal,tc.transverse , Point::new(tc.proj_x,tc.proj_y));
+			
+			settings.add_trace(&mut clusterer, tc.longitudinal, tc.transverse, min_depth..=max_depth, |d| tc.amplitudes.get(d).copied().flatten());
+		}
+		progress.update(stage, total, Some(total));
+		summary.bad_lines = parser.diagnostics().problem_count();
+		report_diagnostics(&self.common.input, parser.diagnostics(), self.diagnostics.as_deref())?;
+		self.positions.fix_positions(&mut positions)?;
+
+		let clusters = settings.large_clusters(&clusterer);
+		summary.clusters = clusters.len();
+		let features = match self.geom_type{
+			GeomType::Multipolygon => positions.multipolygon_features_from_clusters(clusters.clone(),self.size_threshold.into(),layers, progress)?,
+			GeomType::Multipoint => positions.multipoint_features_from_clusters(clusters.clone(), self.size_threshold.into(), layers, progress)?,
+			GeomType::MultipointZ => positions.multipoint_z_features_from_clusters(clusters.clone(), self.size_threshold.into(), layers, &self.z_model(), progress)?,
+			GeomType::MultipolygonZ => positions.multipolygon_z_features_from_clusters(clusters.clone(), self.size_threshold.into(), layers, &self.z_model(), progress)?,
+			GeomType::PolyhedralSurfaceZ => positions.polyhedral_features_from_clusters(clusters.clone(), self.size_threshold.into(), layers, &self.z_model(), progress)?,
+		};
+		summary.features = features.len();
+		progress.update("Hashing input", 0, Some(1));
+		let metadata = serde_json::to_value(RunMetadata::new(&self.common.input, self)?)?;
+		progress.update("Hashing input", 1, Some(1));
+		self.write_features(features, &metadata)?;
+		
+		if let Some(mesh_output) = &self.mesh_output{
+			progress.update("Writing mesh", 0, Some(1));
+			let mesh = Mesh::from_clusters(&clusters, &positions, &self.z_model(), self.size_threshold);
+			mesh.write(Path::new(mesh_output)).with_context(|| format!("Error writing mesh '{}'", mesh_output))?;
+			progress.update("Writing mesh", 1, Some(1));
+		}
+
+		//write points to csv
+		let geom_csv = output_next_to_input(&self.common.input, "points", "csv");
+		positions.write_csv(Path::new(&geom_csv)).with_context(|| format!("Error writing points csv '{}'", geom_csv))?;
+		
+		//write params to json
+		if self.output_params != ""{
+			let mut file = File::create(&self.output_params)?;
+			let json = serde_json::to_string_pretty(&metadata)?;
+			file.write_all(json.as_bytes())?;
+		}
+		return Ok(summary);
+	}
+}
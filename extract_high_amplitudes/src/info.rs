@@ -0,0 +1,82 @@
+use clap::Args;
+use shared::core::Amplitude;
+use crate::{CommonArgs,report_diagnostics};
+
+
+
+#[derive(Debug , Args)]
+#[command(about = "Print header, sizes, extent and amplitude histogram of input.")]
+pub struct InfoArgs {
+	#[command(flatten)]
+	pub common: CommonArgs,
+	
+	//number of histogram bins between min and max amplitude
+	#[arg(long, default_value_t = 16)]
+	pub bins: usize,
+}
+
+
+
+impl InfoArgs{
+	
+	
+	pub fn info(&self) -> anyhow::Result<()>{
+		let mut parser = self.common.trace_parser(&self.common.input)?;
+		
+		println!("Header:");
+		for line in parser.header.iter(){
+			println!("	{}", line);
+		}
+		println!("X-lines: {}", parser.x_lines);
+		println!("In-lines: {}", parser.in_lines);
+		println!("Samples: {}", parser.samples);
+		
+		let bins = self.bins.max(1);
+		//full range of Amplitude. bin width fixed so histogram can be made in 1 pass.
+		let min = Amplitude::MIN as f64;
+		let width = (Amplitude::MAX as f64 - min + 1.0) / bins as f64;
+		let mut histogram = vec![0usize; bins];
+		let mut missing = 0;
+		let mut range:Option<(Amplitude,Amplitude)> = None;
+		let mut extent:Option<(f64,f64,f64,f64)> = None;
+		
+		for t in parser.by_ref(){
+			let t = t?;
+			extent = Some(match extent{
+				None => (t.proj_x, t.proj_y, t.proj_x, t.proj_y),
+				Some(e) => (e.0.min(t.proj_x), e.1.min(t.proj_y), e.2.max(t.proj_x), e.3.max(t.proj_y)),
+			});
+			for a in t.amplitudes.iter(){
+				match a{
+					None => missing += 1,
+					Some(a) => {
+						histogram[(((*a as f64 - min) / width) as usize).min(bins - 1)] += 1;
+						range = Some(match range{
+							None => (*a,*a),
+							Some(r) => (r.0.min(*a), r.1.max(*a)),
+						});
+					}
+				}
+			}
+		}
+		
+		let diagnostics = parser.diagnostics();
+		println!("Traces: {} ({} could not be parsed)", diagnostics.traces, diagnostics.long_rows + diagnostics.not_numbers);
+		println!("Missing samples: {}", missing);
+		if let Some(e) = extent{
+			println!("Extent: x {} to {}, y {} to {}", e.0, e.2, e.1, e.3);
+		}
+		if let Some(r) = range{
+			println!("Amplitude: {} to {}", r.0, r.1);
+		}
+		
+		println!("Histogram:");
+		let largest = *histogram.iter().max().unwrap_or(&0);
+		for (i,count) in histogram.iter().enumerate(){
+			let bar = if largest > 0 {"#".repeat(count * 50 / largest)} else {String::new()};
+			println!("{:>8.0} to {:>8.0} {:>12} {}", min + i as f64 * width, min + (i + 1) as f64 * width - 1.0, count, bar);
+		}
+		report_diagnostics(&self.common.input, diagnostics, None)?;
+		return Ok(());
+	}
+}
@@ -1,19 +1,27 @@
-
-
-use std::path::Path;
-use std::fs::File;
-use clap::Parser;
-use shared::trace::{TraceParser,MAX_LINES};
-use shared::position_data::{PositionData,ClusterFeature,Layer};
-use shared::clustering::Clusterer;
-use shared::core::Amplitude;
-use serde::{Serialize};
-use geo::Point;
-//use serde_json;
-use std::io;
+use std::path::{Path,PathBuf};
+use clap::{Parser,Subcommand,Args,ArgMatches,CommandFactory,FromArgMatches};
+use shared::position_data::PositionData;
+use shared::chainage::Centreline;
+use shared::position_qa::{PositionQa,RepairMethod};
+use shared::array_geometry::ArrayGeometry;
+use shared::progress::{Progress,NoProgress,ConsoleProgress,ConsoleStyle};
+use shared::error::GprError;
+use shared::trace::{TraceParser,ParseMode,ParseDiagnostics,TraceLayout,TraceOrder,Direction};
+use serde::{Serialize,Deserialize};
 use anyhow::{Context,bail};
 use std::io::{stdin, stdout, Read, Write};
 
+mod extract;
+mod info;
+mod convert;
+mod slice;
+mod points;
+mod validate;
+mod config;
+mod batch;
+mod merge;
+mod compare;
+
 
 
 fn pause() {
@@ -24,191 +32,313 @@ fn pause() {
 }
 
 
-#[derive(clap::ValueEnum, Clone, Default, Debug, Serialize,)]
-enum GeomType {
-	#[default]
-	Multipolygon,
-    Multipoint,
+pub use shared::position_data::DEFAULT_LAYERS;
+
+
+//prints counts and 1st few bad lines. writes every listed one to csv when given.
+pub fn report_diagnostics(input:&str, diagnostics:&ParseDiagnostics, output:Option<&str>) -> anyhow::Result<()>{
+	if diagnostics.problem_count() > 0{
+		println!("Bad lines in '{}': {}", input, diagnostics.summary());
+		for p in diagnostics.problems.iter().take(10){
+			println!("  line {} column {}: {}", p.line, p.column.map(|c| c.to_string()).unwrap_or(String::from("-")), p.reason);
+		}
+	}
+	if let Some(output) = output{
+		diagnostics.write_csv(Path::new(output)).with_context(|| format!("Error writing diagnostics '{}'", output))?;
+	}
+	return Ok(());
+}
+
+
+
+//error if output exists. user try to overwrite input or .exe
+pub fn check_output(output:&str, overwrite:bool) -> anyhow::Result<()>{
+	if Path::new(output).exists() && !overwrite {
+		bail!(GprError::config(format!("Output file '{0}' already exists", output)));
+	}
+	Ok(())
+}
+
+
+//[input]_[suffix].[extension] next to input
+pub fn output_next_to_input(input:&str, suffix:&str, extension:&str) -> String{
+	let input = Path::new(input);
+	let stem = input.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+	input.with_file_name(format!("{}_{}", stem, suffix)).with_extension(extension).to_string_lossy().into_owned()
+}
+
+
+
+//files in folder with extension or matching glob. sorted so runs are repeatable.
+pub fn expand_inputs(pattern:&str, extensions:&[&str]) -> anyhow::Result<Vec<PathBuf>>{
+	let input = Path::new(pattern);
+	let mut files:Vec<PathBuf> = if input.is_dir(){
+		std::fs::read_dir(input)?.filter_map(|e| e.ok().map(|e| e.path()))
+			.filter(|p| p.is_file() && p.extension().map_or(false, |e| extensions.iter().any(|x| e.eq_ignore_ascii_case(x))))
+			.collect()
+	}
+	else{
+		glob::glob(pattern).with_context(|| format!("Invalid glob '{}'", pattern))?
+			.filter_map(|p| p.ok()).filter(|p| p.is_file()).collect()
+	};
+	files.sort();
+	return Ok(files);
 }
 
 
-//const empty_path:Utf8PathBuf = Utf8PathBuf.from_str("").unwrap();
 
-#[command(version, about, long_about = "Reads text file exported by examiner. Finds clusters of amplitudes > amplitude-threshold.
-filters out clusters containing < size-threshold amplitudes. 
-for depth ranges (0-50mm...450-500mm) gets geometry from parts of clusters within range.
-Outputs csv with WKT geometry and label for depth range.
-Also outputs all points to csv to [input]_points.csv
+#[derive(clap::ValueEnum, Clone, Copy, Default, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProgressStyle {
+	#[default]
+	Bar,
+	Lines,//JSON per update for other programs
+	None,
+}
 
-The tool makes a list of clusters (a cluster is a group of connected samples) from all samples >= amplitude threshold.
-Samples count as connected when their x and y and z coordinates are within max_gap (5 sample units) of each other.
 
-for each cluster containing >= size_threshold(50) samples
-	it finds parts of the cluster within each depth range and outputs geometry and mean amplitude.
 
+//order of traces in text file. X-lines are along survey, In-lines are channels.
+//copy of shared TraceOrder for clap, which shared doesn't depend on. like SliceAggregation.
+#[derive(clap::ValueEnum, Clone, Copy, Default, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum InputOrder{
+	#[default]
+	ChannelMajor,//every trace of 1st channel then next channel. examiner export.
+	LineMajor,//every channel of 1st trace then next trace.
+}
 
 
+impl From<InputOrder> for TraceOrder{
+	fn from(o:InputOrder) -> TraceOrder{
+		match o{
+			InputOrder::ChannelMajor => TraceOrder::ChannelMajor,
+			InputOrder::LineMajor => TraceOrder::LineMajor,
+		}
+	}
+}
+
 
-Parameters:
-	--amplitude-threshold: Integer from -32768 to 32768
-	--output-params: JSON file to write these arguments to for future reference.
-	--max-gap: Integer >=1 . Higher max_gap makes clusters more likely to merge.
-	Points considered connected if x,y and z within max_gap sample units of each other.
-	--size-threshold: Integer >=0 .Ignore clusters containing < size-threshold samples.
-	--pause: pause after running.
-	--geom-type: type of geometry to export as. multipoint or multipolygon.
-	"
-)]
-#[derive(Debug , Parser , Serialize)]
-struct Args {
+
+//options every subcommand has
+#[derive(Debug, Args, Serialize, Deserialize, Clone)]
+pub struct CommonArgs {
     #[arg(short,long)]
-    input: String,
+    pub input: String,
 	
-	#[arg(short,long)]
-    output: String,
-
-	#[arg(long, default_value_t = String::from(""))]
-    output_params: String,
-	//output_params: Option<Utf8PathBuf>,
+	#[arg(long, short, action)]
+	pub pause: bool,
 	
-	#[clap(short, long, default_value_t, value_enum)]
-    geom_type: GeomType,
+	#[arg(long, action)]
+	pub overwrite: bool,
 	
-	#[arg(long, default_value_t = 10000)]
-    amplitude_threshold: Amplitude,
+	#[clap(long, default_value_t, value_enum)]
+	#[serde(default)]
+	pub progress: ProgressStyle,
 	
-	#[arg(long, default_value_t = 50)]
-    size_threshold: usize,
+	//stop at 1st bad line instead of skipping it
+	#[arg(long, action)]
+	#[serde(default)]
+	pub strict: bool,
 	
+	#[clap(long, default_value_t, value_enum)]
+	#[serde(default)]
+	pub trace_order: InputOrder,
 	
-	#[arg(long, default_value_t = 5)]
-    max_gap: usize,
+	//file starts at last trace along survey
+	#[arg(long, action)]
+	#[serde(default)]
+	pub reverse_longitudinal: bool,
 	
-	#[arg(long, short, action)]
-	pause: bool,
+	//file starts at last channel
+	#[arg(long, action)]
+	#[serde(default)]
+	pub reverse_transverse: bool,
+}
+
+
+impl CommonArgs{
 	
-	#[arg(long, short, action)]
-	overwrite: bool,
-	
-	#[arg(long, default_value_t = String::from("[{\"min_depth\":51,\"max_depth\":53,\"label\":\"0-50mm\"},
-	{\"min_depth\":54,\"max_depth\":57,\"label\":\"50-100mm\"},
-	{\"min_depth\":58,\"max_depth\":61,\"label\":\"100-150mm\"},
-	{\"min_depth\":62,\"max_depth\":65,\"label\":\"150-200mm\"},
-	{\"min_depth\":66,\"max_depth\":69,\"label\":\"200-250mm\"},
-	{\"min_depth\":70,\"max_depth\":73,\"label\":\"250-300mm\"},
-	{\"min_depth\":74,\"max_depth\":77,\"label\":\"300-350mm\"},
-	{\"min_depth\":82,\"max_depth\":85,\"label\":\"350-400mm\"},
-	{\"min_depth\":86,\"max_depth\":89,\"label\":\"400-450mm\"},
-	{\"min_depth\":90,\"max_depth\":93,\"label\":\"450-500mm\"}]
-	"))]
-	layers: String,
+	pub fn progress(&self) -> Box<dyn Progress>{
+		match self.progress{
+			ProgressStyle::Bar => Box::new(ConsoleProgress::new(ConsoleStyle::Bar)),
+			ProgressStyle::Lines => Box::new(ConsoleProgress::new(ConsoleStyle::Lines)),
+			ProgressStyle::None => Box::new(NoProgress),
+		}
+	}
 	
 	
+	pub fn parse_mode(&self) -> ParseMode{
+		if self.strict {ParseMode::Strict} else {ParseMode::Lenient}
+	}
+	
+	
+	pub fn layout(&self) -> TraceLayout{
+		let direction = |reverse:bool| if reverse {Direction::Reverse} else {Direction::Forward};
+		TraceLayout{order: self.trace_order.into(), longitudinal: direction(self.reverse_longitudinal), transverse: direction(self.reverse_transverse)}
+	}
+	
+	
+	//parser for file with mode and layout from these options
+	pub fn trace_parser(&self, file:&str) -> anyhow::Result<TraceParser>{
+		let parser = TraceParser::new(file).with_context(|| format!("Error reading \"{}\"", file))?;
+		return Ok(parser.with_mode(self.parse_mode()).with_layout(self.layout()));
+	}
 }
-//params can be JSON String or filePath
 
 
 
+#[derive(clap::ValueEnum, Clone, Copy, Default, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum PositionRepair {
+	#[default]
+	None,
+	Interpolate,
+	Smooth,
+}
 
-impl Args{
 
+//options for trace positions. shared by extract and points.
+#[derive(Debug, Args, Serialize, Deserialize, Clone)]
+pub struct PositionArgs {
+	#[arg(long, default_value_t = 0.0)]
+    pub start_chainage: f64,
+	
+	#[arg(long)]
+    pub route: Option<String>,
+	
+	#[arg(long)]
+    pub position_report: Option<String>,
+	
+	#[clap(long, default_value_t, value_enum)]
+    pub repair_positions: PositionRepair,
+	
+	//meters between traces along channel. from positions when not given.
+	#[arg(long)]
+	pub expected_spacing: Option<f64>,
+	
+	#[arg(long, action)]
+	pub reconstruct_positions: bool,
+}
 
 
-	fn extract_high_amplitudes(&self) -> anyhow::Result<()>{
-		
-		let layers: Vec<Layer> = serde_json::from_str(&self.layers).with_context(|| "Invalid layers")?;
-		
-		let mut clusterer = Clusterer::new(self.max_gap,self.max_gap,self.max_gap);
-		
-		//error if output exists. user try to overwrite input or .exe
-		if Path::new(&self.output).exists() && !self.overwrite {
-			bail!("Output file '{0}' already exists", self.output);
+impl PositionArgs{
+	
+	
+	pub fn position_data(&self, longitudinal_size:usize, transverse_size:usize) -> anyhow::Result<PositionData>{
+		let mut positions = PositionData::new(longitudinal_size , transverse_size);
+		positions.set_start_chainage(self.start_chainage);
+		if let Some(route) = &self.route{
+			positions.set_route(Centreline::from_file(Path::new(route), self.start_chainage).with_context(|| format!("Invalid route '{}'", route))?);
 		}
-
-		let max_depth:usize = layers.iter().max_by_key(|layer| layer.max_depth).with_context(|| "No layers ")?.max_depth;
-		let min_depth:usize = layers.iter().min_by_key(|layer| layer.min_depth).with_context(|| "No layers ")?.min_depth;
+		return Ok(positions);
+	}
+	
+	
+	//report and repair bad positions
+	fn check_positions(&self, positions:&mut PositionData) -> anyhow::Result<()>{
+		if self.position_report.is_none() && self.repair_positions == PositionRepair::None{
+			return Ok(());
+		}
+		let qa = match self.expected_spacing{
+			Some(spacing) if spacing > 0.0 => PositionQa{expected_spacing: spacing, ..PositionQa::default()},
+			Some(spacing) => bail!(GprError::config(format!("--expected-spacing must be > 0, got {}", spacing))),
+			None => PositionQa::for_positions(positions),
+		};
+		let report = qa.check(positions);
+		eprintln!("Position check: {}", report.summary());
+		if let Some(output) = &self.position_report{
+			report.write_csv(Path::new(output)).with_context(|| format!("Error writing position report '{}'", output))?;
+		}
+		let method = match self.repair_positions{
+			PositionRepair::None => return Ok(()),
+			PositionRepair::Interpolate => RepairMethod::Interpolate,
+			PositionRepair::Smooth => RepairMethod::Smooth,
+		};
+		eprintln!("Repaired {} positions", qa.repair(positions, &report, method));
+		return Ok(());
+	}
 
 
-		let res = TraceParser::new(&self.input);
-		if !res.is_ok(){
-			println!("Error reading \"{}\"", self.input);
-			return Err(res.err().unwrap().into());
-		}
-			
-		let parser: TraceParser = res.unwrap();
-		
-		let mut positions = PositionData::new(parser.x_lines , parser.in_lines);
-		
-		let total:f32 = std::cmp::min(parser.x_lines,MAX_LINES) as f32 * parser.in_lines as f32;
-		let interval:usize = 5000;
-		//let total:usize = std::cmp::min(parser.x_lines,MAX_LINES) * parser.in_lines/interval;
-		
-		eprint!("Reading input and clustering:");			
-		//let mut pb = ProgressBar::new(total as u64);
-
-		for (i,t) in parser.enumerate(){
-			if i % interval ==0 {
-				eprint!("\rReading input and clustering:{:.2?}%",100.0 * i as f32 / total);
-				let _ = io::stdout().flush();
-				//pb.inc();				
-			}
-			
-			
-			
-			if let Ok(tc) = t{
-				positions.add_point(tc.longitudinal,tc.transverse , Point::new(tc.proj_x,tc.proj_y));
-				
-				for i in min_depth..=max_depth{
-					if let Some(opt) = tc.amplitudes.get(i){
-						if let Some(amplitude) = opt{
-							if *amplitude > self.amplitude_threshold{
-								clusterer.add_point(tc.longitudinal.into(), tc.transverse.into(), i.into(), *amplitude)
-							} 
-						}
-					}
-				}
-			}
+	//report and repair bad positions then fill what's left from array geometry
+	pub fn fix_positions(&self, positions:&mut PositionData) -> anyhow::Result<()>{
+		self.check_positions(positions)?;
+		if self.reconstruct_positions{
+			let filled = ArrayGeometry::for_positions(positions).reconstruct(positions);
+			eprintln!("Reconstructed {} positions from array geometry", filled);
 		}
-		eprint!("\rReading input and clustering: Done                \n");
+		return Ok(());
+	}
+}
 
 
-		//pb.finish_print("done Reading input and clustering              ");
 
+#[derive(Debug, Parser)]
+#[command(version, about = "Tools for text files exported by examiner.", long_about = "Tools for text files exported by examiner.
+Run with a subcommand. [subcommand] --help for its options.
 
+extract: find clusters of high amplitudes and write their geometry.
+info: print header, sizes and amplitude histogram.
+convert: convert feature files between csv (WKT) and GeoJSON.
+slice: export horizontal depth slices as GeoTIFF.
+points: export trace positions only.
+validate: check every trace and position.
+batch: run extract on every file in folder or matching glob.
+merge: merge features of several lanes into one layer.
+compare: find changes between 2 surveys of same area.
 
-		let _ = match self.geom_type{
-			GeomType::Multipolygon => ClusterFeature::write_csv(
-				positions.multipolygon_features_from_clusters(clusterer.clusters(),self.size_threshold.into(),layers)
-				,&self.output
-			),
-			
-			GeomType::Multipoint => ClusterFeature::write_csv(
-				positions.multipoint_features_from_clusters(clusterer.clusters(), self.size_threshold.into(), layers)
-				,&self.output
-				)
-			
-		};
+Exit codes: 0 success, 1 other error, 2 configuration or command line usage, 3 file I/O, 4 parse (text, csv, json), 5 geometry, 6 plot, 130 cancelled.
+")]
+struct Cli {
+	#[command(subcommand)]
+	command: Command,
+}
 
 
+#[derive(Debug, Subcommand)]
+enum Command {
+	Extract(extract::ExtractArgs),
+	Info(info::InfoArgs),
+	Convert(convert::ConvertArgs),
+	Slice(slice::SliceArgs),
+	Points(points::PointsArgs),
+	Validate(validate::ValidateArgs),
+	Batch(batch::BatchArgs),
+	Merge(merge::MergeArgs),
+	Compare(compare::CompareArgs),
+}
+
 
-		//write points to csv
-		let input = Path::new(&self.input);
-		let file_name = format!("{}_points",input.file_stem().unwrap().to_os_string().into_string().unwrap());
-		let geom_csv = input.with_file_name(file_name).with_extension("csv");
-		let points_write_result = positions.write_csv(Path::new(&geom_csv));
-		if points_write_result.is_err(){
-			println!("Error writing points csv:{:?}. Continuing", points_write_result);			
+impl Command{
+	
+	fn common(&self) -> &CommonArgs{
+		match self{
+			Command::Extract(a) => &a.common,
+			Command::Info(a) => &a.common,
+			Command::Convert(a) => &a.common,
+			Command::Slice(a) => &a.common,
+			Command::Points(a) => &a.common,
+			Command::Validate(a) => &a.common,
+			Command::Batch(a) => &a.common,
+			Command::Merge(a) => &a.common,
+			Command::Compare(a) => &a.common,
 		}
-		
-		//write params to json
-		if self.output_params != ""{
-			let mut file = File::create(&self.output_params)?;
-			let json = serde_json::to_string(self)?;
-			file.write_all(json.as_bytes())?;
+	}
+	
+	
+	//matches of subcommand
+	fn run(&self, matches:&ArgMatches) -> anyhow::Result<()>{
+		match self{
+			Command::Extract(a) => a.with_config(matches)?.extract_high_amplitudes().map(|_| ()),
+			Command::Info(a) => a.info(),
+			Command::Convert(a) => a.convert(),
+			Command::Slice(a) => a.slice(),
+			Command::Points(a) => a.points(),
+			Command::Validate(a) => a.validate(),
+			Command::Batch(a) => a.batch(),
+			Command::Merge(a) => a.merge(),
+			Command::Compare(a) => a.compare(),
 		}
-
-		return Ok(());
-		
 	}
 }
 
@@ -216,13 +346,18 @@ impl Args{
 
 fn main() {
 	println!("Starting...");
-    let args = Args::parse();	
-	let result = args.extract_high_amplitudes();
+	//matches kept to tell command line options from defaults when applying config
+	let matches = Cli::command().get_matches();
+	let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+	let result = cli.command.run(matches.subcommand().map(|(_,m)| m).unwrap_or(&matches));
 	println!("{:?}",result);
-	if args.pause{
+	if cli.command.common().pause{
 		pause();
 	}
-	
+	if let Err(e) = result{
+		//exit code by kind of shared error so scripts can tell them apart. 1 for anything else.
+		std::process::exit(e.downcast_ref::<GprError>().map(|g| g.exit_code()).unwrap_or(1));
+	}
 }
 
 
@@ -0,0 +1,152 @@
+use std::path::Path;
+use clap::Args;
+use serde_json::{json,Value};
+use shared::position_data::ClusterFeature;
+use shared::merge::{Lane,merge_lanes};
+use shared::geojson::{is_geojson,write_feature_collection};
+use anyhow::{Context,bail};
+use crate::{CommonArgs,check_output,expand_inputs,output_next_to_input};
+
+
+
+#[derive(Debug , Args)]
+#[command(about = "Merge features of several lanes into one layer.", long_about = "Merge features of several lanes into one layer.
+--input is a folder (every .csv, .geojson and .json feature file in it) or glob like \"survey/*_features.csv\".
+In a folder, _points.csv and _run.json files written by extract are left out and other files that aren't features are skipped with a warning.
+Features in same depth band from different lanes within --tolerance meters of each other are merged.
+Polygons are unioned. Merged features get lanes, lane_count and sources (lane:row) attributes.
+Lane name is file name without extension.
+Lanes must be in same coordinate system. Nothing is reprojected.
+EPSG code of each lane comes from extract --epsg in GeoJSON metadata or _run.json next to csv, or GeoJSON crs.
+Lanes with different codes are not merged. Lanes without one are assumed to match.
+Output is GeoJSON for .geojson or .json. csv with WKT otherwise.
+")]
+pub struct MergeArgs {
+	#[command(flatten)]
+	pub common: CommonArgs,
+	
+	#[arg(short,long)]
+    pub output: String,
+	
+	//meters
+	#[arg(long, default_value_t = 0.1)]
+	pub tolerance: f64,
+}
+
+
+
+//other outputs of extract
+fn is_extract_sidecar(file:&Path) -> bool{
+	let name = file.file_name().map(|n| n.to_string_lossy().to_lowercase()).unwrap_or_default();
+	return name.ends_with("_points.csv") || name.ends_with("_run.json");
+}
+
+
+//EPSG:xxxx, urn:ogc:def:crs:EPSG::xxxx etc
+fn epsg_from_crs_name(name:&str) -> Option<u16>{
+	let upper = name.to_uppercase();
+	let code = &upper[upper.rfind("EPSG")? + 4..];
+	return code.trim_start_matches(':').parse().ok();
+}
+
+
+fn read_json(file:&str) -> anyhow::Result<Value>{
+	let text = std::fs::read_to_string(file).with_context(|| format!("Error reading '{}'", file))?;
+	return serde_json::from_str(&text).with_context(|| format!("Invalid JSON in '{}'", file));
+}
+
+
+//EPSG code of features. None when not recorded.
+fn lane_epsg(file:&str) -> anyhow::Result<Option<u16>>{
+	let from_metadata = |v:&Value| v["config"]["epsg"].as_u64().or(v["epsg"].as_u64()).map(|e| e as u16);
+	if is_geojson(file){
+		let collection = read_json(file)?;
+		return Ok(from_metadata(&collection["metadata"]).or_else(|| collection["crs"]["properties"]["name"].as_str().and_then(epsg_from_crs_name)));
+	}
+	let run_file = output_next_to_input(file, "run", "json");
+	if !Path::new(&run_file).exists(){
+		return Ok(None);
+	}
+	return Ok(from_metadata(&read_json(&run_file)?));
+}
+
+
+impl MergeArgs{
+	
+	
+	pub fn merge(&self) -> anyhow::Result<()>{
+		check_output(&self.output, self.common.overwrite)?;
+		let folder = Path::new(&self.common.input).is_dir();
+		let inputs:Vec<_> = expand_inputs(&self.common.input, &["csv","geojson","json"])?.into_iter()
+			.filter(|i| !(folder && is_extract_sidecar(i)))
+			.collect();
+		if inputs.len() == 0{
+			bail!("No feature files in '{}'", self.common.input);
+		}
+		
+		let mut lanes = Vec::with_capacity(inputs.len());
+		let mut epsg:Option<(u16,String)> = None;//first lane with code
+		for input in inputs.iter(){
+			let file = input.to_string_lossy();
+			let features = match ClusterFeature::read(&file){
+				Ok(features) => features,
+				//folder can have other csv and json files like batch summaries
+				Err(e) if folder => {
+					eprintln!("Skipping '{}': not a feature file ({})", file, e);
+					continue;
+				}
+				Err(e) => return Err(e).with_context(|| format!("Error reading '{}'", file)),
+			};
+			if let Some(code) = lane_epsg(&file)?{
+				match &epsg{
+					Some((first,first_file)) if *first != code => bail!("'{}' is EPSG:{} but '{}' is EPSG:{}. Lanes must be in same coordinate system", file, code, first_file, first),
+					Some(_) => {},
+					None => epsg = Some((code, file.to_string())),
+				}
+			}
+			lanes.push(Lane{
+				name: input.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default(),
+				features: features,
+			});
+		}
+		if lanes.len() == 0{
+			bail!("No feature files in '{}'", self.common.input);
+		}
+		let count:usize = lanes.iter().map(|l| l.features.len()).sum();
+		eprintln!("Merging {} features from {} lanes", count, lanes.len());
+		
+		let merged = merge_lanes(&lanes, self.tolerance)?;
+		eprintln!("{} features after merging", merged.len());
+		
+		let metadata = epsg.map(|(code,_)| json!({"epsg": code}));
+		if is_geojson(&self.output){
+			return write_feature_collection(&merged, &self.output, metadata.as_ref()).with_context(|| format!("Error writing '{}'", self.output));
+		}
+		let mut wtr = csv::Writer::from_path(&self.output).with_context(|| format!("Error writing '{}'", self.output))?;
+		for f in merged.iter(){
+			wtr.serialize(f)?;
+		}
+		wtr.flush()?;
+		if let Some(metadata) = metadata{
+			let run_file = output_next_to_input(&self.output, "run", "json");
+			std::fs::write(&run_file, serde_json::to_string_pretty(&metadata)?).with_context(|| format!("Error writing '{}'", run_file))?;
+		}
+		return Ok(());
+	}
+}
+
+
+
+
+#[cfg(test)]
+mod merge_tests{
+	use super::*;
+	
+	#[test]
+	fn test_epsg_from_crs_name(){
+		assert_eq!(epsg_from_crs_name("urn:ogc:def:crs:EPSG::27700"), Some(27700));
+		assert_eq!(epsg_from_crs_name("EPSG:4326"), Some(4326));
+		assert_eq!(epsg_from_crs_name("urn:ogc:def:crs:OGC:1.3:CRS84"), None);
+		assert!(is_extract_sidecar(Path::new("lane 1_points.csv")) && !is_extract_sidecar(Path::new("lane 1_features.csv")));
+	}
+}
@@ -0,0 +1,45 @@
+use std::path::Path;
+use clap::Args;
+use geo::Point;
+use crate::{CommonArgs,PositionArgs,check_output,output_next_to_input,report_diagnostics};
+
+
+
+#[derive(Debug , Args)]
+#[command(about = "Write trace positions to csv without clustering.", long_about = "Write trace positions to csv without clustering.
+Columns are longitudinal, transverse, x, y and chainage.
+Writes to [input]_points.csv when --output not given.
+Positions can be checked, repaired and reconstructed with the same options as extract.
+")]
+pub struct PointsArgs {
+	#[command(flatten)]
+	pub common: CommonArgs,
+	
+	#[arg(short,long)]
+    pub output: Option<String>,
+	
+	#[command(flatten)]
+	pub positions: PositionArgs,
+}
+
+
+
+impl PointsArgs{
+	
+	
+	pub fn points(&self) -> anyhow::Result<()>{
+		let output = self.output.clone().unwrap_or(output_next_to_input(&self.common.input, "points", "csv"));
+		check_output(&output, self.common.overwrite)?;
+		
+		let mut parser = self.common.trace_parser(&self.common.input)?;
+		let mut positions = self.positions.position_data(parser.x_lines, parser.in_lines)?;
+		for t in parser.by_ref(){
+			let t = t?;
+			positions.add_point(t.longitudinal, t.transverse, Point::new(t.proj_x, t.proj_y));
+		}
+		report_diagnostics(&self.common.input, parser.diagnostics(), None)?;
+		self.positions.fix_positions(&mut positions)?;
+		positions.write_csv(Path::new(&output))?;
+		return Ok(());
+	}
+}
@@ -0,0 +1,83 @@
+use std::path::Path;
+use clap::Args;
+use shared::amplitude_data::{AmplitudeData,Aggregation};
+use shared::position_data::Layer;
+use anyhow::Context;
+use crate::config::parse_layers;
+use crate::{CommonArgs,DEFAULT_LAYERS,check_output,report_diagnostics};
+
+
+
+#[derive(clap::ValueEnum, Clone, Copy, Default, Debug)]
+pub enum SliceAggregation {
+	#[default]
+	Mean,
+	Max,
+	Rms,
+}
+
+
+impl From<SliceAggregation> for Aggregation{
+	fn from(a:SliceAggregation) -> Aggregation{
+		match a{
+			SliceAggregation::Mean => Aggregation::Mean,
+			SliceAggregation::Max => Aggregation::Max,
+			SliceAggregation::Rms => Aggregation::Rms,
+		}
+	}
+}
+
+
+
+#[derive(Debug , Args)]
+#[command(about = "Export horizontal depth slices as GeoTIFF.", long_about = "Export horizontal depth slices as GeoTIFF.
+Samples within each layer are combined with --aggregation (mean, max absolute or rms) then gridded onto --cell-size grid.
+One band per layer or, with --file-per-layer, [output]_[label].tif for each layer.
+Cells without traces are filled from traces within --fill-radius cells.
+")]
+pub struct SliceArgs {
+	#[command(flatten)]
+	pub common: CommonArgs,
+	
+	#[arg(short,long)]
+    pub output: String,
+	
+	#[clap(long, default_value_t, value_enum)]
+	pub aggregation: SliceAggregation,
+	
+	//meters
+	#[arg(long, default_value_t = 0.05)]
+	pub cell_size: f64,
+	
+	#[arg(long, default_value_t = 1)]
+	pub fill_radius: usize,
+	
+	#[arg(long)]
+	pub epsg: Option<u16>,
+	
+	#[arg(long, action)]
+	pub file_per_layer: bool,
+	
+	#[arg(long, default_value_t = String::from(DEFAULT_LAYERS))]
+	pub layers: String,
+}
+
+
+
+impl SliceArgs{
+	
+	
+	pub fn slice(&self) -> anyhow::Result<()>{
+		let layers: Vec<Layer> = parse_layers(&self.layers)?;
+		if !self.file_per_layer{
+			check_output(&self.output, self.common.overwrite)?;
+		}
+		let (data,diagnostics) = AmplitudeData::read_traces(self.common.trace_parser(&self.common.input)?, &*self.common.progress()).with_context(|| format!("Error reading \"{}\"", self.common.input))?;
+		report_diagnostics(&self.common.input, &diagnostics, None)?;
+		eprint!("Writing slices...");
+		data.write_depth_slices(Path::new(&self.output), &layers, self.aggregation.into(), self.cell_size, self.fill_radius, self.epsg, self.file_per_layer)
+			.with_context(|| format!("Error writing '{}'", self.output))?;
+		eprint!("\rWriting slices: done\n");
+		return Ok(());
+	}
+}
@@ -0,0 +1,61 @@
+use clap::Args;
+use shared::position_data::PositionData;
+use shared::position_qa::PositionQa;
+use geo::Point;
+use anyhow::bail;
+use crate::{CommonArgs,report_diagnostics};
+
+
+
+#[derive(Debug , Args)]
+#[command(about = "Check every trace parses and positions are sensible.", long_about = "Check every trace parses and positions are sensible.
+Reports traces that can't be parsed, traces with wrong number of samples and missing, duplicate or outlier positions.
+Bad lines are listed with line number, column and reason. --diagnostics writes them all to csv.
+With --strict stops at the first bad line.
+Fails when any problems are found.
+")]
+pub struct ValidateArgs {
+	#[command(flatten)]
+	pub common: CommonArgs,
+	
+	#[arg(long)]
+	pub diagnostics: Option<String>,
+}
+
+
+
+impl ValidateArgs{
+	
+	
+	pub fn validate(&self) -> anyhow::Result<()>{
+		let mut parser = self.common.trace_parser(&self.common.input)?;
+		let expected = parser.x_lines * parser.in_lines;
+		let samples = parser.samples;
+		let mut positions = PositionData::new(parser.x_lines, parser.in_lines);
+		let mut short = 0;
+		
+		for t in parser.by_ref(){
+			let t = t?;
+			if t.amplitudes.iter().filter(|a| a.is_some()).count() < samples{
+				short += 1;
+			}
+			if t.longitudinal < positions.longitudinal_size() && t.transverse < positions.transverse_size(){
+				positions.add_point(t.longitudinal, t.transverse, Point::new(t.proj_x, t.proj_y));
+			}
+		}
+		
+		let diagnostics = parser.diagnostics();
+		let errors = diagnostics.long_rows + diagnostics.not_numbers;
+		let report = PositionQa::for_positions(&positions).check(&positions);
+		println!("Traces: {} of {} expected", diagnostics.traces, expected);
+		println!("Traces that could not be parsed: {}", errors);
+		println!("Traces with missing samples: {}", short);
+		println!("Positions: {}", report.summary());
+		report_diagnostics(&self.common.input, diagnostics, self.diagnostics.as_deref())?;
+		
+		if diagnostics.traces != expected || errors > 0 || short > 0 || report.issues.len() > 0{
+			bail!("'{}' has problems", self.common.input);
+		}
+		return Ok(());
+	}
+}
@@ -71,6 +71,7 @@ impl MyApp{
 		
 		let mut binding = Command::new(&path);
 		let c = binding
+			.arg("extract")
 			.arg("--input").arg(&self.input_file)
 			.arg("--output").arg(&self.output_file)
 			.arg("--amplitude-threshold").arg(format!("{0}",self.amplitude_threshold))
@@ -85,7 +86,7 @@ impl MyApp{
 	fn display_command(&self) -> String{
 		let mut path = env::current_dir().unwrap_or(PathBuf::from(EXE));
 		path.push(EXE);
-		format!("{:?} --input {:?} --output {:?} --amplitude-threshold {:?} --max-gap {:?} --size-threshold {:?} --overwrite --pause"
+		format!("{:?} extract --input {:?} --output {:?} --amplitude-threshold {:?} --max-gap {:?} --size-threshold {:?} --overwrite --pause"
 		, path, self.input_file, self.output_file, self.amplitude_threshold, self.max_gap, self.size_threshold)
 	}
 	
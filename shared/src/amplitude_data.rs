@@ -4,15 +4,23 @@
 //use anyhow::{Result,anyhow};
 use geo::{Point};
 //use shapefile::{dbase,Polygon as ShapefilePolygon};
-use std::error::Error;
-use crate::trace::{TraceParser};
+use crate::trace::{TraceParser,ParseDiagnostics};
 //use crate::clustering::{Clusterer , Cluster};
 use std::fmt;
 use crate::core::Amplitude;
 use ndarray::{Array2, Array3, Array};
-use crate::plot::{PlotData};
+use crate::plot::{PlotData,LinePlotData,Series};
+use crate::position_data::{PositionData,is_known_position};
 use std::cmp::min;
-use anyhow::{bail,Result};
+use crate::error::{Result,GprError,InFile};
+use serde::{Serialize,Deserialize};
+use std::path::Path;
+use std::ops::Range;
+use std::fs::File;
+use std::io::{BufWriter,Write};
+use crate::position_data::Layer;
+use crate::geotiff::{GridSpec,write_geotiff};
+use crate::progress::{Progress,NoProgress};
 
 //const C:f64 = 299792458.0;//speed of light
 //const TIME_INTERVAL:f64 = 9.765625E-11;
@@ -20,8 +28,8 @@ use anyhow::{bail,Result};
 
 
 //distance between elements in meters.
-const X_SPACING: f64 = 0.072;
-const Y_SPACING: f64 = 0.0762;
+pub const X_SPACING: f64 = 0.072;
+pub const Y_SPACING: f64 = 0.0762;
 pub const Z_SPACING:f64 = 0.01309289678;// TIME_INTERVAL*C/EPSILON.sqrt();
 
 
@@ -48,6 +56,33 @@ impl element_iterator{
 
 
 
+//how samples within depth window combine into one value
+#[derive(Serialize,Deserialize,Debug,Clone,Copy,PartialEq,Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Aggregation{
+	#[default]
+	Mean,
+	Max,//largest absolute amplitude
+	Rms,
+}
+
+
+impl Aggregation{
+	pub fn aggregate(&self, values:&[Amplitude]) -> Option<f32>{
+		if values.len() == 0{
+			return None;
+		}
+		let n = values.len() as f64;
+		let r = match self{
+			Aggregation::Mean => values.iter().map(|v| *v as f64).sum::<f64>() / n,
+			Aggregation::Max => values.iter().map(|v| *v as f64).fold(0.0, |a:f64, v| if v.abs() > a.abs() {v} else {a}),
+			Aggregation::Rms => (values.iter().map(|v| (*v as f64).powi(2)).sum::<f64>() / n).sqrt(),
+		};
+		Some(r as f32)
+	}
+}
+
+
 #[allow(dead_code)]
 
 //#[derive(Clone)]
@@ -70,38 +105,30 @@ impl fmt::Debug for AmplitudeData {
 
 impl AmplitudeData{
 	
-	//trans, trans_size, depth, depth_size
+	/*
+	plot data functions take any index ranges. parts of ranges outside the data are None.
+	marker is position to draw cross hairs through.
+	*/
 	
-	//from transverse_interval
-	pub fn transverse_plot_data(&self, longitudinal:usize, transverse:usize, transverse_interval:usize, depth:usize, depth_interval:usize) -> Result<PlotData>{
-		
+	
+	//x_range by y_range of values from value(x,y).
+	fn plot_values(&self, x_range:&Range<usize>, y_range:&Range<usize>, value:impl Fn(usize,usize) -> Option<Amplitude>) -> Result<Array2<Option<Amplitude>>>{
 		if self.transverse_size ==0 || self.longitudinal_size==0||self.depth_size == 0{
-			bail!("size = 0");
+			return Err(GprError::plot("size = 0"));
 		}
-		
-		if transverse_interval ==0 || depth_interval==0{
-			bail!("Interval of 0");
+		if x_range.is_empty() || y_range.is_empty(){
+			return Err(GprError::plot("Empty plot range"));
 		}
-		
-		
-		let min_depth = depth_interval * (depth/depth_interval);
-		let max_depth = min_depth + depth_interval;
-
-		let min_transverse = transverse_interval * (transverse/transverse_interval);
-		let max_transverse = min_transverse + transverse_interval;
-
-		let mut amplitudes: Array2<Option<Amplitude>> = Array::from_elem((1+max_transverse-min_transverse, 1+max_depth-min_depth), None);//single element has shape (1,1)
-		
-		for x in min_transverse..min(max_transverse, self.transverse_size){
-			for y in min_depth..min(max_depth,self.depth_size){
-				amplitudes[(x-min_transverse,y-min_depth)] = self.value(longitudinal,x,y);
-			}
-		}
-		
-		
+		return Ok(Array::from_shape_fn((x_range.len(), y_range.len()), |(x,y)| value(x_range.start + x, y_range.start + y)));
+	}
+	
+	
+	//fixed longitudinal x-line. transverse by depth.
+	pub fn transverse_plot_data(&self, longitudinal:usize, transverse:usize, transverse_range:Range<usize>, depth:usize, depth_range:Range<usize>) -> Result<PlotData>{
+		let amplitudes = self.plot_values(&transverse_range, &depth_range, |x,y| self.get_value(longitudinal,x,y))?;
 		return Ok(PlotData{
-		min_x_index: min_transverse,
-		min_y_index: min_depth,
+		min_x_index: transverse_range.start,
+		min_y_index: depth_range.start,
 		marker_x: transverse,
 		marker_y: depth,
 		x_scale: 1.0, //meters per index
@@ -113,6 +140,132 @@ impl AmplitudeData{
 	}
 	
 	
+	//fixed transverse channel. longitudinal by depth.
+	pub fn longitudinal_plot_data(&self, longitudinal:usize, transverse:usize, longitudinal_range:Range<usize>, depth:usize, depth_range:Range<usize>) -> Result<PlotData>{
+		let amplitudes = self.plot_values(&longitudinal_range, &depth_range, |x,y| self.get_value(x,transverse,y))?;
+		return Ok(PlotData{
+		min_x_index: longitudinal_range.start,
+		min_y_index: depth_range.start,
+		marker_x: longitudinal,
+		marker_y: depth,
+		x_scale: 1.0,
+		y_scale: 1.0,
+		x_label: "Longitudinal".to_string(),
+		y_label: "Depth".to_string(),
+		amplitudes: amplitudes,
+		});
+	}
+	
+	
+	/*
+	plan view. longitudinal by transverse.
+	samples within depth_window centred on depth combined with aggregation. depth_window of 1 is single depth.
+	*/
+	pub fn depth_slice_plot_data(&self, longitudinal:usize, transverse:usize, longitudinal_range:Range<usize>, transverse_range:Range<usize>, depth:usize, depth_window:usize, aggregation:Aggregation) -> Result<PlotData>{
+		if depth_window==0{
+			return Err(GprError::plot("Interval of 0"));
+		}
+		
+		let min_depth = depth.saturating_sub((depth_window - 1)/2);
+		let max_depth = min_depth + depth_window - 1;
+		
+		let amplitudes = self.plot_values(&longitudinal_range, &transverse_range, |x,y| {
+			let values:Vec<Amplitude> = (min_depth..=max_depth).filter_map(|d| self.get_value(x,y,d)).collect();
+			return aggregation.aggregate(&values).map(|v| v.round().clamp(Amplitude::MIN as f32, Amplitude::MAX as f32) as Amplitude);
+		})?;
+		
+		return Ok(PlotData{
+		min_x_index: longitudinal_range.start,
+		min_y_index: transverse_range.start,
+		marker_x: longitudinal,
+		marker_y: transverse,
+		x_scale: 1.0,
+		y_scale: 1.0,
+		x_label: "Longitudinal".to_string(),
+		y_label: "Transverse".to_string(),
+		amplitudes: amplitudes,
+		});
+	}
+	
+	
+	
+	/*
+	A-scan of trace at longitudinal, transverse over depth_range.
+	neighbours channels either side are extra series. threshold drawn as reference line.
+	*/
+	pub fn trace_plot_data(&self, longitudinal:usize, transverse:usize, neighbours:usize, depth:usize, depth_range:Range<usize>, threshold:Option<Amplitude>) -> Result<LinePlotData>{
+		if longitudinal >= self.longitudinal_size || transverse >= self.transverse_size{
+			return Err(GprError::plot("Trace outside data"));
+		}
+		if depth_range.is_empty(){
+			return Err(GprError::plot("Empty plot range"));
+		}
+		
+		let trace = |t:usize| Series{
+			label: format!("Channel {}", t),
+			values: depth_range.clone().map(|d| self.get_value(longitudinal,t,d).map(|v| v as f64)).collect(),
+		};
+		let mut series = vec![trace(transverse)];
+		for t in transverse.saturating_sub(neighbours)..min(transverse + neighbours + 1, self.transverse_size){
+			if t != transverse{
+				series.push(trace(t));
+			}
+		}
+		
+		return Ok(LinePlotData{
+		min_y_index: depth_range.start,
+		y_size: depth_range.len(),
+		marker_y: depth,
+		y_scale: 1.0,
+		x_label: "Amplitude".to_string(),
+		y_label: "Depth".to_string(),
+		series: series,
+		reference_lines: threshold.map(|t| vec![("Amplitude threshold".to_string(), t as f64)]).unwrap_or_default(),
+		});
+	}
+	
+	
+	
+	//longitudinal x transverse values combining samples between min_depth and max_depth (inclusive).
+	pub fn depth_slice(&self, min_depth:usize, max_depth:usize, aggregation:Aggregation) -> Array2<Option<f32>>{
+		let mut slice = Array::from_elem((self.longitudinal_size, self.transverse_size), None);
+		let max_depth = min(max_depth, self.depth_size.saturating_sub(1));
+		for l in 0..self.longitudinal_size{
+			for t in 0..self.transverse_size{
+				let values:Vec<Amplitude> = (min_depth..=max_depth).filter_map(|d| self.value(l,t,d)).collect();
+				slice[(l,t)] = aggregation.aggregate(&values);
+			}
+		}
+		return slice;
+	}
+	
+	
+	/*
+	horizontal slices gridded onto cell_size grid as GeoTIFF.
+	one band per layer or, with file_per_layer, output_[label].tif for each layer.
+	*/
+	pub fn write_depth_slices(&self, output:&Path, layers:&[Layer], aggregation:Aggregation, cell_size:f64, fill_radius:usize, epsg:Option<u16>, file_per_layer:bool) -> Result<()>{
+		let positions = self.position_data();
+		let grid = GridSpec::covering(&positions, cell_size)?;
+		let mut bands:Vec<Vec<f32>> = Vec::with_capacity(layers.len());
+		for layer in layers.iter(){
+			bands.push(grid.grid(&positions, &self.depth_slice(layer.min_depth, layer.max_depth, aggregation), fill_radius));
+		}
+		let labels:Vec<String> = layers.iter().map(|l| l.label.clone()).collect();
+		
+		if !file_per_layer{
+			return write_geotiff(output, &grid, &bands, &labels, epsg);
+		}
+		
+		let stem = output.file_stem().and_then(|s| s.to_str()).unwrap_or("slice");
+		for (band,label) in bands.into_iter().zip(labels.into_iter()){
+			let safe:String = label.chars().map(|c| if c.is_alphanumeric() || c == '-' {c} else {'_'}).collect();
+			let file = output.with_file_name(format!("{}_{}.tif", stem, safe));
+			write_geotiff(&file, &grid, &[band], &[label], epsg)?;
+		}
+		return Ok(());
+	}
+	
 	
 	fn set_value(&mut self, longitudinal:usize, transverse:usize, depth:usize, value:Option<Amplitude>){
 		self.amplitudes[(longitudinal,transverse,depth)] = value;
@@ -124,6 +277,25 @@ impl AmplitudeData{
 	}
 	
 	
+	//None where out of bounds
+	pub fn get_value(&self, longitudinal:usize, transverse:usize, depth:usize) -> Option<Amplitude>{
+		*self.amplitudes.get((longitudinal,transverse,depth))?
+	}
+	
+	
+	pub fn position_data(&self) -> PositionData{
+		let mut positions = PositionData::new(self.longitudinal_size, self.transverse_size);
+		for (lon,col) in self.points.iter().enumerate(){
+			for (tr,p) in col.iter().enumerate(){
+				if let Some(pt) = p{
+					positions.add_point(lon, tr, *pt);
+				}
+			}
+		}
+		return positions;
+	}
+	
+	
 	pub fn from_size(longitudinal:usize , transverse:usize , depth:usize ) -> AmplitudeData{
 		return AmplitudeData{amplitudes:Array::from_elem((longitudinal, transverse, depth), None), //L,T,D
 		longitudinal_size:longitudinal,
@@ -142,28 +314,71 @@ impl AmplitudeData{
 	//}
 
 	
-	pub fn from_text_file(filename:&str) -> Result<AmplitudeData, Box<dyn Error>>{
+	/*
+	text file in same layout as examiner export so it can be read back with from_text_file.
+	missing amplitudes and positions are written as empty fields. lines are kept so later traces keep their index.
+	lines are in order TraceParser reads them: longitudinal varies fastest.
+	*/
+	pub fn write_text_file(&self, filename:&Path, header:&str) -> Result<()>{
+		return self.write_text(filename, header).in_file(filename);
+	}
 	
-		let parser: TraceParser = TraceParser::new(filename)?;
-		
-		//println!("size:{:?}",parser.size);// x: 25, y: 1310, z: 255 }
-
-		let mut d = AmplitudeData::from_size(parser.x_lines , parser.in_lines , parser.samples);
-
 	
-		for row in parser{
-			if let Ok(trace) = row{
-				for(i,v) in trace.amplitudes.into_iter().enumerate(){
-					d.set_value(trace.longitudinal, trace.transverse, i, v);
+	fn write_text(&self, filename:&Path, header:&str) -> std::io::Result<()>{
+		let mut w = BufWriter::new(File::create(filename)?);
+		for line in header.lines(){
+			writeln!(w, "#{}", line)?;
+		}
+		writeln!(w, "#Volume: X-lines={}, In-lines={}, Samples={}", self.longitudinal_size, self.transverse_size, self.depth_size)?;
+		for t in 0..self.transverse_size{
+			for l in 0..self.longitudinal_size{
+				match self.points[l][t]{
+					Some(p) => write!(w, "{:.6}\t{:.6}", p.x(), p.y())?,
+					None => write!(w, "\t")?,
 				}
-				//d.amplitudes.column_mut((trace.longitudinal,trace.transverse));
-				
-				d.points[trace.longitudinal][trace.transverse] = Some(Point::new(trace.proj_x, trace.proj_y));
+				for d in 0..self.depth_size{
+					write!(w, "\t{}", self.value(l,t,d).map(|v| v.to_string()).unwrap_or_default())?;
 				}
+				writeln!(w)?;
+			}
 		}
+		w.flush()?;
+		return Ok(());
+	}
+	
+	
+	pub fn from_text_file(filename:&str) -> Result<AmplitudeData>{
+		return AmplitudeData::from_text_file_with_progress(filename, &NoProgress);
+	}
+	
+	
+	//reports every 1000 traces. stops with Cancelled error when cancelled.
+	pub fn from_text_file_with_progress(filename:&str, progress:&dyn Progress) -> Result<AmplitudeData>{
+		return Ok(AmplitudeData::read_traces(TraceParser::new(filename)?, progress)?.0);
+	}
+	
+	
+	//every trace of parser set up with mode and layout. with what was wrong with the file.
+	pub fn read_traces(mut parser:TraceParser, progress:&dyn Progress) -> Result<(AmplitudeData,ParseDiagnostics)>{
+	
+		let total = parser.x_lines * parser.in_lines;
 		
+		let mut d = AmplitudeData::from_size(parser.x_lines , parser.in_lines , parser.samples);
+
+		for (i,row) in parser.by_ref().enumerate(){
+			if i % 1000 == 0{
+				progress.update("Reading", i, Some(total));
+				progress.check()?;
+			}
+			let trace = row?;
+			for(i,v) in trace.amplitudes.into_iter().enumerate(){
+				d.set_value(trace.longitudinal, trace.transverse, i, v);
+			}
+			d.points[trace.longitudinal][trace.transverse] = Some(Point::new(trace.proj_x, trace.proj_y)).filter(is_known_position);
+		}
+		progress.update("Reading", total, Some(total));
 		
-		return Ok(d);
+		return Ok((d, parser.diagnostics().clone()));
 	}
 	
 	
@@ -208,6 +423,90 @@ mod amplitude_data_tests{
 
 
 	
+	#[test]
+	fn test_depth_slice(){
+		let mut data = AmplitudeData::from_size(2,2,4);
+		data.set_value(0,0,1,Some(-30));
+		data.set_value(0,0,2,Some(10));
+		assert_eq!(data.depth_slice(0,3,Aggregation::Mean)[(0,0)], Some(-10.0));
+		assert_eq!(data.depth_slice(0,3,Aggregation::Max)[(0,0)], Some(-30.0));
+		assert_eq!(data.depth_slice(0,3,Aggregation::Rms)[(0,0)], Some(500.0f32.sqrt()));
+		assert_eq!(data.depth_slice(0,3,Aggregation::Mean)[(1,1)], None);
+	}
+	
+	
+	#[test]
+	fn test_plot_data_ranges(){
+		let mut data = AmplitudeData::from_size(10,3,6);
+		data.set_value(8,1,4,Some(7));
+		let pd = data.longitudinal_plot_data(8,1,7..9,4,3..6).unwrap();
+		assert_eq!((pd.min_x_index, pd.min_y_index, pd.marker_x, pd.marker_y), (7,3,8,4));
+		assert_eq!(pd.amplitudes.shape(), &[2,3]);
+		assert_eq!(pd.amplitudes[(1,1)], Some(7));
+		//past end of data is None
+		let pd = data.transverse_plot_data(8,1,1..5,4,4..5).unwrap();
+		assert_eq!(pd.amplitudes.shape(), &[4,1]);
+		assert_eq!(pd.amplitudes[(0,0)], Some(7));
+		assert_eq!(pd.amplitudes[(3,0)], None);
+		assert!(data.transverse_plot_data(8,1,2..2,4,0..6).is_err());
+	}
+	
+	
+	#[test]
+	fn test_depth_slice_plot_data(){
+		let mut data = AmplitudeData::from_size(4,3,6);
+		data.set_value(1,2,2,Some(-30));
+		data.set_value(1,2,3,Some(10));
+		data.set_value(1,2,4,Some(1000));
+		let pd = data.depth_slice_plot_data(1,2,0..4,0..3,3,3,Aggregation::Max).unwrap();
+		assert_eq!((pd.min_x_index, pd.marker_x, pd.marker_y), (0,1,2));
+		assert_eq!(pd.amplitudes.shape(), &[4,3]);
+		assert_eq!(pd.amplitudes[(1,2)], Some(1000));
+		assert_eq!(data.depth_slice_plot_data(1,2,0..4,0..3,3,1,Aggregation::Max).unwrap().amplitudes[(1,2)], Some(10));
+		assert_eq!(data.depth_slice_plot_data(1,2,1..2,2..3,3,2,Aggregation::Mean).unwrap().amplitudes[(0,0)], Some(505));
+	}
+	
+	
+	#[test]
+	fn test_trace_plot_data(){
+		let mut data = AmplitudeData::from_size(2,3,6);
+		data.set_value(1,0,2,Some(-30));
+		data.set_value(1,1,2,Some(10));
+		let pd = data.trace_plot_data(1,0,1,3,1..4,Some(100)).unwrap();
+		assert_eq!(pd.series.len(), 2);
+		assert_eq!(pd.series[0].values, vec![None, Some(-30.0), None]);
+		assert_eq!(pd.series[1].label, "Channel 1");
+		assert_eq!(pd.series[1].values[1], Some(10.0));
+		assert_eq!(pd.reference_lines[0].1, 100.0);
+		assert!(matches!(data.trace_plot_data(2,0,1,3,1..4,None), Err(GprError::Plot(_))));
+	}
+	
+	
+	#[test]
+	fn test_write_text(){
+		let mut data = AmplitudeData::from_size(2,2,3);
+		for l in 0..2{
+			for t in 0..2{
+				data.points[l][t] = Some(Point::new(100.0 + l as f64, 200.0 + t as f64));
+				for d in 0..3{
+					data.set_value(l,t,d,Some((l * 100 + t * 10 + d) as Amplitude));
+				}
+			}
+		}
+		data.set_value(0,1,2,None);
+		data.points[1][1] = None;
+		let file = std::env::temp_dir().join("amplitude_data_write_test.txt");
+		data.write_text_file(&file, "test").unwrap();
+		let read = AmplitudeData::from_text_file(file.to_str().unwrap()).unwrap();
+		assert_eq!(read.value(0,1,1), Some(11));
+		assert_eq!(read.value(0,1,2), None);
+		assert_eq!(read.value(1,1,2), Some(112));
+		assert_eq!(read.points[1][1], None);
+		assert_eq!(read.points[1][0], Some(Point::new(101.0, 200.0)));
+		std::fs::remove_file(&file).unwrap();
+	}
+	
+	
 	#[test]
 	fn test_from_text(){
 		let data = AmplitudeData::from_text_file(F).unwrap();
@@ -0,0 +1,229 @@
+use geo::Point;
+use serde::{Serialize,Deserialize};
+use crate::position_data::PositionData;
+use crate::amplitude_data::Y_SPACING;
+
+
+/*
+fills in missing channel positions from the antenna array geometry.
+
+channels are in a straight line perpendicular to direction of travel, channel_spacing apart.
+heading of each X-line comes from centres of nearby X-lines.
+centre of each X-line comes from whichever channels have positions.
+X-lines with no positions at all get centre and heading interpolated from neighbours.
+*/
+
+
+//side of direction of travel that channel 0 is on
+#[derive(Serialize,Deserialize,Debug,Clone,Copy,PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Side{
+	Left,
+	Right,
+}
+
+
+#[derive(Serialize,Deserialize,Debug,Clone,Copy)]
+pub struct ArrayGeometry{
+	pub channel_spacing: f64,//meters
+	pub centre_channel: f64,//channel at array centre. fractional for even channel count
+	pub channel_zero_side: Option<Side>,//found from X-lines with 2+ positions when None
+	pub heading_window: usize,//X-lines either side used for heading
+}
+
+
+//linear interpolation of gaps between known values. extrapolates at ends. all None stays None.
+fn fill_gaps(values:&[Option<(f64,f64)>]) -> Vec<Option<(f64,f64)>>{
+	let known:Vec<usize> = (0..values.len()).filter(|i| values[*i].is_some()).collect();
+	if known.len() == 0{
+		return values.to_vec();
+	}
+	let mut filled = values.to_vec();
+	let mut k = 0;
+	for i in 0..values.len(){
+		if values[i].is_some(){
+			continue;
+		}
+		if known.len() == 1{
+			filled[i] = values[known[0]];
+			continue;
+		}
+		while k + 2 < known.len() && known[k+1] < i{
+			k += 1;
+		}
+		//between known[k] and known[k+1]. extrapolates at ends.
+		let (a,b) = (known[k], known[k+1]);
+		let (pa,pb) = (values[a].unwrap(), values[b].unwrap());
+		let t = (i as f64 - a as f64) / (b - a) as f64;
+		filled[i] = Some((pa.0 + t * (pb.0 - pa.0), pa.1 + t * (pb.1 - pa.1)));
+	}
+	return filled;
+}
+
+
+fn mean(points:&[(f64,f64)]) -> Option<(f64,f64)>{
+	if points.len() == 0{
+		return None;
+	}
+	let n = points.len() as f64;
+	Some((points.iter().map(|p| p.0).sum::<f64>() / n, points.iter().map(|p| p.1).sum::<f64>() / n))
+}
+
+
+impl ArrayGeometry{
+
+
+	pub fn for_positions(positions:&PositionData) -> ArrayGeometry{
+		ArrayGeometry{
+			channel_spacing: Y_SPACING,
+			centre_channel: (positions.transverse_size() as f64 - 1.0) / 2.0,
+			channel_zero_side: None,
+			heading_window: 5,
+		}
+	}
+
+
+	fn known(positions:&PositionData, lon:usize) -> Vec<(usize,(f64,f64))>{
+		(0..positions.transverse_size()).filter_map(|t| positions.get_point(lon,t).map(|p| (t,(p.x(),p.y())))).collect()
+	}
+
+
+	//unit heading of each X-line from centres either side
+	fn headings(&self, centres:&[Option<(f64,f64)>]) -> Vec<Option<(f64,f64)>>{
+		let n = centres.len();
+		let mut headings:Vec<Option<(f64,f64)>> = vec![None; n];
+		for l in 0..n{
+			let a = centres[l.saturating_sub(self.heading_window)];
+			let b = centres[(l + self.heading_window).min(n - 1)];
+			if let (Some(a),Some(b)) = (a,b){
+				let (dx,dy) = (b.0 - a.0, b.1 - a.1);
+				let length = dx.hypot(dy);
+				if length > 0.0{
+					headings[l] = Some((dx / length, dy / length));
+				}
+			}
+		}
+		return fill_gaps(&headings);
+	}
+
+
+	//majority vote from X-lines with 2+ positions
+	fn detect_side(&self, positions:&PositionData, headings:&[Option<(f64,f64)>]) -> Option<Side>{
+		let mut votes:i64 = 0;
+		for (l,h) in headings.iter().enumerate(){
+			let known = ArrayGeometry::known(positions, l);
+			if let (Some(h), Some(first), Some(last)) = (h, known.first(), known.last()){
+				if first.0 == last.0{
+					continue;
+				}
+				//direction of increasing channel . left normal
+				let across = ((last.1).0 - (first.1).0, (last.1).1 - (first.1).1);
+				let dot = across.0 * -h.1 + across.1 * h.0;
+				if dot > 0.0{
+					votes += 1;//channels increase to left so channel 0 on right
+				}
+				else if dot < 0.0{
+					votes -= 1;
+				}
+			}
+		}
+		match votes{
+			v if v > 0 => Some(Side::Right),
+			v if v < 0 => Some(Side::Left),
+			_ => None,
+		}
+	}
+
+
+	/*
+	fills every missing position. returns number filled.
+	nothing is done without any positions or direction of travel.
+	*/
+	pub fn reconstruct(&self, positions:&mut PositionData) -> usize{
+		let size = positions.longitudinal_size();
+		if size == 0{
+			return 0;
+		}
+		let rough:Vec<Option<(f64,f64)>> = (0..size).map(|l| mean(&ArrayGeometry::known(positions,l).iter().map(|k| k.1).collect::<Vec<(f64,f64)>>())).collect();
+		let headings = self.headings(&fill_gaps(&rough));
+		let side = self.channel_zero_side.or(self.detect_side(positions, &headings)).unwrap_or(Side::Left);
+
+		//offset of channel from centre
+		let offset = |t:usize, h:(f64,f64)| {
+			let d = (t as f64 - self.centre_channel) * self.channel_spacing;
+			//unit vector towards increasing channel
+			let u = match side{
+				Side::Right => (-h.1, h.0),
+				Side::Left => (h.1, -h.0),
+			};
+			(u.0 * d, u.1 * d)
+		};
+
+		let centres:Vec<Option<(f64,f64)>> = (0..size).map(|l| {
+			let h = headings[l]?;
+			let known:Vec<(f64,f64)> = ArrayGeometry::known(positions,l).iter().map(|(t,p)| {let o = offset(*t,h); (p.0 - o.0, p.1 - o.1)}).collect();
+			mean(&known)
+		}).collect();
+		let centres = fill_gaps(&centres);
+
+		let mut filled = 0;
+		for l in 0..size{
+			if let (Some(c),Some(h)) = (centres[l],headings[l]){
+				for t in 0..positions.transverse_size(){
+					if positions.get_point(l,t).is_none(){
+						let o = offset(t,h);
+						positions.add_point(l, t, Point::new(c.0 + o.0, c.1 + o.1));
+						filled += 1;
+					}
+				}
+			}
+		}
+		return filled;
+	}
+}
+
+
+
+
+#[cfg(test)]
+mod array_geometry_tests{
+	use super::*;
+
+	//travelling north. channel 0 on left (west)
+	fn survey() -> PositionData{
+		let mut positions = PositionData::new(20,5);
+		for l in 0..20{
+			for t in 0..5{
+				positions.add_point(l,t,Point::new(100.0 + t as f64 * Y_SPACING, 200.0 + l as f64 * 0.1));
+			}
+		}
+		positions
+	}
+
+
+	#[test]
+	fn test_reconstruct(){
+		let complete = survey();
+		let mut positions = PositionData::new(20,5);
+		for l in 0..20{
+			if l % 2 == 0{
+				positions.add_point(l,2,complete.get_point(l,2).unwrap());//centre only
+			}
+			if l == 3{
+				positions.add_point(l,0,complete.get_point(l,0).unwrap());
+				positions.add_point(l,4,complete.get_point(l,4).unwrap());
+			}
+			if l == 7{
+				positions.add_point(l,1,Point::new(0.0,0.0));//no GPS. reconstructed like missing.
+			}
+		}
+		let geometry = ArrayGeometry::for_positions(&positions);
+		assert_eq!(geometry.reconstruct(&mut positions), 100 - 12);
+		for l in 0..20{
+			for t in 0..5{
+				let (a,b) = (positions.get_point(l,t).unwrap(), complete.get_point(l,t).unwrap());
+				assert!((a.x() - b.x()).abs() < 1e-6 && (a.y() - b.y()).abs() < 1e-6, "L{l} T{t}: {a:?} != {b:?}");
+			}
+		}
+	}
+}
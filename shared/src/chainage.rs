@@ -0,0 +1,146 @@
+use std::fs;
+use std::path::Path;
+use crate::error::{Result,GprError,InFile};
+use geo::{Point,LineString,Coord};
+use rstar::RTree;
+use rstar::primitives::{Line,GeomWithData};
+use wkt::TryFromWkt;
+use crate::position_data::PositionData;
+
+
+/*
+linear referencing.
+chainage is distance along centreline from its start + start_chainage.
+offset is perpendicular distance from centreline. positive to the left looking along increasing chainage.
+*/
+
+
+type Segment = GeomWithData<Line<[f64;2]>,usize>;//index of first vertex
+
+
+#[derive(Debug,Clone)]
+pub struct Centreline{
+	vertices: Vec<Coord>,
+	cumulative: Vec<f64>,//distance along line at each vertex
+	start_chainage: f64,
+	index: RTree<Segment>,
+}
+
+
+impl Centreline{
+
+
+	pub fn new(line:LineString, start_chainage:f64) -> Result<Centreline>{
+		let mut vertices:Vec<Coord> = Vec::with_capacity(line.0.len());
+		for c in line.0.into_iter(){
+			//zero length segments have no direction
+			if vertices.last() != Some(&c){
+				vertices.push(c);
+			}
+		}
+		if vertices.len() < 2{
+			return Err(GprError::geometry("centreline needs at least 2 distinct vertices"));
+		}
+		let mut cumulative = vec![0.0];
+		for w in vertices.windows(2){
+			cumulative.push(cumulative.last().unwrap() + (w[1].x - w[0].x).hypot(w[1].y - w[0].y));
+		}
+		let segments:Vec<Segment> = vertices.windows(2).enumerate()
+			.map(|(i,w)| GeomWithData::new(Line::new([w[0].x, w[0].y], [w[1].x, w[1].y]), i))
+			.collect();
+		Ok(Centreline{vertices: vertices, cumulative: cumulative, start_chainage: start_chainage, index: RTree::bulk_load(segments)})
+	}
+
+
+	//route file. WKT LINESTRING or csv with x,y columns.
+	pub fn from_file(file:&Path, start_chainage:f64) -> Result<Centreline>{
+		let text = fs::read_to_string(file).in_file(file)?;
+		if text.trim_start().to_uppercase().starts_with("LINESTRING"){
+			let line = LineString::<f64>::try_from_wkt_str(text.trim()).map_err(|e| GprError::parse(format!("Invalid WKT: {:?}", e)).in_file(file))?;
+			return Centreline::new(line, start_chainage);
+		}
+
+		let mut reader = csv::ReaderBuilder::new().has_headers(false).flexible(true).from_reader(text.as_bytes());
+		let mut coords:Vec<Coord> = Vec::new();
+		for (i,record) in reader.records().enumerate(){
+			let record = record.in_file(file)?;
+			let x = record.get(0).and_then(|v| v.trim().parse::<f64>().ok());
+			let y = record.get(1).and_then(|v| v.trim().parse::<f64>().ok());
+			match (x,y){
+				(Some(x),Some(y)) => coords.push(Coord{x: x, y: y}),
+				_ if i == 0 => {},//header
+				_ => return Err(GprError::parse("Invalid x,y").in_file(file).at_line(i + 1)),
+			}
+		}
+		return Centreline::new(LineString::new(coords), start_chainage);
+	}
+
+
+	//line through mean position of each longitudinal index
+	pub fn from_positions(positions:&PositionData, start_chainage:f64) -> Result<Centreline>{
+		let mut coords:Vec<Coord> = Vec::with_capacity(positions.longitudinal_size());
+		for lon in 0..positions.longitudinal_size(){
+			let points:Vec<Point> = (0..positions.transverse_size()).filter_map(|tr| positions.get_point(lon,tr)).collect();
+			if points.len() > 0{
+				let n = points.len() as f64;
+				coords.push(Coord{x: points.iter().map(|p| p.x()).sum::<f64>() / n, y: points.iter().map(|p| p.y()).sum::<f64>() / n});
+			}
+		}
+		return Centreline::new(LineString::new(coords), start_chainage);
+	}
+
+
+	//(chainage,offset) of nearest position on line. extends beyond first and last vertex.
+	pub fn locate(&self, p:Point) -> (f64,f64){
+		let segment = self.index.nearest_neighbor(&[p.x(), p.y()]).expect("centreline has segments");
+		let i = segment.data;
+		let (a,b) = (self.vertices[i], self.vertices[i+1]);
+		let (dx,dy) = (b.x - a.x, b.y - a.y);
+		let length = dx.hypot(dy);
+		let mut t = ((p.x() - a.x) * dx + (p.y() - a.y) * dy) / (length * length);
+		if i > 0{
+			t = t.max(0.0);
+		}
+		if i + 2 < self.vertices.len(){
+			t = t.min(1.0);
+		}
+		let along = self.cumulative[i] + t * length;
+		let offset = (dx * (p.y() - a.y) - dy * (p.x() - a.x)) / length;
+		return (self.start_chainage + along, offset);
+	}
+
+
+	pub fn length(&self) -> f64{
+		*self.cumulative.last().unwrap_or(&0.0)
+	}
+}
+
+
+
+
+#[cfg(test)]
+mod chainage_tests{
+	use super::*;
+
+	#[test]
+	fn test_locate(){
+		let line = LineString::from(vec![(0.0,0.0),(10.0,0.0),(10.0,10.0)]);
+		let c = Centreline::new(line, 100.0).unwrap();
+		assert_eq!(c.length(), 20.0);
+		assert_eq!(c.locate(Point::new(5.0,2.0)), (105.0,2.0), "left is positive");
+		assert_eq!(c.locate(Point::new(12.0,5.0)), (115.0,-2.0), "right of 2nd segment is negative");
+		assert_eq!(c.locate(Point::new(-3.0,-1.0)), (97.0,-1.0), "extends before start");
+	}
+
+
+	#[test]
+	fn test_from_positions(){
+		let mut positions = PositionData::new(3,2);
+		for lon in 0..3{
+			positions.add_point(lon,0,Point::new(lon as f64,1.0));
+			positions.add_point(lon,1,Point::new(lon as f64,-1.0));
+		}
+		let c = Centreline::from_positions(&positions, 0.0).unwrap();
+		assert_eq!(c.locate(Point::new(1.5,1.0)), (1.5,1.0));
+	}
+}
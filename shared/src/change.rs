@@ -0,0 +1,322 @@
+use std::collections::{HashMap,HashSet};
+use rstar::RTree;
+use rstar::primitives::GeomWithData;
+use serde::{Serialize,Deserialize};
+use wkt::ToWkt;
+use crate::error::Result;
+use crate::core::Amplitude;
+use crate::amplitude_data::{AmplitudeData,Aggregation,X_SPACING};
+use crate::clustering::{Cluster,ClusterSettings,find_clusters};
+use crate::progress::NoProgress;
+use crate::position_data::{PositionData,Layer};
+
+
+/*
+change detection between 2 surveys of same area.
+
+before survey is the reference. after survey is resampled onto its traces:
+each before trace takes the nearest after trace within max_distance once the after survey is shifted by registration.
+registration is from trace positions. optionally refined by searching shifts within search_radius
+for best correlation of rms amplitude over the layers.
+
+clusters of both are found on the same traces so can be compared cell by cell within each depth band.
+*/
+
+
+type TraceIndex = GeomWithData<[f64;2],(usize,usize)>;//longitudinal,transverse
+
+
+#[derive(Serialize,Deserialize,Debug,Clone,Copy,PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind{
+	New,
+	Vanished,
+	Grown,
+	Shrunk,
+	Unchanged,
+}
+
+
+#[derive(Serialize,Debug,Clone)]
+pub struct ClusterChange{
+	pub depth_band: String,
+	pub change: ChangeKind,
+	pub cells_before: usize,//traces in band of overlapping before clusters
+	pub cells_after: usize,
+	pub mean_amplitude_before: Option<Amplitude>,
+	pub mean_amplitude_after: Option<Amplitude>,
+	pub wkt: String,//after cluster. before cluster when vanished.
+}
+
+
+//shift added to after positions to line up with before
+#[derive(Serialize,Debug,Clone,Copy,PartialEq,Default)]
+pub struct Registration{
+	pub dx: f64,
+	pub dy: f64,
+	pub matched: usize,//before traces with after trace within max_distance
+	pub correlation: Option<f64>,
+}
+
+
+#[derive(Debug,Clone,Copy)]
+pub struct ChangeDetector{
+	pub max_distance: f64,//meters. before traces further than this from any after trace have no after values.
+	pub search_radius: f64,//meters. 0 to trust positions.
+	pub search_step: f64,//meters
+	pub clusters: ClusterSettings,
+	pub growth: f64,//fraction of cells. grown when after > before * (1 + growth)
+}
+
+
+impl Default for ChangeDetector{
+	fn default() -> Self{
+		ChangeDetector{max_distance: X_SPACING, search_radius: 0.0, search_step: X_SPACING / 2.0, clusters: ClusterSettings::default(), growth: 0.2}
+	}
+}
+
+
+fn trace_index(data:&AmplitudeData) -> RTree<TraceIndex>{
+	let mut points = Vec::new();
+	for (l,col) in data.points.iter().enumerate(){
+		for (t,p) in col.iter().enumerate(){
+			if let Some(p) = p{
+				points.push(GeomWithData::new([p.x(), p.y()], (l,t)));
+			}
+		}
+	}
+	return RTree::bulk_load(points);
+}
+
+
+fn correlation(pairs:&[(f64,f64)]) -> Option<f64>{
+	let n = pairs.len() as f64;
+	if pairs.len() < 3{
+		return None;
+	}
+	let (ma,mb) = (pairs.iter().map(|p| p.0).sum::<f64>() / n, pairs.iter().map(|p| p.1).sum::<f64>() / n);
+	let cov:f64 = pairs.iter().map(|p| (p.0 - ma) * (p.1 - mb)).sum();
+	let va:f64 = pairs.iter().map(|p| (p.0 - ma).powi(2)).sum();
+	let vb:f64 = pairs.iter().map(|p| (p.1 - mb).powi(2)).sum();
+	if va == 0.0 || vb == 0.0{
+		return None;
+	}
+	Some(cov / (va * vb).sqrt())
+}
+
+
+//cells of cluster in depth range
+fn cells(cluster:&Cluster, min_depth:usize, max_depth:usize) -> HashSet<(usize,usize)>{
+	cluster.regions.iter().flat_map(|r| r.x_y_between(min_depth, max_depth)).collect()
+}
+
+
+fn mean(values:&[Amplitude]) -> Option<Amplitude>{
+	if values.len() == 0{
+		return None;
+	}
+	Some((values.iter().map(|v| *v as i64).sum::<i64>() / values.len() as i64) as Amplitude)
+}
+
+
+//cluster in one depth band
+struct BandCluster{
+	cells: HashSet<(usize,usize)>,
+	mean_amplitude: Option<Amplitude>,
+	wkt: String,
+}
+
+
+impl ChangeDetector{
+
+
+	//after trace nearest each before trace
+	fn nearest(&self, before:&AmplitudeData, index:&RTree<TraceIndex>, registration:&Registration, step:usize) -> Vec<((usize,usize),(usize,usize))>{
+		let mut pairs = Vec::new();
+		let max_squared = self.max_distance * self.max_distance;
+		for (l,col) in before.points.iter().enumerate().step_by(step){
+			for (t,p) in col.iter().enumerate(){
+				if let Some(p) = p{
+					let query = [p.x() - registration.dx, p.y() - registration.dy];
+					if let Some(n) = index.nearest_neighbor(&query){
+						let d = (n.geom()[0] - query[0]).powi(2) + (n.geom()[1] - query[1]).powi(2);
+						if d <= max_squared{
+							pairs.push(((l,t), n.data));
+						}
+					}
+				}
+			}
+		}
+		return pairs;
+	}
+
+
+	/*
+	shift of after survey. zero unless search_radius > 0.
+	search uses every 4th before X-line to keep it quick.
+	best shift has highest correlation * traces matched.
+	*/
+	pub fn register(&self, before:&AmplitudeData, after:&AmplitudeData, min_depth:usize, max_depth:usize) -> Registration{
+		let index = trace_index(after);
+		let mut best = Registration::default();
+		best.matched = self.nearest(before, &index, &best, 1).len();
+		if self.search_radius <= 0.0 || self.search_step <= 0.0{
+			return best;
+		}
+		let a = before.depth_slice(min_depth, max_depth, Aggregation::Rms);
+		let b = after.depth_slice(min_depth, max_depth, Aggregation::Rms);
+		let steps = (self.search_radius / self.search_step).round() as i64;
+		let mut best_score:Option<f64> = None;
+		for i in -steps..=steps{
+			for j in -steps..=steps{
+				let candidate = Registration{dx: i as f64 * self.search_step, dy: j as f64 * self.search_step, matched: 0, correlation: None};
+				let values:Vec<(f64,f64)> = self.nearest(before, &index, &candidate, 4).iter()
+					.filter_map(|(p,q)| Some(((a[*p])? as f64, (b[*q])? as f64)))
+					.collect();
+				//weighted by overlap so shifts matching few traces don't win by chance
+				if let Some(r) = correlation(&values){
+					let score = r * values.len() as f64;
+					if best_score.map_or(true, |s| score > s){
+						best_score = Some(score);
+						best = Registration{correlation: Some(r), ..candidate};
+					}
+				}
+			}
+		}
+		best.matched = self.nearest(before, &index, &best, 1).len();
+		return best;
+	}
+
+
+	//after survey on traces of before survey
+	pub fn resample(&self, before:&AmplitudeData, after:&AmplitudeData, registration:&Registration) -> AmplitudeData{
+		let mut resampled = AmplitudeData::from_size(before.longitudinal_size, before.transverse_size, before.depth_size);
+		resampled.points = before.points.clone();
+		for (p,q) in self.nearest(before, &trace_index(after), registration, 1).into_iter(){
+			for d in 0..before.depth_size{
+				resampled.amplitudes[(p.0, p.1, d)] = after.get_value(q.0, q.1, d);
+			}
+		}
+		return resampled;
+	}
+
+
+	//after - before where both have values. same traces as before.
+	pub fn difference(before:&AmplitudeData, resampled:&AmplitudeData) -> AmplitudeData{
+		let mut difference = AmplitudeData::from_size(before.longitudinal_size, before.transverse_size, before.depth_size);
+		difference.points = before.points.clone();
+		for ((i,a),b) in before.amplitudes.indexed_iter().zip(resampled.amplitudes.iter()){
+			if let (Some(a),Some(b)) = (a,b){
+				difference.amplitudes[i] = Some(b.saturating_sub(*a));
+			}
+		}
+		return difference;
+	}
+
+
+	fn band_clusters(clusters:&[Cluster], positions:&PositionData, layer:&Layer) -> Vec<BandCluster>{
+		clusters.iter().filter_map(|c| {
+			let cells = cells(c, layer.min_depth, layer.max_depth);
+			if cells.len() == 0{
+				return None;
+			}
+			//no geometry when none of its traces have positions. can't be written as feature so left out.
+			let wkt = positions.multipolygon_from_cluster(c, layer.min_depth, layer.max_depth).ok()?.wkt_string();
+			Some(BandCluster{cells: cells, mean_amplitude: mean(&c.values_between(layer.min_depth, layer.max_depth)), wkt: wkt})
+		}).collect()
+	}
+
+
+	/*
+	new, vanished, grown, shrunk and unchanged clusters per layer.
+	after clusters are compared to all before clusters they overlap.
+	*/
+	pub fn changes(&self, before:&AmplitudeData, resampled:&AmplitudeData, layers:&[Layer]) -> Result<Vec<ClusterChange>>{
+		let min_depth = layers.iter().map(|l| l.min_depth).min().unwrap_or(0);
+		let max_depth = layers.iter().map(|l| l.max_depth).max().unwrap_or(0);
+		let positions = before.position_data();
+		let before_clusters = find_clusters(before, &self.clusters, min_depth..=max_depth, &NoProgress)?;
+		let after_clusters = find_clusters(resampled, &self.clusters, min_depth..=max_depth, &NoProgress)?;
+
+		let mut changes = Vec::new();
+		for layer in layers.iter(){
+			let a = ChangeDetector::band_clusters(&before_clusters, &positions, layer);
+			let b = ChangeDetector::band_clusters(&after_clusters, &positions, layer);
+			let mut owner:HashMap<(usize,usize),usize> = HashMap::new();
+			for (i,c) in a.iter().enumerate(){
+				for cell in c.cells.iter(){
+					owner.insert(*cell, i);
+				}
+			}
+			let mut matched = vec![false; a.len()];
+
+			for c in b.iter(){
+				let overlapping:HashSet<usize> = c.cells.iter().filter_map(|cell| owner.get(cell).copied()).collect();
+				let cells_before:usize = overlapping.iter().map(|i| a[*i].cells.len()).sum();
+				let after = c.cells.len() as f64;
+				let change = if overlapping.len() == 0 {ChangeKind::New}
+					else if after > cells_before as f64 * (1.0 + self.growth) {ChangeKind::Grown}
+					else if after * (1.0 + self.growth) < cells_before as f64 {ChangeKind::Shrunk}
+					else {ChangeKind::Unchanged};
+				for i in overlapping.iter(){
+					matched[*i] = true;
+				}
+				let before_values:Vec<Amplitude> = overlapping.iter().filter_map(|i| a[*i].mean_amplitude).collect();
+				changes.push(ClusterChange{depth_band: layer.label.clone(), change: change, cells_before: cells_before, cells_after: c.cells.len(),
+					mean_amplitude_before: mean(&before_values), mean_amplitude_after: c.mean_amplitude, wkt: c.wkt.clone()});
+			}
+
+			for (i,c) in a.iter().enumerate(){
+				if !matched[i]{
+					changes.push(ClusterChange{depth_band: layer.label.clone(), change: ChangeKind::Vanished, cells_before: c.cells.len(), cells_after: 0,
+						mean_amplitude_before: c.mean_amplitude, mean_amplitude_after: None, wkt: c.wkt.clone()});
+				}
+			}
+		}
+		return Ok(changes);
+	}
+}
+
+
+
+
+#[cfg(test)]
+mod change_tests{
+	use super::*;
+	use geo::Point;
+
+	//20x5 traces 10 samples. block of high amplitude at longitudinal start..start+len.
+	fn survey(shift:f64, blocks:&[(usize,usize)]) -> AmplitudeData{
+		let mut data = AmplitudeData::from_size(20,5,10);
+		for l in 0..20{
+			for t in 0..5{
+				data.points[l][t] = Some(Point::new(100.0 + l as f64 * X_SPACING + shift, 100.0 + t as f64 * 0.0762));
+				for d in 0..10{
+					let high = blocks.iter().any(|(s,n)| l >= *s && l < s + n) && d >= 4 && d <= 6;
+					data.amplitudes[(l,t,d)] = Some(if high {20000} else {((l * 7 + t * 3 + d) % 11) as Amplitude * 100});
+				}
+			}
+		}
+		return data;
+	}
+
+
+	#[test]
+	fn test_changes(){
+		let before = survey(0.0, &[(2,3),(10,2)]);
+		//after positions 2 traces out. first feature grown, second gone, new one at 16.
+		let after = survey(2.0 * X_SPACING, &[(2,6),(16,3)]);
+		let detector = ChangeDetector{clusters: ClusterSettings{size_threshold: 5, ..Default::default()}, search_radius: 3.0 * X_SPACING, search_step: X_SPACING, ..Default::default()};
+		let registration = detector.register(&before, &after, 0, 9);
+		assert!((registration.dx + 2.0 * X_SPACING).abs() < 1e-9 && registration.dy.abs() < 1e-9, "{registration:?}");
+
+		let resampled = detector.resample(&before, &after, &registration);
+		assert_eq!(resampled.get_value(7,0,5), Some(20000));
+		assert_eq!(ChangeDetector::difference(&before, &resampled).get_value(10,0,5), Some(((10 * 7 + 5) % 11) as Amplitude * 100 - 20000));
+
+		let layers = vec![Layer{min_depth: 4, max_depth: 6, label: "band".into()}];
+		let mut found:Vec<ChangeKind> = detector.changes(&before, &resampled, &layers).unwrap().iter().map(|c| c.change).collect();
+		found.sort_by_key(|c| *c as usize);
+		assert_eq!(found, vec![ChangeKind::New, ChangeKind::Vanished, ChangeKind::Grown]);
+	}
+}
@@ -0,0 +1,158 @@
+use std::ops::Range;
+use crate::amplitude_data::AmplitudeData;
+use crate::clustering::{Cluster,ClusterSettings,Cuboid,XYZV,find_clusters};
+use crate::position_data::Layer;
+use crate::progress::Progress;
+use crate::error::Result;
+
+
+/*
+clusters of high amplitudes found in loaded data, for drawing over plots.
+same rule as extractor: samples > amplitude_threshold within depths of layers, clusters >= size_threshold samples.
+cells are in plot indices like PlotData. group picks colour: cluster id or index of depth band.
+*/
+
+
+#[derive(Debug,Clone,Copy,PartialEq,Default)]
+pub enum OverlayColouring{
+	#[default]
+	Cluster,
+	DepthBand,//samples outside every layer are group layers.len()
+}
+
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct OverlayCell{
+	pub x: usize,
+	pub y: usize,
+	pub group: usize,
+}
+
+
+#[derive(Debug,Clone)]
+pub struct ClusterSummary{
+	pub id: usize,
+	pub volume: usize,
+	pub bounds: Cuboid,
+	pub peak: XYZV,//highest amplitude
+	pub depth_band: Option<String>,//of peak
+}
+
+
+impl ClusterSummary{
+	pub fn description(&self) -> String{
+		let b = &self.bounds;
+		return format!("{}: {} samples, L {}-{}, T {}-{}, depth {}-{} {}", self.id, self.volume, b.min_x, b.max_x, b.min_y, b.max_y, b.min_z, b.max_z, self.depth_band.as_deref().unwrap_or(""));
+	}
+}
+
+
+
+pub struct ClusterOverlay{
+	pub clusters: Vec<Cluster>,//index is id
+	pub layers: Vec<Layer>,
+	pub settings: ClusterSettings,
+}
+
+
+impl ClusterOverlay{
+
+	pub fn find(data:&AmplitudeData, settings:ClusterSettings, layers:Vec<Layer>, progress:&dyn Progress) -> Result<ClusterOverlay>{
+		let min_depth = layers.iter().map(|l| l.min_depth).min().unwrap_or(0);
+		let max_depth = layers.iter().map(|l| l.max_depth).max().unwrap_or(0);
+		let clusters = find_clusters(data, &settings, min_depth..=max_depth, progress)?;
+		return Ok(ClusterOverlay{clusters: clusters, layers: layers, settings: settings});
+	}
+
+
+	fn depth_band(&self, depth:usize) -> Option<usize>{
+		self.layers.iter().position(|l| l.min_depth <= depth && depth <= l.max_depth)
+	}
+
+
+	//cells from cell(point). one per plot position, later clusters on top.
+	fn cells(&self, colouring:OverlayColouring, cell:impl Fn(&XYZV) -> Option<(usize,usize)>) -> Vec<OverlayCell>{
+		let mut cells:Vec<OverlayCell> = Vec::new();
+		for (id,cluster) in self.clusters.iter().enumerate(){
+			for p in cluster.points(){
+				if let Some((x,y)) = cell(p){
+					let group = match colouring{
+						OverlayColouring::Cluster => id,
+						OverlayColouring::DepthBand => self.depth_band(p.z).unwrap_or(self.layers.len()),
+					};
+					cells.push(OverlayCell{x: x, y: y, group: group});
+				}
+			}
+		}
+		//reversed so stable sort puts later cells first and dedup keeps them
+		cells.reverse();
+		cells.sort_by_key(|c| (c.x, c.y));
+		cells.dedup_by_key(|c| (c.x, c.y));
+		return cells;
+	}
+
+
+	//transverse by depth at longitudinal
+	pub fn transverse_cells(&self, longitudinal:usize, transverse_range:&Range<usize>, depth_range:&Range<usize>, colouring:OverlayColouring) -> Vec<OverlayCell>{
+		self.cells(colouring, |p| (p.x == longitudinal && transverse_range.contains(&p.y) && depth_range.contains(&p.z)).then_some((p.y, p.z)))
+	}
+
+
+	//longitudinal by depth at transverse
+	pub fn longitudinal_cells(&self, transverse:usize, longitudinal_range:&Range<usize>, depth_range:&Range<usize>, colouring:OverlayColouring) -> Vec<OverlayCell>{
+		self.cells(colouring, |p| (p.y == transverse && longitudinal_range.contains(&p.x) && depth_range.contains(&p.z)).then_some((p.x, p.z)))
+	}
+
+
+	//longitudinal by transverse for samples in depths
+	pub fn depth_slice_cells(&self, depths:&Range<usize>, longitudinal_range:&Range<usize>, transverse_range:&Range<usize>, colouring:OverlayColouring) -> Vec<OverlayCell>{
+		self.cells(colouring, |p| (depths.contains(&p.z) && longitudinal_range.contains(&p.x) && transverse_range.contains(&p.y)).then_some((p.x, p.y)))
+	}
+
+
+	pub fn summaries(&self) -> Vec<ClusterSummary>{
+		return self.clusters.iter().enumerate().filter_map(|(id,c)| {
+			let peak = *c.points().max_by_key(|p| p.v)?;
+			Some(ClusterSummary{
+				id: id,
+				volume: c.volume(),
+				bounds: c.bounds(),
+				peak: peak,
+				depth_band: self.depth_band(peak.z).map(|i| self.layers[i].label.clone()),
+			})
+		}).collect();
+	}
+}
+
+
+
+
+#[cfg(test)]
+mod cluster_overlay_tests{
+	use super::*;
+	use crate::progress::NoProgress;
+
+	#[test]
+	fn test_overlay(){
+		let mut data = AmplitudeData::from_size(10,4,20);
+		for l in 2..5{
+			data.amplitudes[(l,1,6)] = Some(20000);
+		}
+		data.amplitudes[(3,1,6)] = Some(30000);
+		data.amplitudes[(8,3,6)] = Some(20000);//too small
+		let layers = vec![Layer{min_depth: 5, max_depth: 7, label: "a".to_string()}];
+		let settings = ClusterSettings{amplitude_threshold: 10000, size_threshold: 2, max_gap: 1};
+		let overlay = ClusterOverlay::find(&data, settings, layers, &NoProgress).unwrap();
+		assert_eq!(overlay.clusters.len(), 1);
+
+		let summary = &overlay.summaries()[0];
+		assert_eq!(summary.volume, 3);
+		assert_eq!((summary.peak.x, summary.peak.y, summary.peak.z), (3,1,6));
+		assert_eq!(summary.depth_band.as_deref(), Some("a"));
+
+		assert_eq!(overlay.longitudinal_cells(1, &(0..10), &(0..20), OverlayColouring::Cluster).len(), 3);
+		assert_eq!(overlay.longitudinal_cells(1, &(3..10), &(0..20), OverlayColouring::Cluster).len(), 2);
+		assert_eq!(overlay.transverse_cells(3, &(0..4), &(0..20), OverlayColouring::DepthBand), vec![OverlayCell{x: 1, y: 6, group: 0}]);
+		assert!(overlay.depth_slice_cells(&(7..9), &(0..10), &(0..4), OverlayColouring::Cluster).is_empty());
+	}
+}
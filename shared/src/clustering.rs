@@ -1,6 +1,10 @@
 use crate::core::{Amplitude,usize_dif,usize_subtract};
+use crate::amplitude_data::AmplitudeData;
+use crate::progress::Progress;
+use crate::error::Result;
 use std::cmp::{min,max};
 use std::collections::HashSet;
+use std::ops::RangeInclusive;
 
 
 /*
@@ -31,14 +35,15 @@ pub struct XYZV {
 
 
 
-#[derive(Debug,Clone,Copy)]
-struct Cuboid{
-	min_x:usize,
-	min_y:usize,
-	min_z:usize,
-	max_x:usize,
-	max_y:usize,
-	max_z:usize,
+//inclusive
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Cuboid{
+	pub min_x:usize,
+	pub min_y:usize,
+	pub min_z:usize,
+	pub max_x:usize,
+	pub max_y:usize,
+	pub max_z:usize,
 }
 
 impl Cuboid{
@@ -263,6 +268,16 @@ impl Cluster{
 	}
 
 
+	pub fn bounds(&self) -> Cuboid{
+		return self.bounds;
+	}
+	
+	
+	pub fn points(&self) -> impl Iterator<Item = &XYZV>{
+		self.regions.iter().flat_map(|r| r.data.iter())
+	}
+	
+	
 	pub fn volume(&self) -> usize{
 		let mut vol:usize = 0;
 		for r in self.regions.iter(){
@@ -393,6 +408,71 @@ impl Clusterer{
 
 
 
+/*
+which samples are clustered. same rule for extractor, viewer overlay and change detection:
+samples > amplitude_threshold, up to max_gap apart in each direction. clusters of >= size_threshold samples kept.
+*/
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct ClusterSettings{
+	pub amplitude_threshold: Amplitude,
+	pub size_threshold: usize,
+	pub max_gap: usize,
+}
+
+
+//same as extractor defaults
+impl Default for ClusterSettings{
+	fn default() -> Self{
+		return ClusterSettings{amplitude_threshold: 10000, size_threshold: 50, max_gap: 5};
+	}
+}
+
+
+impl ClusterSettings{
+	
+	pub fn clusterer(&self) -> Clusterer{
+		return Clusterer::new(self.max_gap, self.max_gap, self.max_gap);
+	}
+	
+	
+	//samples of trace at x,y within depths. value gives sample at depth.
+	pub fn add_trace(&self, clusterer:&mut Clusterer, x:usize, y:usize, depths:RangeInclusive<usize>, value:impl Fn(usize) -> Option<Amplitude>){
+		for z in depths{
+			if let Some(v) = value(z){
+				if v > self.amplitude_threshold{
+					clusterer.add_point(x, y, z, v);
+				}
+			}
+		}
+	}
+	
+	
+	pub fn large_clusters(&self, clusterer:&Clusterer) -> Vec<Cluster>{
+		return clusterer.active.iter().filter(|c| c.volume() >= self.size_threshold).cloned().collect();
+	}
+}
+
+
+
+//clusters >= size_threshold of samples within depths.
+pub fn find_clusters(data:&AmplitudeData, settings:&ClusterSettings, depths:RangeInclusive<usize>, progress:&dyn Progress) -> Result<Vec<Cluster>>{
+	let depths = *depths.start()..=min(*depths.end(), data.depth_size.saturating_sub(1));
+	let mut clusterer = settings.clusterer();
+	let stage = "Finding clusters";
+	for l in 0..data.longitudinal_size{
+		progress.update(stage, l, Some(data.longitudinal_size));
+		progress.check()?;
+		for t in 0..data.transverse_size{
+			settings.add_trace(&mut clusterer, l, t, depths.clone(), |d| data.get_value(l,t,d));
+		}
+	}
+	progress.update(stage, data.longitudinal_size, Some(data.longitudinal_size));
+	return Ok(settings.large_clusters(&clusterer));
+}
+
+
+
+
 //cargo test cluster -- --nocapture
 
 #[cfg(test)]
@@ -425,6 +505,23 @@ mod cluster_tests{
 	}
 	
 	
+	#[test]
+	fn test_find_clusters(){
+		let mut data = AmplitudeData::from_size(10,3,10);
+		for l in 0..4{
+			data.amplitudes[(l,1,5)] = Some(20000);
+		}
+		data.amplitudes[(4,1,5)] = Some(10000);//not above threshold
+		data.amplitudes[(8,1,5)] = Some(20000);//too small
+		data.amplitudes[(3,1,6)] = Some(20000);
+		let settings = ClusterSettings{amplitude_threshold: 10000, size_threshold: 2, max_gap: 1};
+		let clusters = find_clusters(&data, &settings, 2..=20, &crate::progress::NoProgress).unwrap();
+		assert_eq!(clusters.iter().map(|c| c.volume()).collect::<Vec<usize>>(), vec![5]);
+		let clusters = find_clusters(&data, &settings, 2..=5, &crate::progress::NoProgress).unwrap();
+		assert_eq!(clusters.iter().map(|c| c.volume()).collect::<Vec<usize>>(), vec![4]);
+	}
+	
+	
 }
 
 
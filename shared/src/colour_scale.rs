@@ -0,0 +1,223 @@
+use plotters::prelude::*;
+use plotters::coord::Shift;
+use colorgrad::Gradient;
+use serde::{Serialize,Deserialize};
+use ndarray::Array2;
+use crate::core::Amplitude;
+use crate::error::Result;
+
+
+/*
+amplitude to colour for plots. used by svg plots in plot.rs and by the viewer.
+
+ColourScale is the settings. colouring() works out limits for particular data and gives Colouring,
+which holds a lookup table so colouring each sample is cheap.
+*/
+
+
+//pixels wide of colourbar at right of plots
+pub const COLOURBAR_WIDTH: u32 = 110;
+
+//entries in lookup table
+const TABLE_SIZE: usize = 256;
+
+
+#[derive(Serialize,Deserialize,Debug,Clone,Copy,PartialEq,Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Colormap{
+	Greyscale,//black low
+	#[default]
+	Seismic,//blue - white - red. for amplitudes either side of 0
+	Viridis,
+	Inferno,
+	Turbo,
+	GreenRed,
+}
+
+
+impl Colormap{
+	//order used by viewer combo box
+	pub const ALL: [Colormap;6] = [Colormap::Greyscale, Colormap::Seismic, Colormap::Viridis, Colormap::Inferno, Colormap::Turbo, Colormap::GreenRed];
+
+
+	pub fn name(&self) -> &'static str{
+		match self{
+			Colormap::Greyscale => "Greyscale",
+			Colormap::Seismic => "Seismic",
+			Colormap::Viridis => "Viridis",
+			Colormap::Inferno => "Inferno",
+			Colormap::Turbo => "Turbo",
+			Colormap::GreenRed => "Green-red",
+		}
+	}
+
+
+	//t in 0..1
+	fn gradient(&self) -> Box<dyn Gradient>{
+		match self{
+			Colormap::Greyscale => colorgrad::preset::greys().boxed(),
+			Colormap::Seismic => colorgrad::preset::rd_bu().boxed(),
+			Colormap::Viridis => colorgrad::preset::viridis().boxed(),
+			Colormap::Inferno => colorgrad::preset::inferno().boxed(),
+			Colormap::Turbo => colorgrad::preset::turbo().boxed(),
+			Colormap::GreenRed => colorgrad::preset::rd_yl_gn().boxed(),
+		}
+	}
+
+
+	//presets going from light/red to dark/green are reversed
+	fn reversed(&self) -> bool{
+		matches!(self, Colormap::Greyscale | Colormap::Seismic | Colormap::GreenRed)
+	}
+}
+
+
+
+#[derive(Serialize,Deserialize,Debug,Clone,Copy,PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Clip{
+	Full,//whole range of amplitude type
+	Percentile(f32),//drop this percent of values at each end
+	Range{min:f32, max:f32},
+}
+
+
+
+#[derive(Serialize,Deserialize,Debug,Clone,Copy,PartialEq)]
+pub struct ColourScale{
+	pub colormap: Colormap,
+	pub clip: Clip,
+	pub symmetric: bool,//limits made +- largest magnitude so 0 is middle colour
+	pub gamma: f32,//contrast. applied either side of middle when symmetric
+}
+
+
+impl Default for ColourScale{
+	fn default() -> Self{
+		return ColourScale{colormap: Colormap::default(), clip: Clip::Percentile(1.0), symmetric: true, gamma: 1.0};
+	}
+}
+
+
+impl ColourScale{
+
+	//(min,max) for colours. min < max.
+	pub fn limits(&self, amplitudes:&Array2<Option<Amplitude>>) -> (f32,f32){
+		let (mut min, mut max) = match self.clip{
+			Clip::Full => (Amplitude::MIN as f32, Amplitude::MAX as f32),
+			Clip::Range{min, max} => (min, max),
+			Clip::Percentile(percent) => {
+				let mut values:Vec<Amplitude> = amplitudes.iter().filter_map(|a| *a).collect();
+				if values.is_empty(){
+					(Amplitude::MIN as f32, Amplitude::MAX as f32)
+				}
+				else{
+					values.sort_unstable();
+					let last = (values.len() - 1) as f32;
+					let fraction = (percent / 100.0).clamp(0.0, 0.5);
+					(values[(fraction * last).round() as usize] as f32, values[((1.0 - fraction) * last).round() as usize] as f32)
+				}
+			}
+		};
+		if self.symmetric{
+			max = min.abs().max(max.abs());
+			min = -max;
+		}
+		if !(max > min){
+			max = min + 1.0;
+		}
+		return (min,max);
+	}
+
+
+	pub fn colouring(&self, amplitudes:&Array2<Option<Amplitude>>) -> Colouring{
+		let (min,max) = self.limits(amplitudes);
+		let gradient = self.colormap.gradient();
+		let table = (0..TABLE_SIZE).map(|i| {
+			let t = i as f32 / (TABLE_SIZE - 1) as f32;
+			let c = gradient.at(if self.colormap.reversed() {1.0 - t} else {t}).to_rgba8();
+			RGBColor(c[0], c[1], c[2])
+		}).collect();
+		return Colouring{min: min, max: max, symmetric: self.symmetric, gamma: self.gamma.max(0.01), table: table};
+	}
+}
+
+
+
+pub struct Colouring{
+	pub min: f32,
+	pub max: f32,
+	symmetric: bool,
+	gamma: f32,
+	table: Vec<RGBColor>,
+}
+
+
+impl Colouring{
+
+	//values outside limits get end colours
+	pub fn colour(&self, value:f32) -> RGBColor{
+		let mut t = ((value - self.min) / (self.max - self.min)).clamp(0.0, 1.0);
+		if self.gamma != 1.0{
+			if self.symmetric{
+				let d = 2.0 * t - 1.0;
+				t = 0.5 + 0.5 * d.signum() * d.abs().powf(self.gamma);
+			}
+			else{
+				t = t.powf(self.gamma);
+			}
+		}
+		return self.table[(t * (TABLE_SIZE - 1) as f32).round() as usize];
+	}
+
+
+	//vertical bar with value axis. x_label_area_size 40 like plots so bar lines up with plot.
+	pub fn draw_colourbar<DB:DrawingBackend>(&self, area:&DrawingArea<DB,Shift>) -> Result<()> where DB::ErrorType: 'static{
+		let mut chart = ChartBuilder::on(area)
+			.margin(5)
+			.x_label_area_size(40)
+			.right_y_label_area_size(70)
+			.build_cartesian_2d(0.0 .. 1.0, self.min as f64 .. self.max as f64)?;
+
+		chart
+		.configure_mesh()
+		.disable_x_mesh()
+		.disable_y_mesh()
+		.disable_x_axis()
+		.label_style(("sans-serif", 20))
+		.draw()?;
+
+		let steps = TABLE_SIZE;
+		let step = (self.max - self.min) as f64 / steps as f64;
+		chart.draw_series((0..steps).map(|i| {
+			let low = self.min as f64 + step * i as f64;
+			Rectangle::new([(0.0, low), (1.0, low + step)], self.colour((low + step / 2.0) as f32).filled())
+		}))?;
+		return Ok(());
+	}
+}
+
+
+
+
+#[cfg(test)]
+mod colour_scale_tests{
+	use super::*;
+	use ndarray::Array;
+
+	#[test]
+	fn test_limits(){
+		let amplitudes:Array2<Option<Amplitude>> = Array::from_shape_fn((10,10), |(x,y)| Some((x*10+y) as Amplitude - 20));
+		let mut scale = ColourScale{colormap: Colormap::Greyscale, clip: Clip::Percentile(10.0), symmetric: false, gamma: 1.0};
+		assert_eq!(scale.limits(&amplitudes), (-10.0, 69.0));
+		scale.symmetric = true;
+		assert_eq!(scale.limits(&amplitudes), (-69.0, 69.0));
+		scale.clip = Clip::Range{min: 5.0, max: 5.0};
+		scale.symmetric = false;
+		assert_eq!(scale.limits(&amplitudes), (5.0, 6.0));
+
+		let colouring = ColourScale{colormap: Colormap::Greyscale, clip: Clip::Range{min: 0.0, max: 100.0}, symmetric: false, gamma: 1.0}.colouring(&amplitudes);
+		assert_eq!(colouring.colour(-50.0), colouring.colour(0.0));
+		assert!(colouring.colour(0.0).0 < colouring.colour(100.0).0);
+	}
+}
@@ -0,0 +1,194 @@
+use std::fmt;
+use std::path::{Path,PathBuf};
+
+
+/*
+errors from the shared crate.
+file and line are filled in where known so messages can be shown to users as they are.
+*/
+
+
+#[derive(Debug)]
+pub enum GprError{
+	Io{file: Option<PathBuf>, source: std::io::Error},
+	Parse{file: Option<PathBuf>, line: Option<usize>, message: String},
+	Csv{file: Option<PathBuf>, source: csv::Error},
+	Json{file: Option<PathBuf>, source: serde_json::Error},
+	Geometry(String),
+	Config(String),
+	Plot(String),//plot of range outside data or drawing failed
+	Cancelled,
+}
+
+
+pub type Result<T> = std::result::Result<T,GprError>;
+
+
+impl GprError{
+
+	pub fn parse(message:impl Into<String>) -> GprError{
+		GprError::Parse{file: None, line: None, message: message.into()}
+	}
+
+
+	pub fn geometry(message:impl Into<String>) -> GprError{
+		GprError::Geometry(message.into())
+	}
+
+
+	pub fn config(message:impl Into<String>) -> GprError{
+		GprError::Config(message.into())
+	}
+
+
+	pub fn plot(message:impl Into<String>) -> GprError{
+		GprError::Plot(message.into())
+	}
+
+
+	//sets file where not already known
+	pub fn in_file(mut self, path:&Path) -> GprError{
+		match &mut self{
+			GprError::Io{file, ..} | GprError::Parse{file, ..} | GprError::Csv{file, ..} | GprError::Json{file, ..} => {
+				if file.is_none(){
+					*file = Some(path.to_path_buf());
+				}
+			}
+			_ => {}
+		}
+		return self;
+	}
+
+
+	//sets line where not already known
+	pub fn at_line(mut self, number:usize) -> GprError{
+		if let GprError::Parse{line, ..} = &mut self{
+			if line.is_none(){
+				*line = Some(number);
+			}
+		}
+		return self;
+	}
+
+
+	//distinct process exit code for each kind
+	pub fn exit_code(&self) -> i32{
+		match self{
+			GprError::Config(_) => 2,
+			GprError::Io{..} => 3,
+			GprError::Parse{..} | GprError::Csv{..} | GprError::Json{..} => 4,
+			GprError::Geometry(_) => 5,
+			GprError::Plot(_) => 6,
+			GprError::Cancelled => 130,
+		}
+	}
+}
+
+
+fn location(file:&Option<PathBuf>, line:Option<usize>) -> String{
+	match (file,line){
+		(Some(f),Some(l)) => format!(" '{}' line {}", f.display(), l),
+		(Some(f),None) => format!(" '{}'", f.display()),
+		(None,Some(l)) => format!(" line {}", l),
+		(None,None) => String::new(),
+	}
+}
+
+
+impl fmt::Display for GprError{
+	fn fmt(&self, f:&mut fmt::Formatter<'_>) -> fmt::Result{
+		match self{
+			GprError::Io{file, source} => write!(f, "I/O error{}: {}", location(file, None), source),
+			GprError::Parse{file, line, message} => write!(f, "Parse error{}: {}", location(file, *line), message),
+			GprError::Csv{file, source} => write!(f, "CSV error{}: {}", location(file, source.position().map(|p| p.line() as usize)), source),
+			GprError::Json{file, source} => write!(f, "JSON error{}: {}", location(file, None), source),
+			GprError::Geometry(message) => write!(f, "Geometry error: {}", message),
+			GprError::Config(message) => write!(f, "Configuration error: {}", message),
+			GprError::Plot(message) => write!(f, "Plot error: {}", message),
+			GprError::Cancelled => write!(f, "Cancelled"),
+		}
+	}
+}
+
+
+impl std::error::Error for GprError{
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)>{
+		match self{
+			GprError::Io{source, ..} => Some(source),
+			GprError::Csv{source, ..} => Some(source),
+			GprError::Json{source, ..} => Some(source),
+			_ => None,
+		}
+	}
+}
+
+
+impl From<std::io::Error> for GprError{
+	fn from(e:std::io::Error) -> GprError{
+		GprError::Io{file: None, source: e}
+	}
+}
+
+
+impl From<csv::Error> for GprError{
+	fn from(e:csv::Error) -> GprError{
+		GprError::Csv{file: None, source: e}
+	}
+}
+
+
+impl From<serde_json::Error> for GprError{
+	fn from(e:serde_json::Error) -> GprError{
+		GprError::Json{file: None, source: e}
+	}
+}
+
+
+impl<E:std::error::Error + Send + Sync> From<plotters::drawing::DrawingAreaErrorKind<E>> for GprError{
+	fn from(e:plotters::drawing::DrawingAreaErrorKind<E>) -> GprError{
+		GprError::plot(e.to_string())
+	}
+}
+
+
+impl From<std::num::ParseIntError> for GprError{
+	fn from(e:std::num::ParseIntError) -> GprError{
+		GprError::parse(e.to_string())
+	}
+}
+
+
+impl From<std::num::ParseFloatError> for GprError{
+	fn from(e:std::num::ParseFloatError) -> GprError{
+		GprError::parse(e.to_string())
+	}
+}
+
+
+//adds file to error of any result
+pub trait InFile<T>{
+	fn in_file(self, path:&Path) -> Result<T>;
+}
+
+
+impl<T,E:Into<GprError>> InFile<T> for std::result::Result<T,E>{
+	fn in_file(self, path:&Path) -> Result<T>{
+		self.map_err(|e| e.into().in_file(path))
+	}
+}
+
+
+
+
+#[cfg(test)]
+mod error_tests{
+	use super::*;
+
+	#[test]
+	fn test_display(){
+		let e:Result<i16> = "x".parse::<i16>().in_file(Path::new("lane.txt"));
+		let e = e.unwrap_err().at_line(12);
+		assert_eq!(e.to_string(), "Parse error 'lane.txt' line 12: invalid digit found in string");
+		assert_eq!(e.exit_code(), 4);
+	}
+}
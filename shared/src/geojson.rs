@@ -0,0 +1,176 @@
+use std::str::FromStr;
+use std::io::Write;
+use std::path::Path;
+use crate::error::{Result,GprError,InFile};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::{json,Value};
+use wkt::Wkt;
+use wkt::types::{Coord,LineString,Polygon};
+
+
+/*
+conversion between WKT strings and GeoJSON geometry objects.
+keeps Z. POLYHEDRALSURFACE becomes MultiPolygon of its faces because GeoJSON has no polyhedral type.
+*/
+
+
+fn position(c:&Coord<f64>) -> Value{
+	match c.z{
+		Some(z) => json!([c.x, c.y, z]),
+		None => json!([c.x, c.y]),
+	}
+}
+
+fn line(l:&LineString<f64>) -> Value{
+	Value::Array(l.coords().iter().map(position).collect())
+}
+
+fn polygon(p:&Polygon<f64>) -> Value{
+	Value::Array(p.rings().iter().map(line).collect())
+}
+
+
+fn geometry(w:&Wkt<f64>) -> Result<Value>{
+	Ok(match w{
+		Wkt::Point(p) => json!({"type":"Point", "coordinates": p.coord().map(position).ok_or_else(|| GprError::geometry("empty point"))?}),
+		Wkt::LineString(l) => json!({"type":"LineString", "coordinates": line(l)}),
+		Wkt::Polygon(p) => json!({"type":"Polygon", "coordinates": polygon(p)}),
+		Wkt::MultiPoint(m) => json!({"type":"MultiPoint", "coordinates": m.points().iter().filter_map(|p| p.coord().map(position)).collect::<Vec<Value>>()}),
+		Wkt::MultiLineString(m) => json!({"type":"MultiLineString", "coordinates": m.line_strings().iter().map(line).collect::<Vec<Value>>()}),
+		Wkt::MultiPolygon(m) => json!({"type":"MultiPolygon", "coordinates": m.polygons().iter().map(polygon).collect::<Vec<Value>>()}),
+		Wkt::GeometryCollection(g) => json!({"type":"GeometryCollection", "geometries": g.geometries().iter().map(geometry).collect::<Result<Vec<Value>>>()?}),
+	})
+}
+
+
+//POLYHEDRALSURFACE has same syntax as MULTIPOLYGON
+pub fn polyhedral_as_multipolygon(wkt:&str) -> String{
+	let trimmed = wkt.trim();
+	match trimmed.get(..17){
+		Some(start) if start.eq_ignore_ascii_case("POLYHEDRALSURFACE") => format!("MULTIPOLYGON{}", &trimmed[17..]),
+		_ => trimmed.to_string(),
+	}
+}
+
+
+//by extension. .geojson or .json
+pub fn is_geojson(file:&str) -> bool{
+	match std::path::Path::new(file).extension().and_then(|e| e.to_str()){
+		Some(e) => e.eq_ignore_ascii_case("geojson") || e.eq_ignore_ascii_case("json"),
+		None => false,
+	}
+}
+
+
+pub fn wkt_to_geojson(wkt:&str) -> Result<Value>{
+	let w = Wkt::<f64>::from_str(&polyhedral_as_multipolygon(wkt)).map_err(|e| GprError::geometry(format!("Invalid WKT: {}", e)))?;
+	return geometry(&w);
+}
+
+
+
+fn wkt_position(v:&Value) -> Result<(String,bool)>{
+	let numbers:Vec<f64> = v.as_array().ok_or_else(|| GprError::geometry("position is not array"))?
+		.iter().map(|n| n.as_f64().ok_or_else(|| GprError::geometry("position is not numbers"))).collect::<Result<Vec<f64>>>()?;
+	match numbers.len(){
+		2 => Ok((format!("{} {}", numbers[0], numbers[1]), false)),
+		3 => Ok((format!("{} {} {}", numbers[0], numbers[1], numbers[2]), true)),
+		_ => Err(GprError::geometry("position needs 2 or 3 numbers")),
+	}
+}
+
+
+//coordinates nested depth deep as WKT text. returns whether any Z.
+fn wkt_coordinates(v:&Value, depth:usize) -> Result<(String,bool)>{
+	if depth == 0{
+		return wkt_position(v);
+	}
+	let mut parts = Vec::new();
+	let mut z = false;
+	for c in v.as_array().ok_or_else(|| GprError::geometry("coordinates are not array"))?.iter(){
+		let (text,has_z) = wkt_coordinates(c, depth - 1)?;
+		z |= has_z;
+		parts.push(if depth == 1 {text} else {format!("({})", text)});
+	}
+	Ok((parts.join(","), z))
+}
+
+
+pub fn geojson_to_wkt(geometry:&Value) -> Result<String>{
+	let kind = geometry["type"].as_str().ok_or_else(|| GprError::geometry("geometry has no type"))?;
+	let (name,depth) = match kind{
+		"Point" => ("POINT", 0),
+		"MultiPoint" => ("MULTIPOINT", 1),
+		"LineString" => ("LINESTRING", 1),
+		"MultiLineString" => ("MULTILINESTRING", 2),
+		"Polygon" => ("POLYGON", 2),
+		"MultiPolygon" => ("MULTIPOLYGON", 3),
+		"GeometryCollection" => {
+			let parts = geometry["geometries"].as_array().ok_or_else(|| GprError::geometry("collection has no geometries"))?
+				.iter().map(geojson_to_wkt).collect::<Result<Vec<String>>>()?;
+			return Ok(format!("GEOMETRYCOLLECTION({})", parts.join(",")));
+		}
+		other => return Err(GprError::geometry(format!("Unsupported geometry type {}", other))),
+	};
+	let (text,z) = wkt_coordinates(&geometry["coordinates"], depth)?;
+	let text = if kind == "MultiPoint" {text.split(',').map(|p| format!("({})", p)).collect::<Vec<String>>().join(",")} else {text};
+	Ok(format!("{}{} ({})", name, if z {" Z"} else {""}, text))
+}
+
+
+
+/*
+FeatureCollection of records with WKT. wkt field becomes geometry, other fields become properties.
+metadata as foreign member.
+*/
+pub fn write_feature_collection<T:Serialize>(features:&[T], output:&str, metadata:Option<&Value>) -> Result<()>{
+	let mut collection = Vec::with_capacity(features.len());
+	for f in features.iter(){
+		let mut properties = serde_json::to_value(f)?;
+		let wkt = properties.as_object_mut().and_then(|p| p.remove("wkt")).ok_or_else(|| GprError::geometry("feature has no wkt"))?;
+		let geometry = wkt_to_geojson(wkt.as_str().ok_or_else(|| GprError::geometry("wkt is not text"))?)?;
+		collection.push(json!({"type":"Feature", "geometry":geometry, "properties":properties}));
+	}
+	let mut collection = json!({"type":"FeatureCollection", "features":collection});
+	if let Some(metadata) = metadata{
+		collection["metadata"] = metadata.clone();
+	}
+	let file = std::fs::File::create(output).in_file(Path::new(output))?;
+	let mut w = std::io::BufWriter::new(file);
+	serde_json::to_writer(&mut w, &collection).in_file(Path::new(output))?;
+	w.flush().in_file(Path::new(output))?;
+	Ok(())
+}
+
+
+//geometry of each feature back to wkt field
+pub fn read_feature_collection<T:DeserializeOwned>(input:&str) -> Result<Vec<T>>{
+	let file = std::fs::File::open(input).in_file(Path::new(input))?;
+	let collection:Value = serde_json::from_reader(std::io::BufReader::new(file)).in_file(Path::new(input))?;
+	let mut features = Vec::new();
+	for f in collection["features"].as_array().ok_or_else(|| GprError::parse("no features").in_file(Path::new(input)))?.iter(){
+		let mut properties = f["properties"].clone();
+		properties["wkt"] = Value::String(geojson_to_wkt(&f["geometry"])?);
+		features.push(serde_json::from_value(properties).in_file(Path::new(input))?);
+	}
+	Ok(features)
+}
+
+
+
+
+#[cfg(test)]
+mod geojson_tests{
+	use super::*;
+
+	#[test]
+	fn test_round_trip(){
+		for w in ["MULTIPOLYGON (((0 0,1 0,1 1,0 0)))", "MULTIPOINT Z ((1 2 3),(4 5 6))", "POINT (1 2)"]{
+			assert_eq!(geojson_to_wkt(&wkt_to_geojson(w).unwrap()).unwrap(), w);
+		}
+		let polyhedral = wkt_to_geojson("POLYHEDRALSURFACE Z (((0 0 1,1 0 1,1 1 1,0 0 1)))").unwrap();
+		assert_eq!(polyhedral["type"], "MultiPolygon");
+		assert_eq!(polyhedral["coordinates"][0][0][1], json!([1.0, 0.0, 1.0]));
+	}
+}
@@ -0,0 +1,118 @@
+use geo::{Polygon,MultiPolygon,CoordsIter};
+use serde::{Serialize,Deserialize};
+use crate::amplitude_data::Z_SPACING;
+
+
+/*
+3D geometry.
+geo only does 2D so Z geometries are written straight to WKT here.
+
+Z is depth below surface in meters (positive down)
+or elevation when surface_level is given.
+*/
+
+
+
+#[derive(Serialize,Deserialize,Debug,Clone,Copy)]
+pub struct ZModel{
+	pub zero_depth: usize,//sample index of surface
+	pub z_spacing: f64,//meters per sample
+	pub surface_level: Option<f64>,//elevation of surface. Z is elevation when set.
+}
+
+
+impl Default for ZModel{
+	fn default() -> Self{
+		ZModel{zero_depth: 51, z_spacing: Z_SPACING, surface_level: None}
+	}
+}
+
+
+impl ZModel{
+
+	//depth below surface in meters
+	pub fn depth(&self, sample:f64) -> f64{
+		(sample - self.zero_depth as f64) * self.z_spacing
+	}
+
+	//Z coordinate for sample index. fractional for interpolated positions.
+	pub fn z(&self, sample:f64) -> f64{
+		match self.surface_level{
+			Some(level) => level - self.depth(sample),
+			None => self.depth(sample),
+		}
+	}
+}
+
+
+
+fn coord_z(x:f64, y:f64, z:f64) -> String{
+	format!("{} {} {}", x, y, z)
+}
+
+
+fn ring_z(poly: &Polygon<f64>, z:f64) -> String{
+	let coords:Vec<String> = poly.exterior().coords_iter().map(|c| coord_z(c.x, c.y, z)).collect();
+	format!("({})", coords.join(","))
+}
+
+
+pub fn multipoint_z_wkt(points:&[(f64,f64,f64)]) -> String{
+	let coords:Vec<String> = points.iter().map(|p| format!("({})", coord_z(p.0, p.1, p.2))).collect();
+	format!("MULTIPOINT Z ({})", coords.join(","))
+}
+
+
+//all polygons flat at z
+pub fn multipolygon_z_wkt(polygons:&MultiPolygon<f64>, z:f64) -> String{
+	let polys:Vec<String> = polygons.iter().map(|p| format!("({})", ring_z(p, z))).collect();
+	format!("MULTIPOLYGON Z ({})", polys.join(","))
+}
+
+
+//prism for each polygon from top to bottom. faces are top, bottom then sides.
+pub fn polyhedral_surface_wkt(polygons:&MultiPolygon<f64>, top:f64, bottom:f64) -> String{
+	let mut faces:Vec<String> = Vec::new();
+	for p in polygons.iter(){
+		let ring:Vec<(f64,f64)> = p.exterior().coords_iter().map(|c| (c.x, c.y)).collect();
+		if ring.len() < 4{
+			continue;//degenerate hull (point or line). no volume
+		}
+		faces.push(format!("({})", ring_z(p, top)));
+
+		let reversed:Vec<String> = ring.iter().rev().map(|c| coord_z(c.0, c.1, bottom)).collect();
+		faces.push(format!("(({}))", reversed.join(",")));
+
+		for w in ring.windows(2){
+			let (a,b) = (w[0], w[1]);
+			faces.push(format!("(({},{},{},{},{}))",
+				coord_z(a.0, a.1, top), coord_z(a.0, a.1, bottom), coord_z(b.0, b.1, bottom), coord_z(b.0, b.1, top), coord_z(a.0, a.1, top)));
+		}
+	}
+	format!("POLYHEDRALSURFACE Z ({})", faces.join(","))
+}
+
+
+
+#[cfg(test)]
+mod geometry_z_tests{
+	use super::*;
+	use geo::polygon;
+
+	#[test]
+	fn test_z_model(){
+		let depth = ZModel{zero_depth:10, z_spacing:0.5, surface_level:None};
+		assert_eq!(depth.z(14.0), 2.0);
+		let elevation = ZModel{zero_depth:10, z_spacing:0.5, surface_level:Some(100.0)};
+		assert_eq!(elevation.z(14.0), 98.0);
+	}
+
+	#[test]
+	fn test_polyhedral_surface(){
+		let p = MultiPolygon::new(vec![polygon![(x: 0.0, y: 0.0), (x: 1.0, y: 0.0), (x: 1.0, y: 1.0), (x: 0.0, y: 0.0)]]);
+		let w = polyhedral_surface_wkt(&p, 0.1, 0.2);
+		assert!(w.starts_with("POLYHEDRALSURFACE Z ((("));
+		assert_eq!(w.matches("((").count(), 5, "top, bottom and 3 sides");
+		assert_eq!(multipoint_z_wkt(&[(1.0,2.0,3.0)]), "MULTIPOINT Z ((1 2 3))");
+	}
+}
@@ -0,0 +1,319 @@
+use std::fs::File;
+use std::io::{BufWriter,Write};
+use std::path::Path;
+use crate::error::{Result,GprError,InFile};
+use ndarray::Array2;
+use crate::position_data::PositionData;
+
+
+/*
+minimal GeoTIFF writer.
+little endian classic TIFF, float32, uncompressed. each band is one strip (planar configuration 2).
+georeferenced with ModelPixelScale + ModelTiepoint from top left corner.
+*/
+
+
+pub const NODATA:f32 = -9999.0;
+
+const SHORT:u16 = 3;
+const LONG:u16 = 4;
+const ASCII:u16 = 2;
+const DOUBLE:u16 = 12;
+
+
+//regular grid in real world coordinates. row 0 is north.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct GridSpec{
+	pub min_x: f64,
+	pub max_y: f64,
+	pub cell_size: f64,
+	pub width: usize,
+	pub height: usize,
+}
+
+
+impl GridSpec{
+
+	//grid covering every point in positions. cells centred on min, so max is floor(extent / cell_size + 0.5) cells further.
+	pub fn covering(positions:&PositionData, cell_size:f64) -> Result<GridSpec>{
+		if cell_size <= 0.0{
+			return Err(GprError::config("cell size must be > 0"));
+		}
+		let mut min = (f64::MAX, f64::MAX);
+		let mut max = (f64::MIN, f64::MIN);
+		for (_l,_t,p) in positions.points(){
+			min = (min.0.min(p.x()), min.1.min(p.y()));
+			max = (max.0.max(p.x()), max.1.max(p.y()));
+		}
+		if min.0 > max.0{
+			return Err(GprError::geometry("no positions"));
+		}
+		Ok(GridSpec{
+			min_x: min.0 - cell_size / 2.0,
+			max_y: max.1 + cell_size / 2.0,
+			cell_size: cell_size,
+			width: ((max.0 - min.0) / cell_size + 0.5).floor() as usize + 1,
+			height: ((max.1 - min.1) / cell_size + 0.5).floor() as usize + 1,
+		})
+	}
+
+
+	//(col,row) of cell containing x,y
+	pub fn cell(&self, x:f64, y:f64) -> Option<(usize,usize)>{
+		let col = ((x - self.min_x) / self.cell_size).floor();
+		let row = ((self.max_y - y) / self.cell_size).floor();
+		if col < 0.0 || row < 0.0 || col as usize >= self.width || row as usize >= self.height{
+			return None;
+		}
+		Some((col as usize, row as usize))
+	}
+
+
+	//centre of cell
+	pub fn centre(&self, col:usize, row:usize) -> (f64,f64){
+		(self.min_x + (col as f64 + 0.5) * self.cell_size, self.max_y - (row as f64 + 0.5) * self.cell_size)
+	}
+
+
+	/*
+	grids values at trace positions. cell value is mean of traces in cell.
+	empty cells take mean of filled cells within fill_radius cells. NODATA otherwise.
+	values is longitudinal,transverse like PositionData.
+	row major, row 0 north.
+	*/
+	pub fn grid(&self, positions:&PositionData, values:&Array2<Option<f32>>, fill_radius:usize) -> Vec<f32>{
+		let mut sums = vec![0.0f64; self.width * self.height];
+		let mut counts = vec![0usize; self.width * self.height];
+		for (l,t,p) in positions.points(){
+			if let (Some(Some(v)),Some((col,row))) = (values.get((l,t)), self.cell(p.x(), p.y())){
+				sums[row * self.width + col] += *v as f64;
+				counts[row * self.width + col] += 1;
+			}
+		}
+		let mut cells:Vec<f32> = sums.iter().zip(counts.iter()).map(|(s,c)| if *c > 0 {(*s / *c as f64) as f32} else {NODATA}).collect();
+
+		if fill_radius > 0{
+			let r = fill_radius as i64;
+			let filled = cells.clone();
+			for row in 0..self.height as i64{
+				for col in 0..self.width as i64{
+					if counts[(row * self.width as i64 + col) as usize] > 0{
+						continue;
+					}
+					let mut sum = 0.0;
+					let mut n = 0;
+					for dr in -r..=r{
+						for dc in -r..=r{
+							let (rr,cc) = (row + dr, col + dc);
+							if rr >= 0 && cc >= 0 && rr < self.height as i64 && cc < self.width as i64{
+								let i = (rr * self.width as i64 + cc) as usize;
+								if counts[i] > 0{
+									sum += filled[i] as f64;
+									n += 1;
+								}
+							}
+						}
+					}
+					if n > 0{
+						cells[(row * self.width as i64 + col) as usize] = (sum / n as f64) as f32;
+					}
+				}
+			}
+		}
+		return cells;
+	}
+}
+
+
+
+struct Entry{
+	tag: u16,
+	kind: u16,
+	count: u32,
+	data: Vec<u8>,
+}
+
+
+fn shorts(tag:u16, values:&[u16]) -> Entry{
+	Entry{tag: tag, kind: SHORT, count: values.len() as u32, data: values.iter().flat_map(|v| v.to_le_bytes()).collect()}
+}
+
+fn longs(tag:u16, values:&[u32]) -> Entry{
+	Entry{tag: tag, kind: LONG, count: values.len() as u32, data: values.iter().flat_map(|v| v.to_le_bytes()).collect()}
+}
+
+fn doubles(tag:u16, values:&[f64]) -> Entry{
+	Entry{tag: tag, kind: DOUBLE, count: values.len() as u32, data: values.iter().flat_map(|v| v.to_le_bytes()).collect()}
+}
+
+fn ascii(tag:u16, value:&str) -> Entry{
+	let mut data = value.as_bytes().to_vec();
+	data.push(0);
+	Entry{tag: tag, kind: ASCII, count: data.len() as u32, data: data}
+}
+
+
+fn xml_escape(s:&str) -> String{
+	s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+
+/*
+writes float32 bands. each band is grid.width*grid.height row major.
+labels become band descriptions (GDAL metadata).
+epsg is projected coordinate system. left user defined when None.
+*/
+pub fn write_geotiff(output:&Path, grid:&GridSpec, bands:&[Vec<f32>], labels:&[String], epsg:Option<u16>) -> Result<()>{
+	if bands.len() == 0{
+		return Err(GprError::config("no bands"));
+	}
+	let band_size = grid.width * grid.height;
+	if bands.iter().any(|b| b.len() != band_size){
+		return Err(GprError::geometry("band size does not match grid"));
+	}
+	let n = bands.len() as u16;
+	let strip_bytes = (band_size * 4) as u32;
+	let data_start:u32 = 8;
+
+	let mut geo_keys:Vec<u16> = vec![1,1,0,0, 1024,0,1,1, 1025,0,1,1];//projected, pixel is area
+	if let Some(code) = epsg{
+		geo_keys.extend_from_slice(&[3072,0,1,code]);
+	}
+	geo_keys[3] = (geo_keys.len() / 4 - 1) as u16;
+
+	let mut metadata = String::from("<GDALMetadata>");
+	for (i,l) in labels.iter().enumerate(){
+		metadata.push_str(&format!("<Item name=\"DESCRIPTION\" sample=\"{}\" role=\"description\">{}</Item>", i, xml_escape(l)));
+	}
+	metadata.push_str("</GDALMetadata>");
+
+	let mut entries = vec![
+		longs(256, &[grid.width as u32]),
+		longs(257, &[grid.height as u32]),
+		shorts(258, &vec![32; n as usize]),
+		shorts(259, &[1]),//no compression
+		shorts(262, &[1]),//black is zero
+		longs(273, &(0..n as u32).map(|i| data_start + i * strip_bytes).collect::<Vec<u32>>()),
+		shorts(277, &[n]),
+		longs(278, &[grid.height as u32]),
+		longs(279, &vec![strip_bytes; n as usize]),
+		shorts(284, &[2]),//planar
+		shorts(339, &vec![3; n as usize]),//float
+		doubles(33550, &[grid.cell_size, grid.cell_size, 0.0]),
+		doubles(33922, &[0.0, 0.0, 0.0, grid.min_x, grid.max_y, 0.0]),
+		shorts(34735, &geo_keys),
+		ascii(42112, &metadata),
+		ascii(42113, &format!("{}", NODATA)),
+	];
+	if n > 1{
+		entries.push(shorts(338, &vec![0; n as usize - 1]));//unspecified extra samples
+	}
+	entries.sort_by_key(|e| e.tag);
+
+	//values too big for entry go after image data
+	let mut extra:Vec<u8> = Vec::new();
+	let extra_start = data_start as usize + bands.len() * strip_bytes as usize;
+	let mut offsets:Vec<Option<u32>> = Vec::new();
+	for e in entries.iter(){
+		if e.data.len() > 4{
+			offsets.push(Some((extra_start + extra.len()) as u32));
+			extra.extend_from_slice(&e.data);
+			if extra.len() % 2 == 1{
+				extra.push(0);
+			}
+		}
+		else{
+			offsets.push(None);
+		}
+	}
+	let ifd_offset = (extra_start + extra.len()) as u32;
+
+	let write = || -> std::io::Result<()>{
+		let mut w = BufWriter::new(File::create(output)?);
+		w.write_all(b"II")?;
+		w.write_all(&42u16.to_le_bytes())?;
+		w.write_all(&ifd_offset.to_le_bytes())?;
+		for b in bands.iter(){
+			for v in b.iter(){
+				w.write_all(&v.to_le_bytes())?;
+			}
+		}
+		w.write_all(&extra)?;
+
+		w.write_all(&(entries.len() as u16).to_le_bytes())?;
+		for (e,offset) in entries.iter().zip(offsets.iter()){
+			w.write_all(&e.tag.to_le_bytes())?;
+			w.write_all(&e.kind.to_le_bytes())?;
+			w.write_all(&e.count.to_le_bytes())?;
+			match offset{
+				Some(o) => w.write_all(&o.to_le_bytes())?,
+				None => {
+					let mut value = e.data.clone();
+					value.resize(4, 0);
+					w.write_all(&value)?;
+				}
+			}
+		}
+		w.write_all(&0u32.to_le_bytes())?;//no more IFDs
+		w.flush()?;
+		Ok(())
+	};
+	return write().in_file(output);
+}
+
+
+
+
+#[cfg(test)]
+mod geotiff_tests{
+	use super::*;
+	use geo::Point;
+	use ndarray::Array;
+
+	fn positions() -> PositionData{
+		let mut positions = PositionData::new(4,2);
+		for l in 0..4{
+			for t in 0..2{
+				positions.add_point(l,t,Point::new(100.0 + l as f64,200.0 + t as f64));
+			}
+		}
+		positions
+	}
+
+
+	#[test]
+	fn test_grid(){
+		let positions = positions();
+		let grid = GridSpec::covering(&positions, 1.0).unwrap();
+		assert_eq!((grid.width,grid.height), (4,2));
+		assert_eq!(grid.cell(100.0,201.0), Some((0,0)), "row 0 is north");
+
+		let mut values = Array::from_elem((4,2), Some(1.0));
+		values[(3,0)] = None;
+		let cells = grid.grid(&positions, &values, 0);
+		assert_eq!(cells[0], 1.0);
+		assert_eq!(cells[4+3], NODATA);
+		assert_eq!(grid.grid(&positions, &values, 1)[4+3], 1.0, "filled from neighbours");
+
+		//extent not whole number of cells
+		let mut positions = PositionData::new(60,1);
+		for l in 0..60{
+			positions.add_point(l,0,Point::new(l as f64 * 0.072, 0.0));
+		}
+		for cell_size in [0.1, 1.0, 0.072, 0.25]{
+			let grid = GridSpec::covering(&positions, cell_size).unwrap();
+			assert!(positions.points().all(|(_l,_t,p)| grid.cell(p.x(), p.y()).is_some()), "cell size {}", cell_size);
+		}
+	}
+
+
+	#[test]
+	fn test_write_geotiff(){
+		let grid = GridSpec::covering(&positions(), 1.0).unwrap();
+		let output = std::env::temp_dir().join("geotiff_tests.tif");
+		write_geotiff(&output, &grid, &[vec![1.0;8],vec![2.0;8]], &["a".to_string(),"b".to_string()], Some(27700)).unwrap();
+		let bytes = std::fs::read(&output).unwrap();
+		assert_eq!(&bytes[0..4], &[b'I',b'I',42,0]);
+		assert_eq!(f32::from_le_bytes([bytes[8+32],bytes[9+32],bytes[10+32],bytes[11+32]]), 2.0, "second band after first");
+	}
+}
@@ -4,3 +4,17 @@ pub mod trace;
 pub mod clustering;
 pub mod position_data;
 pub mod plot;
+pub mod geometry_z;
+pub mod mesh;
+pub mod geotiff;
+pub mod chainage;
+pub mod position_qa;
+pub mod array_geometry;
+pub mod geojson;
+pub mod merge;
+pub mod change;
+pub mod progress;
+pub mod error;
+pub mod colour_scale;
+pub mod cluster_overlay;
+pub mod trace_map;
@@ -0,0 +1,239 @@
+use std::str::FromStr;
+use geo::{Geometry,MultiPolygon,GeometryCollection,BoundingRect,Distance,Euclidean,unary_union};
+use rstar::{RTree,AABB};
+use rstar::primitives::{Rectangle,GeomWithData};
+use wkt::{Wkt,ToWkt,TryFromWkt};
+use serde::{Serialize,Deserialize};
+use crate::error::{Result,GprError};
+use crate::core::Amplitude;
+use crate::position_data::ClusterFeature;
+use crate::geometry_z::multipoint_z_wkt;
+use crate::geojson::polyhedral_as_multipolygon;
+
+
+/*
+merges features of separately processed lanes into one layer.
+
+features in same depth band from different lanes are merged when within tolerance of each other.
+merging is transitive so a feature crossing 3 lanes becomes 1 feature.
+features of same lane are never merged directly. they were separate clusters.
+all lanes must be in same projected CRS. nothing is reprojected.
+
+polygons are unioned. 3D polygons and polyhedral surfaces become their 2D footprint when merged.
+points are combined keeping Z.
+mean_amplitude is mean of merged features. chainage and offset are full range when all features are from one lane.
+None when from several as each lane's are measured from its own centreline.
+*/
+
+
+#[derive(Debug,Clone)]
+pub struct Lane{
+	pub name: String,
+	pub features: Vec<ClusterFeature>,
+}
+
+
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct MergedFeature{
+	pub depth_band: String,
+	pub mean_amplitude: Amplitude,
+	pub start_chainage: Option<f64>,
+	pub end_chainage: Option<f64>,
+	pub min_offset: Option<f64>,
+	pub max_offset: Option<f64>,
+	pub lanes: String,//lane names separated by ;
+	pub lane_count: usize,
+	pub sources: String,//lane:row of each merged feature separated by ;
+	pub wkt: String,
+}
+
+
+
+pub(crate) fn parse_geometry(wkt:&str) -> std::result::Result<Geometry<f64>,String>{
+	Geometry::<f64>::try_from_wkt_str(&polyhedral_as_multipolygon(wkt)).map_err(|e| format!("Invalid WKT '{}': {:?}", wkt, e))
+}
+
+
+fn find(parent:&mut Vec<usize>, mut i:usize) -> usize{
+	while parent[i] != i{
+		parent[i] = parent[parent[i]];
+		i = parent[i];
+	}
+	return i;
+}
+
+
+fn min_option(values:impl Iterator<Item = Option<f64>>) -> Option<f64>{
+	values.flatten().reduce(f64::min)
+}
+
+
+fn max_option(values:impl Iterator<Item = Option<f64>>) -> Option<f64>{
+	values.flatten().reduce(f64::max)
+}
+
+
+//x,y,z of every point in multipoint wkt. None when not multipoint.
+fn points_z(wkt:&str) -> Option<Vec<(f64,f64,f64)>>{
+	match Wkt::<f64>::from_str(wkt).ok()?{
+		Wkt::MultiPoint(m) => Some(m.points().iter().filter_map(|p| p.coord().map(|c| (c.x, c.y, c.z.unwrap_or(0.0)))).collect()),
+		Wkt::Point(p) => p.coord().map(|c| vec![(c.x, c.y, c.z.unwrap_or(0.0))]),
+		_ => None,
+	}
+}
+
+
+fn merged_wkt(features:&[&ClusterFeature], geometries:&[&Geometry<f64>]) -> String{
+	if features.len() == 1{
+		return features[0].wkt.clone();
+	}
+	let polygons:Vec<MultiPolygon<f64>> = geometries.iter().filter_map(|g| match g{
+		Geometry::Polygon(p) => Some(MultiPolygon::new(vec![p.clone()])),
+		Geometry::MultiPolygon(m) => Some(m.clone()),
+		_ => None,
+	}).collect();
+	if polygons.len() == geometries.len(){
+		return unary_union(polygons.iter()).wkt_string();
+	}
+	let has_z = features.iter().any(|f| f.wkt.to_uppercase().contains(" Z"));
+	let points:Option<Vec<Vec<(f64,f64,f64)>>> = features.iter().map(|f| points_z(&f.wkt)).collect();
+	if let (true, Some(points)) = (has_z, &points){
+		return multipoint_z_wkt(&points.concat());
+	}
+	let mut all:Vec<Geometry<f64>> = Vec::new();
+	for g in geometries.iter(){
+		match g{
+			Geometry::MultiPoint(m) => all.extend(m.iter().map(|p| Geometry::Point(*p))),
+			_ => all.push((*g).clone()),
+		}
+	}
+	if all.iter().all(|g| matches!(g, Geometry::Point(_))){
+		let points:Vec<geo::Point<f64>> = all.into_iter().filter_map(|g| if let Geometry::Point(p) = g {Some(p)} else {None}).collect();
+		return geo::MultiPoint::new(points).wkt_string();
+	}
+	return GeometryCollection::new_from(all).wkt_string();
+}
+
+
+
+type Envelope = GeomWithData<Rectangle<[f64;2]>,usize>;
+
+
+/*
+features of every lane merged within depth bands.
+output in order of depth band then first feature.
+*/
+pub fn merge_lanes(lanes:&[Lane], tolerance:f64) -> Result<Vec<MergedFeature>>{
+	//(lane index, row index, feature, geometry)
+	let mut items:Vec<(usize,usize,&ClusterFeature,Geometry<f64>)> = Vec::new();
+	for (l,lane) in lanes.iter().enumerate(){
+		for (r,f) in lane.features.iter().enumerate(){
+			items.push((l, r, f, parse_geometry(&f.wkt).map_err(|e| GprError::geometry(format!("lane {} row {}: {}", lane.name, r, e)))?));
+		}
+	}
+	
+	let mut bands:Vec<&str> = Vec::new();
+	for item in items.iter(){
+		if !bands.contains(&item.2.depth_band.as_str()){
+			bands.push(&item.2.depth_band);
+		}
+	}
+	
+	let mut parent:Vec<usize> = (0..items.len()).collect();
+	for band in bands.iter(){
+		let members:Vec<usize> = (0..items.len()).filter(|i| items[*i].2.depth_band == *band).collect();
+		let envelopes:Vec<Envelope> = members.iter().filter_map(|i| {
+			let r = items[*i].3.bounding_rect()?;
+			Some(GeomWithData::new(Rectangle::from_corners([r.min().x, r.min().y], [r.max().x, r.max().y]), *i))
+		}).collect();
+		let tree = RTree::bulk_load(envelopes.clone());
+		for e in envelopes.iter(){
+			let (lower, upper) = (e.geom().lower(), e.geom().upper());
+			let search = AABB::from_corners([lower[0] - tolerance, lower[1] - tolerance], [upper[0] + tolerance, upper[1] + tolerance]);
+			for other in tree.locate_in_envelope_intersecting(&search){
+				let (i,j) = (e.data, other.data);
+				if j <= i || items[i].0 == items[j].0{
+					continue;
+				}
+				if Euclidean.distance(&items[i].3, &items[j].3) <= tolerance{
+					let (a,b) = (find(&mut parent, i), find(&mut parent, j));
+					parent[a.max(b)] = a.min(b);
+				}
+			}
+		}
+	}
+	
+	//groups in order of first member. root is always smallest index.
+	let mut groups:Vec<Vec<usize>> = Vec::new();
+	let mut group_of:Vec<Option<usize>> = vec![None; items.len()];
+	for i in 0..items.len(){
+		let root = find(&mut parent, i);
+		match group_of[root]{
+			Some(g) => groups[g].push(i),
+			None => {
+				group_of[root] = Some(groups.len());
+				groups.push(vec![i]);
+			}
+		}
+	}
+	groups.sort_by_key(|g| (bands.iter().position(|b| *b == items[g[0]].2.depth_band), g[0]));
+	
+	let mut merged = Vec::with_capacity(groups.len());
+	for g in groups.iter(){
+		let features:Vec<&ClusterFeature> = g.iter().map(|i| items[*i].2).collect();
+		let geometries:Vec<&Geometry<f64>> = g.iter().map(|i| &items[*i].3).collect();
+		let mut lane_names:Vec<&str> = Vec::new();
+		for i in g.iter(){
+			if !lane_names.contains(&lanes[items[*i].0].name.as_str()){
+				lane_names.push(&lanes[items[*i].0].name);
+			}
+		}
+		let sum:i64 = features.iter().map(|f| f.mean_amplitude as i64).sum();
+		let one_lane = lane_names.len() == 1;
+		merged.push(MergedFeature{
+			depth_band: features[0].depth_band.clone(),
+			mean_amplitude: (sum / features.len() as i64) as Amplitude,
+			start_chainage: min_option(features.iter().map(|f| f.start_chainage)).filter(|_| one_lane),
+			end_chainage: max_option(features.iter().map(|f| f.end_chainage)).filter(|_| one_lane),
+			min_offset: min_option(features.iter().map(|f| f.min_offset)).filter(|_| one_lane),
+			max_offset: max_option(features.iter().map(|f| f.max_offset)).filter(|_| one_lane),
+			lanes: lane_names.join(";"),
+			lane_count: lane_names.len(),
+			sources: g.iter().map(|i| format!("{}:{}", lanes[items[*i].0].name, items[*i].1)).collect::<Vec<String>>().join(";"),
+			wkt: merged_wkt(&features, &geometries),
+		});
+	}
+	return Ok(merged);
+}
+
+
+
+
+#[cfg(test)]
+mod merge_tests{
+	use super::*;
+	
+	fn square(band:&str, x:f64, amplitude:Amplitude) -> ClusterFeature{
+		ClusterFeature{depth_band: band.to_string(), mean_amplitude: amplitude, start_chainage: Some(x), end_chainage: Some(x + 1.0), min_offset: None, max_offset: None,
+			wkt: format!("MULTIPOLYGON ((({x} 0,{} 0,{} 1,{x} 1,{x} 0)))", x + 1.0, x + 1.0)}
+	}
+	
+	#[test]
+	fn test_merge_lanes(){
+		let a = Lane{name: "a".into(), features: vec![square("top", -2.0, 100), square("top", 1.02, 100), square("deep", 0.0, 100), square("top", -0.97, 100)]};
+		let b = Lane{name: "b".into(), features: vec![square("top", 1.05, 200), square("deep", 5.0, 100)]};
+		let merged = merge_lanes(&[a,b], 0.1).unwrap();
+		let summary:Vec<(&str,&str,&str,Amplitude)> = merged.iter().map(|m| (m.depth_band.as_str(), m.lanes.as_str(), m.sources.as_str(), m.mean_amplitude)).collect();
+		assert_eq!(summary, vec![
+			("top","a","a:0",100),
+			("top","a;b","a:1;b:0",150),
+			("top","a","a:3",100),//touches a:0 but same lane
+			("deep","a","a:2",100),
+			("deep","b","b:1",100),
+		]);
+		assert!(merged[1].wkt.starts_with("MULTIPOLYGON"));
+		//chainage of different lanes not comparable
+		assert_eq!((merged[0].start_chainage, merged[0].end_chainage), (Some(-2.0), Some(-1.0)));
+		assert_eq!((merged[1].start_chainage, merged[1].end_chainage), (None, None));
+	}
+}
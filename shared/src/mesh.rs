@@ -0,0 +1,493 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter,Write};
+use std::path::Path;
+use crate::error::{Result,GprError,InFile};
+use colorgrad::Gradient;
+use geo::Point;
+use serde_json::json;
+use crate::core::Amplitude;
+use crate::clustering::Cluster;
+use crate::position_data::PositionData;
+use crate::amplitude_data::AmplitudeData;
+use crate::geometry_z::ZModel;
+
+
+/*
+isosurface meshes.
+
+marches cubes of 8 neighbouring samples. each cube is split into 6 tetrahedra
+which avoids the ambiguous cases of the classic marching cubes table and gives watertight output.
+vertices are shared between triangles on the same edge.
+
+grid coordinates are (longitudinal, transverse, depth). real world x,y come from PositionData,
+z from ZModel.
+*/
+
+
+//corners of unit cube
+const CORNERS:[(i64,i64,i64);8] = [(0,0,0),(1,0,0),(1,1,0),(0,1,0),(0,0,1),(1,0,1),(1,1,1),(0,1,1)];
+
+//6 tetrahedra around diagonal 0-6
+const TETRAHEDRA:[[usize;4];6] = [[0,5,1,6],[0,1,2,6],[0,2,3,6],[0,3,7,6],[0,7,4,6],[0,4,5,6]];
+
+
+#[derive(Debug,Clone)]
+pub struct MeshGroup{
+	pub name: String,
+	pub first_triangle: usize,
+	pub triangle_count: usize,
+}
+
+
+#[derive(Debug,Clone,Default)]
+pub struct Mesh{
+	pub vertices: Vec<[f64;3]>,
+	pub amplitudes: Vec<Amplitude>,//per vertex. for colouring
+	pub triangles: Vec<[u32;3]>,
+	pub groups: Vec<MeshGroup>,
+	pub z_is_elevation: bool,//z is depth (positive down) otherwise
+}
+
+
+type GridCoord = (i64,i64,i64);
+
+
+//builds triangles for one group. vertices keyed by edge so shared between triangles.
+struct Marcher<'a>{
+	mesh: &'a mut Mesh,
+	positions: &'a PositionData,
+	z_model: &'a ZModel,
+	edge_vertices: HashMap<(GridCoord,GridCoord),Option<u32>>,
+}
+
+
+impl<'a> Marcher<'a>{
+
+	fn point(&self, c:GridCoord) -> Option<Point>{
+		if c.0 < 0 || c.1 < 0{
+			return None;
+		}
+		self.positions.get_point(c.0 as usize, c.1 as usize)
+	}
+
+
+	//vertex on edge from inside corner a to outside corner b.
+	fn vertex(&mut self, a:GridCoord, va:f32, b:GridCoord, vb:f32, level:f32, amplitude:Amplitude) -> Option<u32>{
+		let key = if a < b {(a,b)} else {(b,a)};
+		if let Some(v) = self.edge_vertices.get(&key){
+			return *v;
+		}
+		let t = ((level - va) / (vb - va)) as f64;
+		//positions outside data fall back to other end of edge
+		let pa = self.point(a).or(self.point(b));
+		let pb = self.point(b).or(pa);
+		let v = match (pa,pb){
+			(Some(pa),Some(pb)) => {
+				let depth = a.2 as f64 + t * (b.2 - a.2) as f64;
+				self.mesh.vertices.push([pa.x() + t * (pb.x() - pa.x()) , pa.y() + t * (pb.y() - pa.y()) , self.z_model.z(depth)]);
+				self.mesh.amplitudes.push(amplitude);
+				Some((self.mesh.vertices.len() - 1) as u32)
+			}
+			_ => None,
+		};
+		self.edge_vertices.insert(key, v);
+		return v;
+	}
+
+
+	fn push_triangle(&mut self, v:[Option<u32>;3], inside:[f64;3], outside:[f64;3]){
+		let (Some(a),Some(b),Some(c)) = (v[0],v[1],v[2]) else {return};
+		if a == b || b == c || a == c{
+			return;
+		}
+		//orient so normal points from inside to outside
+		let p = |i:u32| self.mesh.vertices[i as usize];
+		let (pa,pb,pc) = (p(a),p(b),p(c));
+		let u = [pb[0]-pa[0], pb[1]-pa[1], pb[2]-pa[2]];
+		let w = [pc[0]-pa[0], pc[1]-pa[1], pc[2]-pa[2]];
+		let n = [u[1]*w[2]-u[2]*w[1], u[2]*w[0]-u[0]*w[2], u[0]*w[1]-u[1]*w[0]];
+		let d = [outside[0]-inside[0], outside[1]-inside[1], outside[2]-inside[2]];
+		if n[0]*d[0] + n[1]*d[1] + n[2]*d[2] < 0.0{
+			self.mesh.triangles.push([a,c,b]);
+		}
+		else{
+			self.mesh.triangles.push([a,b,c]);
+		}
+	}
+
+
+	//grid coordinates in the same space as vertices for orienting triangles.
+	//corners without position use 1st of others that has one, like vertex uses other end of edge. None when none have.
+	fn grid_position(&self, c:GridCoord, others:&[GridCoord;4]) -> Option<[f64;3]>{
+		let p = self.point(c).or_else(|| others.iter().find_map(|o| self.point(*o)))?;
+		return Some([p.x(), p.y(), self.z_model.z(c.2 as f64)]);
+	}
+
+
+	fn tetrahedron(&mut self, corners:[GridCoord;4], values:[f32;4], amplitudes:[Amplitude;4], level:f32){
+		let inside:Vec<usize> = (0..4).filter(|i| values[*i] >= level).collect();
+		let outside:Vec<usize> = (0..4).filter(|i| values[*i] < level).collect();
+		if inside.len() == 0 || outside.len() == 0{
+			return;
+		}
+		let edge = |s:&mut Self, i:usize, o:usize| s.vertex(corners[i], values[i], corners[o], values[o], level, amplitudes[i]);
+
+		let centre = |s:&Self, idx:&Vec<usize>| {
+			let mut c = [0.0;3];
+			for i in idx.iter(){
+				let p = s.grid_position(corners[*i], &corners)?;
+				for k in 0..3{
+					c[k] += p[k] / idx.len() as f64;
+				}
+			}
+			Some(c)
+		};
+		//no corner has position so no vertices either
+		let (Some(ci),Some(co)) = (centre(self, &inside), centre(self, &outside)) else {return};
+
+		match inside.len(){
+			1 | 3 => {
+				let v:Vec<Option<u32>> = if inside.len() == 1{
+					outside.iter().map(|o| edge(self, inside[0], *o)).collect()
+				}
+				else{
+					inside.iter().map(|i| edge(self, *i, outside[0])).collect()
+				};
+				self.push_triangle([v[0],v[1],v[2]], ci, co);
+			}
+			_ => {
+				//quad between 2 inside and 2 outside
+				let a = edge(self, inside[0], outside[0]);
+				let b = edge(self, inside[0], outside[1]);
+				let c = edge(self, inside[1], outside[1]);
+				let d = edge(self, inside[1], outside[0]);
+				self.push_triangle([a,b,c], ci, co);
+				self.push_triangle([a,c,d], ci, co);
+			}
+		}
+	}
+
+
+	//march all cubes with min corner in ranges. value gives field and amplitude at grid coordinate.
+	fn march<V>(&mut self, min:GridCoord, max:GridCoord, level:f32, value:V)
+	where V: Fn(GridCoord) -> (f32,Amplitude){
+		for l in min.0..max.0{
+			for t in min.1..max.1{
+				for d in min.2..max.2{
+					let coords:Vec<GridCoord> = CORNERS.iter().map(|c| (l+c.0, t+c.1, d+c.2)).collect();
+					let samples:Vec<(f32,Amplitude)> = coords.iter().map(|c| value(*c)).collect();
+					if samples.iter().all(|s| s.0 >= level) || samples.iter().all(|s| s.0 < level){
+						continue;
+					}
+					for tet in TETRAHEDRA.iter(){
+						self.tetrahedron(
+							[coords[tet[0]], coords[tet[1]], coords[tet[2]], coords[tet[3]]],
+							[samples[tet[0]].0, samples[tet[1]].0, samples[tet[2]].0, samples[tet[3]].0],
+							[samples[tet[0]].1, samples[tet[1]].1, samples[tet[2]].1, samples[tet[3]].1],
+							level);
+					}
+				}
+			}
+		}
+	}
+}
+
+
+
+impl Mesh{
+
+
+	fn add_group<F>(&mut self, name:String, positions:&PositionData, z_model:&ZModel, build:F)
+	where F: FnOnce(&mut Marcher){
+		let first = self.triangles.len();
+		let mut marcher = Marcher{mesh: self, positions: positions, z_model: z_model, edge_vertices: HashMap::new()};
+		build(&mut marcher);
+		let count = self.triangles.len() - first;
+		if count > 0{
+			self.groups.push(MeshGroup{name: name, first_triangle: first, triangle_count: count});
+		}
+	}
+
+
+	//surface around the samples of each cluster. group per cluster.
+	pub fn from_clusters(clusters:&[Cluster], positions:&PositionData, z_model:&ZModel, size_threshold:usize) -> Mesh{
+		let mut mesh = Mesh{z_is_elevation: z_model.surface_level.is_some(), ..Default::default()};
+		for (i,cluster) in clusters.iter().enumerate(){
+			if cluster.volume() < size_threshold{
+				continue;
+			}
+			let mut samples:HashMap<GridCoord,Amplitude> = HashMap::with_capacity(cluster.volume());
+			let mut min = (i64::MAX,i64::MAX,i64::MAX);
+			let mut max = (i64::MIN,i64::MIN,i64::MIN);
+			for p in cluster.regions.iter().flat_map(|r| r.data.iter()){
+				let c = (p.x as i64, p.y as i64, p.z as i64);
+				samples.insert(c, p.v);
+				min = (min.0.min(c.0), min.1.min(c.1), min.2.min(c.2));
+				max = (max.0.max(c.0), max.1.max(c.1), max.2.max(c.2));
+			}
+			//1 sample border so surface closes. inside=1, outside=0
+			mesh.add_group(format!("cluster_{}", i), positions, z_model, |m| m.march(
+				(min.0-1, min.1-1, min.2-1), (max.0+1, max.1+1, max.2+1), 0.5,
+				|c| match samples.get(&c){
+					Some(v) => (1.0, *v),
+					None => (0.0, 0),
+				}));
+		}
+		return mesh;
+	}
+
+
+	//surface at amplitude = level through whole volume between depths.
+	pub fn from_amplitude_data(data:&AmplitudeData, level:Amplitude, min_depth:usize, max_depth:usize, z_model:&ZModel) -> Mesh{
+		let mut mesh = Mesh{z_is_elevation: z_model.surface_level.is_some(), ..Default::default()};
+		let positions = data.position_data();
+		let max_depth = max_depth.min(data.depth_size.saturating_sub(1)) as i64;
+		mesh.add_group("isosurface".to_string(), &positions, z_model, |m| m.march(
+			(-1, -1, min_depth as i64 - 1), (data.longitudinal_size as i64, data.transverse_size as i64, max_depth + 1), level as f32,
+			|c| {
+				if c.0 < 0 || c.1 < 0 || c.2 < min_depth as i64 || c.2 > max_depth{
+					return (f32::MIN, 0);
+				}
+				match data.get_value(c.0 as usize, c.1 as usize, c.2 as usize){
+					Some(v) => (v as f32, v),
+					None => (f32::MIN, 0),
+				}
+			}));
+		return mesh;
+	}
+
+
+	//rgb per vertex from amplitude
+	pub fn colors(&self) -> Result<Vec<[u8;3]>>{
+		let min = *self.amplitudes.iter().min().unwrap_or(&Amplitude::MIN) as f32;
+		let mut max = *self.amplitudes.iter().max().unwrap_or(&Amplitude::MAX) as f32;
+		if max <= min{
+			max = min + 1.0;
+		}
+		let gradient = colorgrad::GradientBuilder::new()
+			.html_colors(&["blue", "yellow", "red"])
+			.domain(&[min, max])
+			.build::<colorgrad::LinearGradient>()
+			.map_err(|e| GprError::config(format!("colour gradient: {}", e)))?;
+		return Ok(self.amplitudes.iter().map(|a| {
+			let c = gradient.at(*a as f32).to_rgba8();
+			[c[0], c[1], c[2]]
+		}).collect());
+	}
+
+
+	//writes by extension. .obj .ply or .glb
+	pub fn write(&self, output:&Path) -> Result<()>{
+		let result = match output.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase()).as_deref(){
+			Some("obj") => self.write_obj(output),
+			Some("ply") => self.write_ply(output),
+			Some("glb") => self.write_glb(output),
+			_ => return Err(GprError::config(format!("Unknown mesh format for {:?}. Use .obj, .ply or .glb", output))),
+		};
+		return result.in_file(output);
+	}
+
+
+	//vertex colours as extra v values (read by meshlab, blender...). group per cluster.
+	pub fn write_obj(&self, output:&Path) -> Result<()>{
+		let mut w = BufWriter::new(File::create(output)?);
+		let colors = self.colors()?;
+		for (v,c) in self.vertices.iter().zip(colors.iter()){
+			writeln!(w, "v {} {} {} {:.4} {:.4} {:.4}", v[0], v[1], v[2], c[0] as f32/255.0, c[1] as f32/255.0, c[2] as f32/255.0)?;
+		}
+		for g in self.groups.iter(){
+			writeln!(w, "g {}", g.name)?;
+			for t in self.triangles[g.first_triangle..g.first_triangle+g.triangle_count].iter(){
+				writeln!(w, "f {} {} {}", t[0]+1, t[1]+1, t[2]+1)?;
+			}
+		}
+		w.flush()?;
+		Ok(())
+	}
+
+
+	//ascii ply. face property group is index into groups.
+	pub fn write_ply(&self, output:&Path) -> Result<()>{
+		let mut w = BufWriter::new(File::create(output)?);
+		let colors = self.colors()?;
+		writeln!(w, "ply\nformat ascii 1.0")?;
+		for g in self.groups.iter(){
+			writeln!(w, "comment group {}", g.name)?;
+		}
+		writeln!(w, "element vertex {}", self.vertices.len())?;
+		writeln!(w, "property double x\nproperty double y\nproperty double z")?;
+		writeln!(w, "property uchar red\nproperty uchar green\nproperty uchar blue")?;
+		writeln!(w, "element face {}", self.triangles.len())?;
+		writeln!(w, "property list uchar int vertex_indices\nproperty int group\nend_header")?;
+		for (v,c) in self.vertices.iter().zip(colors.iter()){
+			writeln!(w, "{} {} {} {} {} {}", v[0], v[1], v[2], c[0], c[1], c[2])?;
+		}
+		for (i,g) in self.groups.iter().enumerate(){
+			for t in self.triangles[g.first_triangle..g.first_triangle+g.triangle_count].iter(){
+				writeln!(w, "3 {} {} {} {}", t[0], t[1], t[2], i)?;
+			}
+		}
+		w.flush()?;
+		Ok(())
+	}
+
+
+	/*
+	binary glTF. node and mesh per group sharing position and colour accessors.
+	glTF is y up with f32 positions so vertices are relative to origin node (translation is f64 in json).
+	*/
+	pub fn write_glb(&self, output:&Path) -> Result<()>{
+		if self.vertices.len() == 0{
+			return Err(GprError::geometry("empty mesh"));
+		}
+		let colors = self.colors()?;
+		let up = |v:&[f64;3]| if self.z_is_elevation {v[2]} else {-v[2]};
+		let origin = self.vertices[0];
+		let gltf_position = |v:&[f64;3]| [(v[0]-origin[0]) as f32, (up(v)-up(&origin)) as f32, -(v[1]-origin[1]) as f32];
+
+		let mut bin:Vec<u8> = Vec::new();
+		let mut min = [f32::MAX;3];
+		let mut max = [f32::MIN;3];
+		for v in self.vertices.iter(){
+			let p = gltf_position(v);
+			for k in 0..3{
+				min[k] = min[k].min(p[k]);
+				max[k] = max[k].max(p[k]);
+				bin.extend_from_slice(&p[k].to_le_bytes());
+			}
+		}
+		let colors_offset = bin.len();
+		for c in colors.iter(){
+			bin.extend_from_slice(&[c[0], c[1], c[2], 255]);
+		}
+		let indices_offset = bin.len();
+		for t in self.triangles.iter(){
+			for i in t.iter(){
+				bin.extend_from_slice(&i.to_le_bytes());
+			}
+		}
+
+		let mut accessors = vec![
+			json!({"bufferView":0, "componentType":5126, "count":self.vertices.len(), "type":"VEC3", "min":min, "max":max}),
+			json!({"bufferView":1, "componentType":5121, "normalized":true, "count":self.vertices.len(), "type":"VEC4"}),
+		];
+		let mut meshes = Vec::new();
+		let mut nodes = vec![json!({"name":"origin", "translation":[origin[0], up(&origin), -origin[1]], "children":(1..=self.groups.len()).collect::<Vec<usize>>()})];
+		for g in self.groups.iter(){
+			accessors.push(json!({"bufferView":2, "byteOffset":g.first_triangle*12, "componentType":5125, "count":g.triangle_count*3, "type":"SCALAR"}));
+			meshes.push(json!({"name":g.name, "primitives":[{"attributes":{"POSITION":0, "COLOR_0":1}, "indices":accessors.len()-1}]}));
+			nodes.push(json!({"name":g.name, "mesh":meshes.len()-1}));
+		}
+		let gltf = json!({
+			"asset":{"version":"2.0", "generator":"3d_gpr_workspace"},
+			"scene":0,
+			"scenes":[{"nodes":[0]}],
+			"nodes":nodes,
+			"meshes":meshes,
+			"accessors":accessors,
+			"bufferViews":[
+				{"buffer":0, "byteOffset":0, "byteLength":colors_offset, "byteStride":12, "target":34962},
+				{"buffer":0, "byteOffset":colors_offset, "byteLength":indices_offset-colors_offset, "byteStride":4, "target":34962},
+				{"buffer":0, "byteOffset":indices_offset, "byteLength":bin.len()-indices_offset, "target":34963},
+			],
+			"buffers":[{"byteLength":bin.len()}],
+		});
+
+		let mut json_chunk = serde_json::to_vec(&gltf)?;
+		while json_chunk.len() % 4 != 0{
+			json_chunk.push(b' ');
+		}
+		while bin.len() % 4 != 0{
+			bin.push(0);
+		}
+		let total = 12 + 8 + json_chunk.len() + 8 + bin.len();
+
+		let mut w = BufWriter::new(File::create(output)?);
+		w.write_all(b"glTF")?;
+		w.write_all(&2u32.to_le_bytes())?;
+		w.write_all(&(total as u32).to_le_bytes())?;
+		w.write_all(&(json_chunk.len() as u32).to_le_bytes())?;
+		w.write_all(b"JSON")?;
+		w.write_all(&json_chunk)?;
+		w.write_all(&(bin.len() as u32).to_le_bytes())?;
+		w.write_all(b"BIN\0")?;
+		w.write_all(&bin)?;
+		w.flush()?;
+		Ok(())
+	}
+
+}
+
+
+
+
+#[cfg(test)]
+mod mesh_tests{
+	use super::*;
+	use crate::clustering::Clusterer;
+
+	fn grid_positions(size:usize) -> PositionData{
+		let mut positions = PositionData::new(size,size);
+		for l in 0..size{
+			for t in 0..size{
+				positions.add_point(l,t,Point::new(l as f64,t as f64));
+			}
+		}
+		positions
+	}
+
+
+	//closed surface has every edge in exactly 2 triangles
+	#[test]
+	fn test_cluster_mesh_closed(){
+		let mut clusterer = Clusterer::new(1,1,1);
+		for l in 3..6{
+			for t in 3..5{
+				for d in 10..12{
+					clusterer.add_point(l,t,d,1000);
+				}
+			}
+		}
+		let mesh = Mesh::from_clusters(&clusterer.clusters(), &grid_positions(10), &ZModel::default(), 0);
+		assert_eq!(mesh.groups.len(), 1);
+		assert!(mesh.triangles.len() > 0);
+
+		let mut edges:HashMap<(u32,u32),usize> = HashMap::new();
+		for t in mesh.triangles.iter(){
+			for (a,b) in [(t[0],t[1]),(t[1],t[2]),(t[2],t[0])]{
+				*edges.entry((a.min(b),a.max(b))).or_insert(0) += 1;
+			}
+		}
+		assert!(edges.values().all(|n| *n == 2), "mesh not closed");
+	}
+
+
+	//corner without position is oriented from another corner of its tetrahedron, not the origin
+	#[test]
+	fn test_grid_position_missing(){
+		let mut positions = PositionData::new(3,3);
+		for (l,t) in [(1,1), (2,2)]{
+			positions.add_point(l,t,Point::new(l as f64,t as f64));
+		}
+		let (mut mesh, z_model) = (Mesh::default(), ZModel::default());
+		let marcher = Marcher{mesh: &mut mesh, positions: &positions, z_model: &z_model, edge_vertices: HashMap::new()};
+		let corners = [(1,2,0), (2,2,0), (1,2,1), (2,2,1)];
+		assert_eq!(marcher.grid_position((1,2,1), &corners), Some([2.0, 2.0, z_model.z(1.0)]));
+		assert_eq!(marcher.grid_position((1,1,0), &corners), Some([1.0, 1.0, z_model.z(0.0)]));
+		assert_eq!(marcher.grid_position((1,2,0), &[(1,2,0), (-1,0,0), (1,2,1), (5,5,0)]), None);
+	}
+
+
+	#[test]
+	fn test_write_glb(){
+		let mut clusterer = Clusterer::new(1,1,1);
+		clusterer.add_point(2,2,2,1000);
+		let mesh = Mesh::from_clusters(&clusterer.clusters(), &grid_positions(5), &ZModel::default(), 0);
+		let output = std::env::temp_dir().join("mesh_tests.glb");
+		mesh.write(&output).unwrap();
+		let bytes = std::fs::read(&output).unwrap();
+		assert_eq!(&bytes[0..4], b"glTF");
+		assert_eq!(u32::from_le_bytes([bytes[8],bytes[9],bytes[10],bytes[11]]) as usize, bytes.len());
+	}
+}
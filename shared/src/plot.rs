@@ -2,21 +2,25 @@
 
 
 use plotters::prelude::*;
-use colorgrad::{Gradient,LinearGradient};
-use std::error::Error;
 use crate::core::Amplitude;
+use crate::error::Result;
+use crate::colour_scale::{ColourScale,Colouring,COLOURBAR_WIDTH};
+use std::cmp::min;
+use std::ops::Range;
+use plotters::coord::Shift;
+use plotters::coord::types::RangedCoordf64;
 use ndarray::{Array2, Array};
 
 
 
 
 
-fn make_rectangle(gradient: &LinearGradient, x: usize , x_size:f64 , y: usize ,y_size:f64, value:i16) -> Rectangle<(f64, f64)> {
-	let c = gradient.at(value as f32).to_rgba8();
-	let color = RGBColor(c[0] , c[1] , c[2]);
-	println!("color:{:?}",color);
-
-	return Rectangle::new([(x as f64 * x_size, y as f64 * y_size), ((x + 1) as f64 * x_size, (y + 1) as f64 *y_size)] , color.filled());
+//how raster pixels between sample centres are coloured
+#[derive(Debug,Clone,Copy,PartialEq,Default)]
+pub enum Interpolation{
+	#[default]
+	Nearest,
+	Bilinear,//between 4 nearest samples. nearest where any are missing.
 }
 
 
@@ -57,23 +61,68 @@ impl PlotData{
 
 		
 		
-	pub fn plot(&self , file: &str) -> Result<(), Box<dyn Error>>{
+	/*
+	width x height RGB pixels covering amplitudes. row major from top left with y increasing down like plots.
+	missing samples are background.
+	*/
+	pub fn rasterise(&self, colouring:&Colouring, width:usize, height:usize, interpolation:Interpolation, background:RGBColor) -> Vec<u8>{
+		let (nx,ny) = (self.amplitudes.shape()[0], self.amplitudes.shape()[1]);
+		let mut pixels = Vec::with_capacity(width * height * 3);
+		if nx == 0 || ny == 0{
+			pixels.resize(width * height * 3, 0);
+			return pixels;
+		}
+		
+		//fractional sample index of pixel centres. sample i covers i..i+1
+		let xs:Vec<f32> = (0..width).map(|px| (px as f32 + 0.5) * nx as f32 / width as f32 - 0.5).collect();
+		let ys:Vec<f32> = (0..height).map(|py| (py as f32 + 0.5) * ny as f32 / height as f32 - 0.5).collect();
+		let nearest = |f:f32, n:usize| (f + 0.5).floor().clamp(0.0, (n - 1) as f32) as usize;
+		
+		for y in ys.iter(){
+			for x in xs.iter(){
+				let value = match interpolation{
+					Interpolation::Nearest => self.amplitudes[(nearest(*x,nx), nearest(*y,ny))].map(|v| v as f32),
+					Interpolation::Bilinear => self.bilinear(*x, *y).or_else(|| self.amplitudes[(nearest(*x,nx), nearest(*y,ny))].map(|v| v as f32)),
+				};
+				let c = value.map(|v| colouring.colour(v)).unwrap_or(background);
+				pixels.extend_from_slice(&[c.0, c.1, c.2]);
+			}
+		}
+		return pixels;
+	}
+	
+	
+	//None if any of 4 surrounding samples missing.
+	fn bilinear(&self, x:f32, y:f32) -> Option<f32>{
+		let (nx,ny) = (self.amplitudes.shape()[0], self.amplitudes.shape()[1]);
+		let x = x.clamp(0.0, (nx - 1) as f32);
+		let y = y.clamp(0.0, (ny - 1) as f32);
+		let (x0,y0) = (x.floor() as usize, y.floor() as usize);
+		let (x1,y1) = (min(x0 + 1, nx - 1), min(y0 + 1, ny - 1));
+		let (tx,ty) = (x - x0 as f32, y - y0 as f32);
+		let top = self.amplitudes[(x0,y0)]? as f32 * (1.0 - tx) + self.amplitudes[(x1,y0)]? as f32 * tx;
+		let bottom = self.amplitudes[(x0,y1)]? as f32 * (1.0 - tx) + self.amplitudes[(x1,y1)]? as f32 * tx;
+		return Some(top * (1.0 - ty) + bottom * ty);
+	}
+	
+	
+	//svg with colourbar at right
+	pub fn plot(&self , file: &str, scale:&ColourScale) -> Result<()>{
 		let min_x_val = self.min_x_index as f64 * self.x_scale;
 		let min_y_val = self.min_y_index as f64 * self.y_scale;
 		let max_x_val = (self.min_x_index+self.amplitudes.shape()[0]) as f64 * self.x_scale;
 		let max_y_val = (self.min_y_index+self.amplitudes.shape()[1]) as f64 * self.y_scale;
 
-		let gradient = colorgrad::GradientBuilder::new()
-		.html_colors(&["red", "blue"])
-		.domain(&[i16::MIN as f32, i16::MAX as f32])
-		.build::<colorgrad::LinearGradient>()?;
+		let colouring = scale.colouring(&self.amplitudes);
 		
 		let root_drawing_area = SVGBackend::new(file, (PIXELS, LINES))
 		.into_drawing_area();
 		
 		root_drawing_area.fill(&WHITE)?;
+		let (plot_area, colourbar_area) = root_drawing_area.split_horizontally(PIXELS - COLOURBAR_WIDTH);
+		colouring.draw_colourbar(&colourbar_area)?;
 
-		let mut chart = ChartBuilder::on(&root_drawing_area)
+		let mut chart = ChartBuilder::on(&plot_area)
 			.margin(5)
 			.x_label_area_size(40)
 			.y_label_area_size(60)
@@ -92,8 +141,7 @@ impl PlotData{
 		
 		for (ind,a) in self.amplitudes.indexed_iter(){
 				if let Some(v) = a{
-					let c = gradient.at(*v as f32).to_rgba8();
-					let color = RGBColor(c[0] , c[1] , c[2]);
+					let color = colouring.colour(*v as f32);
 					let x = ind.0;
 					let y = ind.1;
 					rects.push(
@@ -131,14 +179,335 @@ impl PlotData{
 
 
 
+/*
+data for line plots like A-scans. value along x with index down y like other plots.
+first series is main one and drawn thicker. reference lines are vertical at value like amplitude threshold.
+*/
+
+pub struct Series{
+	pub label: String,
+	pub values: Vec<Option<f64>>,//at min_y_index + i
+}
+
+
+pub struct LinePlotData{
+	pub min_y_index: usize,
+	pub y_size: usize,
+	pub marker_y: usize,
+	pub y_scale: f64,
+	pub x_label: String,
+	pub y_label: String,
+	pub series: Vec<Series>,
+	pub reference_lines: Vec<(String,f64)>,
+}
+
+
+//where plot went. pixels of plotting area and values at its edges. y_values from top to bottom.
+pub struct DrawnArea{
+	pub x_pixels: Range<i32>,
+	pub y_pixels: Range<i32>,
+	pub x_values: Range<f64>,
+	pub y_values: Range<f64>,
+}
+
+
+impl LinePlotData{
+	
+	//range of values with bit of space. symmetric when values either side of 0 like amplitudes.
+	fn x_range(&self) -> Range<f64>{
+		let values = self.series.iter().flat_map(|s| s.values.iter().filter_map(|v| *v)).chain(self.reference_lines.iter().map(|r| r.1));
+		let (min_x, max_x) = values.fold((0.0f64, 0.0f64), |(a,b), v| (a.min(v), b.max(v)));
+		let (min_x, max_x) = if min_x < 0.0 && max_x > 0.0 {(-max_x.max(-min_x), max_x.max(-min_x))} else {(min_x, max_x)};
+		if max_x <= min_x{
+			return min_x - 1.0 .. min_x + 1.0;
+		}
+		let space = (max_x - min_x) * 0.05;
+		return min_x - space .. max_x + space;
+	}
+	
+	
+	pub fn draw<DB:DrawingBackend>(&self, area:&DrawingArea<DB,Shift>) -> Result<DrawnArea> where DB::ErrorType: 'static{
+		let x_values = self.x_range();
+		let min_y_val = self.min_y_index as f64 * self.y_scale;
+		let max_y_val = (self.min_y_index + self.y_size) as f64 * self.y_scale;
+		
+		let mut chart = ChartBuilder::on(area)
+			.margin(5)
+			.x_label_area_size(40)
+			.y_label_area_size(60)
+			.build_cartesian_2d(x_values.clone(), max_y_val .. min_y_val)?;
+		
+		chart
+		.configure_mesh()
+		.y_desc(self.y_label.clone())
+		.x_desc(self.x_label.clone())
+		.disable_y_mesh()
+		.x_labels(5)
+		.label_style(("sans-serif", 20))
+		.draw()?;
+		
+		for (i,series) in self.series.iter().enumerate().rev(){
+			let colour = if i == 0 {BLACK.to_rgba()} else {Palette99::pick(i + 1).to_rgba()};
+			let style = colour.stroke_width(if i == 0 {2} else {1});
+			//separate lines where values missing
+			let mut lines:Vec<Vec<(f64,f64)>> = vec![Vec::new()];
+			for (j,v) in series.values.iter().enumerate(){
+				match v{
+					Some(v) => lines.last_mut().unwrap().push((*v, (self.min_y_index + j) as f64 * self.y_scale + self.y_scale / 2.0)),
+					None => lines.push(Vec::new()),
+				}
+			}
+			let mut first = true;
+			for line in lines.into_iter().filter(|l| !l.is_empty()){
+				let drawn = chart.draw_series(LineSeries::new(line, style))?;
+				if first{
+					drawn.label(series.label.clone()).legend(move |(x,y)| PathElement::new(vec![(x,y), (x + 20, y)], style));
+					first = false;
+				}
+			}
+		}
+		
+		for (label,value) in self.reference_lines.iter(){
+			chart.draw_series(DashedLineSeries::new(vec![(*value, min_y_val), (*value, max_y_val)], 10, 5, RED.stroke_width(2)))?
+			.label(label.clone()).legend(|(x,y)| PathElement::new(vec![(x,y), (x + 20, y)], RED));
+		}
+		
+		let marker_y = (self.marker_y as f64 + 0.5) * self.y_scale;
+		if marker_y > min_y_val && marker_y < max_y_val{
+			chart.draw_series(LineSeries::new(vec![(x_values.start, marker_y), (x_values.end, marker_y)], BLUE))?;
+		}
+		
+		chart.configure_series_labels()
+		.position(SeriesLabelPosition::UpperRight)
+		.background_style(WHITE.mix(0.8))
+		.border_style(BLACK)
+		.label_font(("sans-serif", 16))
+		.draw()?;
+		
+		let (x_pixels,y_pixels) = chart.plotting_area().get_pixel_range();
+		return Ok(DrawnArea{x_pixels: x_pixels, y_pixels: y_pixels, x_values: x_values, y_values: min_y_val .. max_y_val});
+	}
+	
+	
+	pub fn plot(&self, file:&str) -> Result<()>{
+		let root_drawing_area = SVGBackend::new(file, (LINES, PIXELS)).into_drawing_area();
+		root_drawing_area.fill(&WHITE)?;
+		self.draw(&root_drawing_area)?;
+		root_drawing_area.present()?;
+		return Ok(());
+	}
+}
+
+
+
+
+
+/*
+map of trace positions in real world coordinates. north up with equal scales.
+each trace is square of point_size coloured by value, grey without colouring or value.
+features are shaded in colour of group like overlays. outlines are of loaded feature files, single point for point features.
+sections are lines through marker like cross hairs.
+footprint is everything but sections and marker so it can be drawn once and position drawn over copies of it.
+*/
+
+pub struct MapPlotData{
+	pub points: Vec<([f64;2],Option<Amplitude>)>,
+	pub point_size: f64,
+	pub sections: Vec<Vec<[f64;2]>>,
+	pub marker: Option<[f64;2]>,
+	pub features: Vec<([f64;2],usize)>,//position, group
+	pub outlines: Vec<(Vec<[f64;2]>,usize)>,//ring, group
+	pub x_label: String,
+	pub y_label: String,
+}
+
+
+type MapChart<'a,DB> = ChartContext<'a,DB,Cartesian2d<RangedCoordf64,RangedCoordf64>>;
+
+
+impl MapPlotData{
+	
+	//bounds of points with half a point of space, widened to aspect of width x height pixels.
+	fn ranges(&self, width:f64, height:f64) -> (Range<f64>,Range<f64>){
+		let half = self.point_size / 2.0;
+		let (mut min_x, mut max_x, mut min_y, mut max_y) = (f64::MAX, f64::MIN, f64::MAX, f64::MIN);
+		for (p,_) in self.points.iter(){
+			min_x = min_x.min(p[0] - half);
+			max_x = max_x.max(p[0] + half);
+			min_y = min_y.min(p[1] - half);
+			max_y = max_y.max(p[1] + half);
+		}
+		if self.points.is_empty(){
+			return (0.0 .. 1.0, 0.0 .. 1.0);
+		}
+		let per_pixel = ((max_x - min_x) / width).max((max_y - min_y) / height).max(f64::EPSILON);
+		let (centre_x, centre_y) = ((min_x + max_x) / 2.0, (min_y + max_y) / 2.0);
+		let (half_width, half_height) = (per_pixel * width / 2.0, per_pixel * height / 2.0);
+		return (centre_x - half_width .. centre_x + half_width, centre_y - half_height .. centre_y + half_height);
+	}
+	
+	
+	//same plotting area and ranges for same size of area and points
+	fn chart<'a,DB:DrawingBackend>(&self, area:&'a DrawingArea<DB,Shift>) -> Result<MapChart<'a,DB>> where DB::ErrorType: 'static{
+		//plotting area is area less margins and label areas
+		let (width,height) = area.dim_in_pixel();
+		let (x_values,y_values) = self.ranges((width as f64 - 70.0).max(1.0), (height as f64 - 50.0).max(1.0));
+		let chart = ChartBuilder::on(area)
+			.margin(5)
+			.x_label_area_size(40)
+			.y_label_area_size(60)
+			.build_cartesian_2d(x_values, y_values)?;
+		return Ok(chart);
+	}
+	
+	
+	/*
+	width x height RGB pixels of traces and features over x_values by y_values. row major from top left.
+	pixels with centres in square of trace take its colour. at least 1 pixel so traces still show when zoomed out.
+	*/
+	fn rasterise(&self, colouring:Option<&Colouring>, width:usize, height:usize, x_values:&Range<f64>, y_values:&Range<f64>, background:RGBColor) -> Vec<u8>{
+		let mut pixels = [background.0, background.1, background.2].repeat(width * height);
+		if width == 0 || height == 0{
+			return pixels;
+		}
+		let x_per = (x_values.end - x_values.start) / width as f64;
+		let y_per = (y_values.end - y_values.start) / height as f64;
+		let half = self.point_size / 2.0;
+		//pixels from distances low..high of edge of raster
+		let span = |low:f64, high:f64, per:f64, n:usize| {
+			if high <= 0.0{
+				return 0..0;
+			}
+			let start = ((low / per - 0.5).ceil().max(0.0) as usize).min(n);
+			let end = ((high / per - 0.5).ceil().max(0.0) as usize).max(start + 1).min(n);
+			return start..end;
+		};
+		let mut fill = |p:&[f64;2], colour:RGBColor, alpha:f64| {
+			let xs = span(p[0] - half - x_values.start, p[0] + half - x_values.start, x_per, width);
+			let ys = span(y_values.end - p[1] - half, y_values.end - p[1] + half, y_per, height);
+			for y in ys{
+				for x in xs.clone(){
+					let i = (y * width + x) * 3;
+					for (c,v) in pixels[i..i + 3].iter_mut().zip([colour.0, colour.1, colour.2]){
+						*c = (v as f64 * alpha + *c as f64 * (1.0 - alpha)).round() as u8;
+					}
+				}
+			}
+		};
+		
+		let grey = RGBColor(200,200,200);
+		for (p,v) in self.points.iter(){
+			let colour = match (colouring, v){
+				(Some(colouring), Some(v)) => colouring.colour(*v as f32),
+				_ => grey,
+			};
+			fill(p, colour, 1.0);
+		}
+		for (p,group) in self.features.iter(){
+			let (r,g,b) = Palette99::pick(*group).rgb();
+			fill(p, RGBColor(r,g,b), 0.6);
+		}
+		return pixels;
+	}
+	
+	
+	//axes, traces, features and outlines
+	pub fn draw_footprint<DB:DrawingBackend>(&self, area:&DrawingArea<DB,Shift>, colouring:Option<&Colouring>) -> Result<DrawnArea> where DB::ErrorType: 'static{
+		let mut chart = self.chart(area)?;
+		chart
+		.configure_mesh()
+		.y_desc(self.y_label.clone())
+		.x_desc(self.x_label.clone())
+		.disable_x_mesh()
+		.disable_y_mesh()
+		.x_labels(4)
+		.y_labels(4)
+		.label_style(("sans-serif", 20))
+		.draw()?;
+		
+		let (x_values,y_values) = (chart.x_range(), chart.y_range());
+		let (x_pixels,y_pixels) = chart.plotting_area().get_pixel_range();
+		let size = ((x_pixels.end - x_pixels.start).max(0) as u32, (y_pixels.end - y_pixels.start).max(0) as u32);
+		let raster = self.rasterise(colouring, size.0 as usize, size.1 as usize, &x_values, &y_values, WHITE);
+		if let Some(element) = BitMapElement::with_owned_buffer((x_values.start, y_values.end), size, raster){
+			chart.draw_series(std::iter::once(element))?;
+		}
+		
+		for (ring,group) in self.outlines.iter(){
+			let style = Palette99::pick(*group).stroke_width(2);
+			match ring.as_slice(){
+				[p] => {chart.draw_series(std::iter::once(Circle::new((p[0], p[1]), 4, style)))?;}
+				_ => {chart.draw_series(std::iter::once(PathElement::new(ring.iter().map(|p| (p[0], p[1])).collect::<Vec<(f64,f64)>>(), style)))?;}
+			}
+		}
+		
+		return Ok(DrawnArea{x_pixels: x_pixels, y_pixels: y_pixels, x_values: x_values, y_values: y_values.end .. y_values.start});
+	}
+	
+	
+	//sections and marker. over footprint drawn on area of same size.
+	pub fn draw_position<DB:DrawingBackend>(&self, area:&DrawingArea<DB,Shift>) -> Result<()> where DB::ErrorType: 'static{
+		let mut chart = self.chart(area)?;
+		for section in self.sections.iter(){
+			chart.draw_series(LineSeries::new(section.iter().map(|p| (p[0], p[1])), BLUE))?;
+		}
+		if let Some(p) = self.marker{
+			chart.draw_series(std::iter::once(Circle::new((p[0], p[1]), 6, BLUE.stroke_width(2))))?;
+		}
+		return Ok(());
+	}
+}
+
+
+
 #[cfg(test)]
 mod plot_tests{
 	use super::*;
+	use crate::colour_scale::{Colormap,Clip};
 	const X_SPACING: f64 = 0.072;
 	const Y_SPACING: f64 = 0.0762;
 	const Z_SPACING:f64 = 0.01309289678;// TIME_INTERVAL*C/EPSILON.sqrt();
 
 
+	#[test]
+	fn test_rasterise(){
+		let pd = PlotData{min_x_index: 0, min_y_index: 0, marker_x: 0, marker_y: 0, x_scale: 1.0, y_scale: 1.0,
+			x_label: "x".to_string(), y_label: "y".to_string(),
+			amplitudes: Array::from_shape_vec((3,1), vec![Some(0), Some(100), None]).unwrap()};
+		let colouring = ColourScale{colormap: Colormap::Greyscale, clip: Clip::Range{min: 0.0, max: 100.0}, symmetric: false, gamma: 1.0}.colouring(&pd.amplitudes);
+		let rgb = |c:RGBColor| vec![c.0, c.1, c.2];
+		let background = RGBColor(1,2,3);
+		
+		let pixels = pd.rasterise(&colouring, 6, 1, Interpolation::Nearest, background);
+		assert_eq!(pixels.len(), 18);
+		assert_eq!(pixels[0..3], rgb(colouring.colour(0.0))[..]);
+		assert_eq!(pixels[6..9], rgb(colouring.colour(100.0))[..]);
+		assert_eq!(pixels[15..18], rgb(background)[..]);
+		
+		//pixel 1 centre is 0.25 of way from sample 0 to 1
+		let pixels = pd.rasterise(&colouring, 6, 1, Interpolation::Bilinear, background);
+		assert_eq!(pixels[3..6], rgb(colouring.colour(25.0))[..]);
+		assert_eq!(pixels[15..18], rgb(background)[..]);
+	}
+
+	
+	#[test]
+	fn test_map_rasterise(){
+		let map = MapPlotData{points: vec![([0.0, 0.0], Some(0)), ([2.0, 0.0], None)], point_size: 1.0, sections: Vec::new(), marker: None,
+			features: vec![([2.0, 0.0], 0)], outlines: Vec::new(), x_label: "x".to_string(), y_label: "y".to_string()};
+		let colouring = ColourScale{colormap: Colormap::Greyscale, clip: Clip::Range{min: 0.0, max: 100.0}, symmetric: false, gamma: 1.0}.colouring(&Array2::from_elem((1,1), Some(0)));
+		let pixels = map.rasterise(Some(&colouring), 6, 2, &(-0.5 .. 2.5), &(-0.5 .. 0.5), WHITE);
+		let (r,g,b) = Palette99::pick(0).rgb();
+		let blend = |c:u8| (c as f64 * 0.6 + 200.0 * 0.4).round() as u8;
+		//2 pixels per metre. both rows same.
+		for row in [0, 18]{
+			assert_eq!(pixels[row..row + 6], [colouring.colour(0.0).0, colouring.colour(0.0).1, colouring.colour(0.0).2].repeat(2)[..]);
+			assert_eq!(pixels[row + 6..row + 12], [255; 6]);
+			assert_eq!(pixels[row + 12..row + 18], [blend(r), blend(g), blend(b)].repeat(2)[..]);
+		}
+	}
+
+
 	/*
 	#[test]
 	fn test_plot(){
@@ -155,7 +524,7 @@ mod plot_tests{
 		amplitudes: vec![vec![Some(0),Some(1000),Some(2000),Some(3000)],vec![Some(3000),Some(4000),Some(5000),None]],
 		};
 		
-		let _ = pd.plot(image);
+		let _ = pd.plot(image, &ColourScale::default());
 
 		
 	}
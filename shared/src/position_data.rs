@@ -1,5 +1,4 @@
 
-use std::error::Error;
 use geo::{MultiPoint,Point,ConvexHull,Polygon,CoordsIter,LineString,Coord,MultiPolygon};
 use csv::Writer;
 use serde::{Deserialize, Serialize};
@@ -7,14 +6,18 @@ use std::path::Path;
 use std::io::{stdout,Write};
 use wkt::ToWkt;
 use crate::core::Amplitude;
-use anyhow::{Result,bail};
+use crate::error::{Result,GprError,InFile};
 use crate::core::{usize_last_multiple,usize_next_multiple};
 
 use crate::clustering::{Cluster,GRID_SIZE,Region};
+use crate::geometry_z::{ZModel,multipoint_z_wkt,multipolygon_z_wkt,polyhedral_surface_wkt};
+use crate::chainage::Centreline;
+use crate::progress::Progress;
+use crate::geojson::{write_feature_collection,read_feature_collection,is_geojson};
 
 
 
-#[derive(Serialize,Deserialize,Debug)]
+#[derive(Serialize,Deserialize,Debug,Clone)]
 pub struct Layer{
 	pub min_depth:usize,
 	pub max_depth:usize,
@@ -22,23 +25,93 @@ pub struct Layer{
 }
 
 
+//depth bands used by extractor. json list of Layer.
+pub const DEFAULT_LAYERS: &str = "[{\"min_depth\":51,\"max_depth\":53,\"label\":\"0-50mm\"},
+	{\"min_depth\":54,\"max_depth\":57,\"label\":\"50-100mm\"},
+	{\"min_depth\":58,\"max_depth\":61,\"label\":\"100-150mm\"},
+	{\"min_depth\":62,\"max_depth\":65,\"label\":\"150-200mm\"},
+	{\"min_depth\":66,\"max_depth\":69,\"label\":\"200-250mm\"},
+	{\"min_depth\":70,\"max_depth\":73,\"label\":\"250-300mm\"},
+	{\"min_depth\":74,\"max_depth\":77,\"label\":\"300-350mm\"},
+	{\"min_depth\":82,\"max_depth\":85,\"label\":\"350-400mm\"},
+	{\"min_depth\":86,\"max_depth\":89,\"label\":\"400-450mm\"},
+	{\"min_depth\":90,\"max_depth\":93,\"label\":\"450-500mm\"}]
+	";
 
 
-#[derive(Serialize,Debug)]
+impl Layer{
+	pub fn defaults() -> Vec<Layer>{
+		return serde_json::from_str(DEFAULT_LAYERS).expect("DEFAULT_LAYERS is valid json");
+	}
+}
+
+
+
+
+#[derive(Serialize,Deserialize,Debug,Clone)]
 pub struct ClusterFeature{
-	depth_band:String,
-	mean_amplitude: Amplitude,
-	wkt: String,
+	pub depth_band:String,
+	pub mean_amplitude: Amplitude,
+	pub start_chainage: Option<f64>,
+	pub end_chainage: Option<f64>,
+	pub min_offset: Option<f64>,
+	pub max_offset: Option<f64>,
+	pub wkt: String,
 }
 
 impl ClusterFeature{
-	pub fn write_csv(features:Vec<ClusterFeature>, output:&str) -> Result<(), Box<dyn Error>>{
-		let mut wtr = Writer::from_path(output)?;
+
+	//chainage and offset range of points
+	fn set_chainage(&mut self, centreline:&Centreline, points:impl Iterator<Item = Point>){
+		for p in points{
+			let (chainage,offset) = centreline.locate(p);
+			self.start_chainage = Some(self.start_chainage.map_or(chainage, |c| c.min(chainage)));
+			self.end_chainage = Some(self.end_chainage.map_or(chainage, |c| c.max(chainage)));
+			self.min_offset = Some(self.min_offset.map_or(offset, |o| o.min(offset)));
+			self.max_offset = Some(self.max_offset.map_or(offset, |o| o.max(offset)));
+		}
+	}
+
+
+	pub fn write_csv(features:Vec<ClusterFeature>, output:&str) -> Result<()>{
+		let file = Path::new(output);
+		let mut wtr = Writer::from_path(file).in_file(file)?;
 		for f in features{
-			let _ = wtr.serialize(f);
+			wtr.serialize(f).in_file(file)?;
 		}
+		wtr.flush().in_file(file)?;
 		Ok(())
 	}
+
+
+	pub fn read_csv(input:&str) -> Result<Vec<ClusterFeature>>{
+		let file = Path::new(input);
+		let mut reader = csv::Reader::from_path(file).in_file(file)?;
+		let mut features = Vec::new();
+		for f in reader.deserialize(){
+			features.push(f.in_file(file)?);
+		}
+		Ok(features)
+	}
+
+
+	pub fn write_geojson(features:&[ClusterFeature], output:&str, metadata:Option<&serde_json::Value>) -> Result<()>{
+		write_feature_collection(features, output, metadata)
+	}
+
+
+	pub fn read_geojson(input:&str) -> Result<Vec<ClusterFeature>>{
+		read_feature_collection(input)
+	}
+
+
+	//GeoJSON for .geojson or .json. csv otherwise.
+	pub fn read(input:&str) -> Result<Vec<ClusterFeature>>{
+		if is_geojson(input){
+			return ClusterFeature::read_geojson(input);
+		}
+		return ClusterFeature::read_csv(input);
+	}
 }
 
 
@@ -50,7 +123,8 @@ struct Position{
 	longitudinal:usize,
 	transverse:usize,
 	x:f64,
-	y:f64
+	y:f64,
+	chainage:Option<f64>,
 }
 
 //ok for slice up to at least 2^48 i16.
@@ -68,14 +142,23 @@ fn mean(numbers: &[Amplitude]) -> Amplitude {
 
 
 
+//examiner writes 0,0 for traces without GPS. counts as no position everywhere.
+pub fn is_known_position(p:&Point) -> bool{
+	!(p.x() == 0.0 && p.y() == 0.0)
+}
+
+
+
 pub struct PositionData{
-	data: Vec<Vec<Option<Point>>>//L,T
+	data: Vec<Vec<Option<Point>>>,//L,T
+	route: Option<Centreline>,//centreline for chainage. from trace positions when None.
+	start_chainage: f64,
 }
 
 impl PositionData{
 
 	pub fn get_point(&self, longitudinal: usize, transverse: usize) -> Option<Point>{
-		*self.data.get(longitudinal)?.get(transverse)?
+		self.data.get(longitudinal)?.get(transverse)?.filter(is_known_position)
 		}
 
 
@@ -86,23 +169,61 @@ impl PositionData{
 
 
 
+	//(longitudinal,transverse,point) of every known point
+	pub fn points(&self) -> impl Iterator<Item = (usize,usize,Point)> + '_{
+		self.data.iter().enumerate().flat_map(|(lon,col)| col.iter().enumerate().filter_map(move |(tr,p)| p.filter(is_known_position).map(|pt| (lon,tr,pt))))
+	}
+
+
 	pub fn new(longitudinal_size: usize , transverse_size: usize ) -> PositionData{
-		PositionData{data: vec![vec![None;transverse_size];longitudinal_size]}
+		PositionData{data: vec![vec![None;transverse_size];longitudinal_size], route: None, start_chainage: 0.0}
+	}
+
+
+	pub fn longitudinal_size(&self) -> usize{
+		self.data.len()
 	}
 
 
+	pub fn transverse_size(&self) -> usize{
+		self.data.first().map(|col| col.len()).unwrap_or(0)
+	}
+
+
+	//chainage of 1st trace when centreline comes from trace positions
+	pub fn set_start_chainage(&mut self, start_chainage:f64){
+		self.start_chainage = start_chainage;
+	}
+
+
+	pub fn set_route(&mut self, route:Centreline){
+		self.route = Some(route);
+	}
+
+
+	//route if set. else line along middle of traces.
+	pub fn centreline(&self) -> Result<Centreline>{
+		match &self.route{
+			Some(route) => Ok(route.clone()),
+			None => Centreline::from_positions(self, self.start_chainage),
+		}
+	}
 
-	pub fn write_csv(&self, output:&Path) -> Result<(), Box<dyn Error>>{
-		let mut wtr = Writer::from_path(output)?;
+
+
+	pub fn write_csv(&self, output:&Path) -> Result<()>{
+		let mut wtr = Writer::from_path(output).in_file(output)?;
+		let centreline = self.centreline()?;
 	//	wtr.write_record(&["longitudinal", "transverse", "x","y"])?;
 		for (lon,col) in self.data.iter().enumerate(){
 			for (tr,v) in col.iter().enumerate(){
 				if let Some(p) = v{
-					wtr.serialize(Position{longitudinal:lon,transverse:tr,x:p.x(),y:p.y()})?;
+					let chainage = Some(centreline.locate(*p).0);
+					wtr.serialize(Position{longitudinal:lon,transverse:tr,x:p.x(),y:p.y(),chainage:chainage}).in_file(output)?;
 				}
 			}
 		}
-		wtr.flush()?;
+		wtr.flush().in_file(output)?;
 		Ok(())
 	}
 	
@@ -152,7 +273,7 @@ impl PositionData{
 			}
 		}
 		if polygons.len() == 0{
-			bail!("no polygons");
+			return Err(GprError::geometry("no polygons"));
 		}
 		
 		return Ok(MultiPolygon::new(polygons));
@@ -173,7 +294,7 @@ impl PositionData{
 			points.append(&mut reg_points);
 		}
 		if points.len() == 0 {
-			bail!("no points");
+			return Err(GprError::geometry("no points"));
 		}
 		return Ok(MultiPoint::new(points));
 
@@ -181,62 +302,96 @@ impl PositionData{
 	}
 	
 	
-	pub fn multipolygon_features_from_clusters(&self , clusters:Vec<Cluster> , size_threshold:usize , layers:Vec<Layer>) -> Vec<ClusterFeature> {
-		let mut features: Vec<ClusterFeature> = Vec::new();
-		let count = clusters.len();
-		//println!("extracting multipolygons from cluster:");	
-		eprint!("\rextracting multipolygons from cluster");
+	//positions of cluster samples between depths. repeated for samples at same x,y.
+	fn cluster_points<'a>(&'a self , cluster:&'a Cluster, min_depth:usize , max_depth:usize) -> impl Iterator<Item = Point> + 'a{
+		cluster.regions.iter()
+			.flat_map(|r| r.data.iter())
+			.filter(move |p| p.z >= min_depth && p.z <= max_depth)
+			.filter_map(|p| self.get_point(p.x , p.y))
+	}
 
-		
-		for (i,cluster) in clusters.iter().enumerate(){
-			if i % 100 == 0{
-				eprint!("\rextracting multipolygons from cluster: {:?} of {:?}" , i , count);
-				//stdout().flush();
-			}
-			if cluster.volume() >= size_threshold{
-				for layer in layers.iter(){
-					if let Ok(geom) = self.multipolygon_from_cluster(cluster,layer.min_depth,layer.max_depth){
-						features.push(
-							ClusterFeature{
-								wkt: geom.wkt_string(),
-								depth_band: layer.label.clone(),
-								mean_amplitude: mean(&cluster.values_between(layer.min_depth,layer.max_depth)),
-							}
-						)
-					}
-				}
-			}
-		}
-		eprint!("\rextracting multipolygons from cluster: done                                         \n");
 
-		return features;
+	//points with Z from depth of each sample
+	fn multipoint_z_from_cluster(&self , cluster:&Cluster, min_depth:usize , max_depth:usize, z_model:&ZModel) -> Result<String>{
+		let points:Vec<(f64,f64,f64)> = cluster.regions.iter()
+			.flat_map(|r| r.data.iter())
+			.filter(|p| p.z >= min_depth && p.z <= max_depth)
+			.filter_map(|p| self.get_point(p.x , p.y).map(|pt| (pt.x() , pt.y() , z_model.z(p.z as f64))))
+			.collect();
+		if points.len() == 0 {
+			return Err(GprError::geometry("no points"));
+		}
+		return Ok(multipoint_z_wkt(&points));
 	}
-	
-	pub fn multipoint_features_from_clusters(&self , clusters:Vec<Cluster> , size_threshold:usize , layers:Vec<Layer>) -> Vec<ClusterFeature> {
+
+
+	//calls geometry for every layer of every cluster >= size_threshold
+	fn features_from_clusters<F>(&self , clusters:Vec<Cluster> , size_threshold:usize , layers:Vec<Layer> , description:&str , progress:&dyn Progress , geometry:F) -> Result<Vec<ClusterFeature>>
+	where F: Fn(&Cluster , &Layer) -> Result<String>{
 		let mut features: Vec<ClusterFeature> = Vec::new();
 		let count = clusters.len();
-		//println!("extracting multipoints from clusters");			
+		let centreline = self.centreline()?;
+		let stage = format!("Extracting {}", description);
+
 		for (i,cluster) in clusters.iter().enumerate(){
 			if i % 100 == 0{
-				println!("extracting multipoints from cluster {:?} of {:?}" , i , count);
-				//stdout().flush();
+				progress.update(&stage, i, Some(count));
+				progress.check()?;
 			}
 			if cluster.volume() >= size_threshold{
 				for layer in layers.iter(){
-					if let Ok(geom) = self.multipoint_from_cluster(cluster,layer.min_depth,layer.max_depth){
-						features.push(
-							ClusterFeature{
-								wkt: geom.wkt_string(),
-								depth_band: layer.label.clone(),
-								mean_amplitude: mean(&cluster.values_between(layer.min_depth,layer.max_depth)),
-							}
-						)
+					if let Ok(wkt) = geometry(cluster , layer){
+						let mut feature = ClusterFeature{
+							wkt: wkt,
+							depth_band: layer.label.clone(),
+							mean_amplitude: mean(&cluster.values_between(layer.min_depth,layer.max_depth)),
+							start_chainage: None,
+							end_chainage: None,
+							min_offset: None,
+							max_offset: None,
+						};
+						feature.set_chainage(&centreline, self.cluster_points(cluster, layer.min_depth, layer.max_depth));
+						features.push(feature);
 					}
 				}
 			}
 		}
-		return features;
-	}	
+		progress.update(&stage, count, Some(count));
+		return Ok(features);
+	}
+
+
+	//MultiPoint Z with a point per sample
+	pub fn multipoint_z_features_from_clusters(&self , clusters:Vec<Cluster> , size_threshold:usize , layers:Vec<Layer> , z_model:&ZModel , progress:&dyn Progress) -> Result<Vec<ClusterFeature>> {
+		self.features_from_clusters(clusters , size_threshold , layers , "multipoint Z" , progress ,
+			|cluster , layer| self.multipoint_z_from_cluster(cluster , layer.min_depth , layer.max_depth , z_model))
+	}
+
+
+	//MultiPolygon Z at top of each layer. layers stack up into a 3D shape.
+	pub fn multipolygon_z_features_from_clusters(&self , clusters:Vec<Cluster> , size_threshold:usize , layers:Vec<Layer> , z_model:&ZModel , progress:&dyn Progress) -> Result<Vec<ClusterFeature>> {
+		self.features_from_clusters(clusters , size_threshold , layers , "multipolygon Z" , progress ,
+			|cluster , layer| Ok(multipolygon_z_wkt(&self.multipolygon_from_cluster(cluster , layer.min_depth , layer.max_depth)? , z_model.z(layer.min_depth as f64))))
+	}
+
+
+	//closed prisms from top to bottom of each layer.
+	pub fn polyhedral_features_from_clusters(&self , clusters:Vec<Cluster> , size_threshold:usize , layers:Vec<Layer> , z_model:&ZModel , progress:&dyn Progress) -> Result<Vec<ClusterFeature>> {
+		self.features_from_clusters(clusters , size_threshold , layers , "polyhedral surfaces" , progress ,
+			|cluster , layer| Ok(polyhedral_surface_wkt(&self.multipolygon_from_cluster(cluster , layer.min_depth , layer.max_depth)? , z_model.z(layer.min_depth as f64) , z_model.z((layer.max_depth + 1) as f64))))
+	}
+
+
+	pub fn multipolygon_features_from_clusters(&self , clusters:Vec<Cluster> , size_threshold:usize , layers:Vec<Layer> , progress:&dyn Progress) -> Result<Vec<ClusterFeature>> {
+		self.features_from_clusters(clusters , size_threshold , layers , "multipolygons" , progress ,
+			|cluster , layer| Ok(self.multipolygon_from_cluster(cluster , layer.min_depth , layer.max_depth)?.wkt_string()))
+	}
+
+
+	pub fn multipoint_features_from_clusters(&self , clusters:Vec<Cluster> , size_threshold:usize , layers:Vec<Layer> , progress:&dyn Progress) -> Result<Vec<ClusterFeature>> {
+		self.features_from_clusters(clusters , size_threshold , layers , "multipoints" , progress ,
+			|cluster , layer| Ok(self.multipoint_from_cluster(cluster , layer.min_depth , layer.max_depth)?.wkt_string()))
+	}
 	
 	
 	
@@ -247,6 +402,8 @@ impl PositionData{
 #[cfg(test)]
 mod position_data_tests{
 	use super::*;
+	use crate::clustering::Clusterer;
+	use crate::progress::NoProgress;
 	
 
 	#[test]
@@ -256,6 +413,36 @@ mod position_data_tests{
 		data.add_point(2,2,p);
 		data.get_point(2,2).unwrap();
 	}
+
+
+	#[test]
+	fn multipoint_z_features(){
+		let mut data = PositionData::new(5,5);
+		for lon in 0..5{
+			for tr in 0..5{
+				data.add_point(lon,tr,Point::new(100.0 + lon as f64,200.0 + tr as f64));
+			}
+		}
+		let mut clusterer = Clusterer::new(1,1,1);
+		clusterer.add_point(1,1,20,100);
+		clusterer.add_point(2,1,21,200);
+		let layers = vec![Layer{min_depth:20,max_depth:21,label:"a".to_string()}];
+		let z_model = ZModel{zero_depth:20, z_spacing:0.5, surface_level:None};
+		let features = data.multipoint_z_features_from_clusters(clusterer.clusters(),0,layers,&z_model,&NoProgress).unwrap();
+		assert_eq!(features.len(),1);
+		assert_eq!(features[0].wkt,"MULTIPOINT Z ((101 201 0),(102 201 0.5))");
+		assert_eq!(features[0].mean_amplitude,150);
+		assert_eq!((features[0].start_chainage,features[0].end_chainage),(Some(1.0),Some(2.0)));
+		assert_eq!((features[0].min_offset,features[0].max_offset),(Some(-1.0),Some(-1.0)),"right of centreline");
+		
+		//one scan has no centreline to give chainage
+		let mut single = PositionData::new(1,5);
+		for tr in 0..5{
+			single.add_point(0,tr,Point::new(100.0,200.0 + tr as f64));
+		}
+		let layers = vec![Layer{min_depth:20,max_depth:21,label:"a".to_string()}];
+		assert!(matches!(single.multipoint_z_features_from_clusters(Vec::new(),0,layers,&z_model,&NoProgress), Err(GprError::Geometry(_))));
+	}
 	
 	
 }
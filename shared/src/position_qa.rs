@@ -0,0 +1,303 @@
+use std::path::Path;
+use csv::Writer;
+use geo::Point;
+use serde::{Serialize,Deserialize};
+use crate::error::{Result,InFile};
+use crate::position_data::PositionData;
+use crate::amplitude_data::X_SPACING;
+
+
+/*
+quality checks on trace positions. each channel is checked along longitudinal axis.
+
+missing: no position or 0,0
+duplicate: same place as previous good position. GPS or odometer stalled.
+outlier: further than tolerance*expected_spacing from where neighbours say it should be.
+	predicted from pairs of neighbours at +-1..window. median of predictions so one bad neighbour doesn't matter.
+expected_spacing comes from the data with for_positions or is given.
+*/
+
+
+#[derive(Serialize,Deserialize,Debug,Clone,Copy,PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PositionIssueKind{
+	Missing,
+	Duplicate,
+	Outlier,
+}
+
+
+#[derive(Serialize,Debug,Clone,PartialEq)]
+pub struct PositionIssue{
+	pub longitudinal: usize,
+	pub transverse: usize,
+	pub kind: PositionIssueKind,
+	pub deviation: Option<f64>,//meters from predicted position or previous position
+}
+
+
+#[derive(Debug,Clone,Copy,PartialEq,Serialize,Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RepairMethod{
+	Interpolate,//linear between nearest good positions on channel
+	Smooth,//interpolate then moving average
+}
+
+
+#[derive(Debug,Clone,Default)]
+pub struct PositionReport{
+	pub issues: Vec<PositionIssue>,
+}
+
+
+impl PositionReport{
+
+	pub fn count(&self, kind:PositionIssueKind) -> usize{
+		self.issues.iter().filter(|i| i.kind == kind).count()
+	}
+
+
+	pub fn summary(&self) -> String{
+		format!("missing:{} duplicate:{} outlier:{}", self.count(PositionIssueKind::Missing), self.count(PositionIssueKind::Duplicate), self.count(PositionIssueKind::Outlier))
+	}
+
+
+	pub fn write_csv(&self, output:&Path) -> Result<()>{
+		let mut wtr = Writer::from_path(output).in_file(output)?;
+		for i in self.issues.iter(){
+			wtr.serialize(i).in_file(output)?;
+		}
+		wtr.flush().in_file(output)?;
+		Ok(())
+	}
+}
+
+
+
+#[derive(Debug,Clone,Copy)]
+pub struct PositionQa{
+	pub expected_spacing: f64,//meters between consecutive traces on channel
+	pub tolerance: f64,//outlier when further than tolerance*expected_spacing from predicted position
+	pub duplicate_distance: f64,//meters
+	pub window: usize,//neighbours either side for prediction and smoothing
+}
+
+
+impl Default for PositionQa{
+	fn default() -> Self{
+		PositionQa{expected_spacing: X_SPACING, tolerance: 5.0, duplicate_distance: 0.001, window: 3}
+	}
+}
+
+
+fn distance(a:Point, b:Point) -> f64{
+	(a.x() - b.x()).hypot(a.y() - b.y())
+}
+
+
+fn median(values:&mut Vec<f64>) -> f64{
+	values.sort_by(|a,b| a.total_cmp(b));
+	let n = values.len();
+	if n % 2 == 1 {values[n/2]} else {(values[n/2 - 1] + values[n/2]) / 2.0}
+}
+
+
+//point at fractional index between (i,a) and (j,b)
+fn lerp(i:usize, a:Point, j:usize, b:Point, at:usize) -> Point{
+	let t = (at as f64 - i as f64) / (j as f64 - i as f64);
+	Point::new(a.x() + t * (b.x() - a.x()), a.y() + t * (b.y() - a.y()))
+}
+
+
+impl PositionQa{
+
+
+	//defaults with expected_spacing from median distance between consecutive positions on each channel. X_SPACING when none.
+	pub fn for_positions(positions:&PositionData) -> PositionQa{
+		let qa = PositionQa::default();
+		let mut distances:Vec<f64> = Vec::new();
+		for t in 0..positions.transverse_size(){
+			let channel = PositionQa::channel(positions, t);
+			distances.extend(channel.windows(2).filter_map(|w| Some(distance(w[0]?, w[1]?))).filter(|d| *d >= qa.duplicate_distance));
+		}
+		if distances.is_empty(){
+			return qa;
+		}
+		return PositionQa{expected_spacing: median(&mut distances), ..qa};
+	}
+
+
+	//positions along one channel. 0,0 counts as missing like everywhere else.
+	fn channel(positions:&PositionData, transverse:usize) -> Vec<Option<Point>>{
+		(0..positions.longitudinal_size())
+			.map(|l| positions.get_point(l, transverse))
+			.collect()
+	}
+
+
+	fn check_channel(&self, transverse:usize, channel:&[Option<Point>]) -> Vec<PositionIssue>{
+		let mut issues = Vec::new();
+		let mut usable:Vec<Option<Point>> = channel.to_vec();
+		let mut previous:Option<Point> = None;
+
+		for (l,p) in channel.iter().enumerate(){
+			match p{
+				None => issues.push(PositionIssue{longitudinal: l, transverse: transverse, kind: PositionIssueKind::Missing, deviation: None}),
+				Some(p) => {
+					if let Some(prev) = previous{
+						let d = distance(prev, *p);
+						if d < self.duplicate_distance{
+							issues.push(PositionIssue{longitudinal: l, transverse: transverse, kind: PositionIssueKind::Duplicate, deviation: Some(d)});
+							usable[l] = None;
+							continue;
+						}
+					}
+					previous = Some(*p);
+				}
+			}
+		}
+
+		let known:Vec<usize> = (0..usable.len()).filter(|l| usable[*l].is_some()).collect();
+		for (k,l) in known.iter().enumerate(){
+			let mut xs = Vec::new();
+			let mut ys = Vec::new();
+			for w in 1..=self.window{
+				if k >= w && k + w < known.len(){
+					let (i,j) = (known[k-w], known[k+w]);
+					let predicted = lerp(i, usable[i].unwrap(), j, usable[j].unwrap(), *l);
+					xs.push(predicted.x());
+					ys.push(predicted.y());
+				}
+			}
+			if xs.len() > 0{
+				let predicted = Point::new(median(&mut xs), median(&mut ys));
+				let d = distance(predicted, usable[*l].unwrap());
+				if d > self.tolerance * self.expected_spacing{
+					issues.push(PositionIssue{longitudinal: *l, transverse: transverse, kind: PositionIssueKind::Outlier, deviation: Some(d)});
+				}
+			}
+		}
+		issues.sort_by_key(|i| i.longitudinal);
+		return issues;
+	}
+
+
+	pub fn check(&self, positions:&PositionData) -> PositionReport{
+		let mut report = PositionReport::default();
+		for t in 0..positions.transverse_size(){
+			report.issues.extend(self.check_channel(t, &PositionQa::channel(positions, t)));
+		}
+		return report;
+	}
+
+
+	/*
+	replaces positions with issues in report. returns number replaced.
+	channels with < 2 good positions are left alone.
+	*/
+	pub fn repair(&self, positions:&mut PositionData, report:&PositionReport, method:RepairMethod) -> usize{
+		let mut repaired = 0;
+		for t in 0..positions.transverse_size(){
+			let mut channel = PositionQa::channel(positions, t);
+			let bad:Vec<usize> = report.issues.iter().filter(|i| i.transverse == t).map(|i| i.longitudinal).collect();
+			for l in bad.iter(){
+				channel[*l] = None;
+			}
+			let good:Vec<usize> = (0..channel.len()).filter(|l| channel[*l].is_some()).collect();
+			if good.len() < 2{
+				continue;
+			}
+
+			let mut fixed = channel.clone();
+			let mut k = 0;
+			for l in 0..channel.len(){
+				if channel[l].is_some(){
+					continue;
+				}
+				while k + 2 < good.len() && good[k+1] < l{
+					k += 1;
+				}
+				//between good[k] and good[k+1]. extrapolates at ends.
+				let (i,j) = (good[k], good[k+1]);
+				fixed[l] = Some(lerp(i, channel[i].unwrap(), j, channel[j].unwrap(), l));
+			}
+
+			if method == RepairMethod::Smooth{
+				let w = self.window;
+				let unsmoothed = fixed.clone();
+				for l in w..channel.len().saturating_sub(w){
+					let n = (2 * w + 1) as f64;
+					let (x,y) = (l-w..=l+w).fold((0.0,0.0), |acc, i| {let p = unsmoothed[i].unwrap(); (acc.0 + p.x(), acc.1 + p.y())});
+					fixed[l] = Some(Point::new(x / n, y / n));
+				}
+			}
+
+			for (l,p) in fixed.into_iter().enumerate(){
+				if let Some(p) = p{
+					if positions.get_point(l, t) != Some(p){
+						positions.add_point(l, t, p);
+						if channel[l].is_none(){
+							repaired += 1;
+						}
+					}
+				}
+			}
+		}
+		return repaired;
+	}
+}
+
+
+
+
+#[cfg(test)]
+mod position_qa_tests{
+	use super::*;
+
+	fn line(size:usize) -> PositionData{
+		let mut positions = PositionData::new(size,1);
+		for l in 0..size{
+			positions.add_point(l,0,Point::new(100.0 + l as f64 * X_SPACING, 100.0));
+		}
+		positions
+	}
+
+
+	#[test]
+	fn test_check(){
+		let mut positions = line(20);
+		positions.add_point(5,0,Point::new(0.0,0.0));//missing
+		positions.add_point(9,0,positions.get_point(8,0).unwrap());//duplicate
+		positions.add_point(14,0,Point::new(100.0 + 14.0 * X_SPACING, 103.0));//jump
+		let report = PositionQa::default().check(&positions);
+		let found:Vec<(usize,PositionIssueKind)> = report.issues.iter().map(|i| (i.longitudinal,i.kind)).collect();
+		assert_eq!(found, vec![(5,PositionIssueKind::Missing),(9,PositionIssueKind::Duplicate),(14,PositionIssueKind::Outlier)]);
+
+		//spacing from data. jump is outlier at default spacing but not when traces are 1m apart.
+		let mut positions = PositionData::new(20,1);
+		for l in 0..20{
+			positions.add_point(l,0,Point::new(100.0 + l as f64, 100.0));
+		}
+		positions.add_point(14,0,Point::new(114.0, 103.0));
+		let qa = PositionQa::for_positions(&positions);
+		assert_eq!(qa.expected_spacing, 1.0);
+		assert_eq!(qa.check(&positions).issues.len(), 0);
+		assert_eq!(PositionQa::default().check(&positions).count(PositionIssueKind::Outlier), 1);
+	}
+
+
+	#[test]
+	fn test_repair(){
+		let mut positions = line(20);
+		positions.add_point(0,0,Point::new(0.0,0.0));
+		positions.add_point(14,0,Point::new(100.0 + 14.0 * X_SPACING, 103.0));
+		let qa = PositionQa::default();
+		let report = qa.check(&positions);
+		assert_eq!(qa.repair(&mut positions, &report, RepairMethod::Interpolate), 2);
+		for l in [0,14]{
+			let p = positions.get_point(l,0).unwrap();
+			assert!((p.x() - 100.0 - l as f64 * X_SPACING).abs() < 1e-9 && (p.y() - 100.0).abs() < 1e-9, "bad repair at {l}: {p:?}");
+		}
+		assert_eq!(qa.check(&positions).issues.len(), 0);
+	}
+}
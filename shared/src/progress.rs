@@ -0,0 +1,154 @@
+use std::io::Write;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool,Ordering};
+use crate::error::{Result,GprError};
+
+
+/*
+progress reporting and cancellation for long jobs.
+
+stage is short description like "Reading input". total is None when not known.
+callers report every so often rather than every item so reporters can be slow-ish.
+long loops call check() between items and return its error to stop early.
+*/
+
+
+pub trait Progress: Sync{
+	fn update(&self, stage:&str, done:usize, total:Option<usize>);
+
+	fn is_cancelled(&self) -> bool{
+		false
+	}
+
+	//Err(GprError::Cancelled) once cancelled
+	fn check(&self) -> Result<()>{
+		if self.is_cancelled(){
+			return Err(GprError::Cancelled);
+		}
+		Ok(())
+	}
+}
+
+
+pub struct NoProgress;
+
+impl Progress for NoProgress{
+	fn update(&self, _stage:&str, _done:usize, _total:Option<usize>){}
+}
+
+
+
+//shared flag. clone into worker and keep one to cancel from.
+#[derive(Debug,Clone,Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken{
+	pub fn new() -> CancelToken{
+		CancelToken::default()
+	}
+
+	pub fn cancel(&self){
+		self.0.store(true, Ordering::SeqCst);
+	}
+
+	pub fn is_cancelled(&self) -> bool{
+		self.0.load(Ordering::SeqCst)
+	}
+}
+
+
+
+//calls function with every update. for GUIs.
+pub struct ProgressCallback<F: Fn(&str, usize, Option<usize>) + Sync>{
+	callback: F,
+	cancel: CancelToken,
+}
+
+impl<F: Fn(&str, usize, Option<usize>) + Sync> ProgressCallback<F>{
+	pub fn new(callback:F, cancel:CancelToken) -> ProgressCallback<F>{
+		ProgressCallback{callback: callback, cancel: cancel}
+	}
+}
+
+impl<F: Fn(&str, usize, Option<usize>) + Sync> Progress for ProgressCallback<F>{
+	fn update(&self, stage:&str, done:usize, total:Option<usize>){
+		(self.callback)(stage, done, total);
+	}
+
+	fn is_cancelled(&self) -> bool{
+		self.cancel.is_cancelled()
+	}
+}
+
+
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum ConsoleStyle{
+	Bar,//redrawn bar on stderr
+	Lines,//line of JSON per update on stderr. for other programs to read.
+}
+
+
+//progress on stderr. bar redrawn only when percentage changes.
+pub struct ConsoleProgress{
+	style: ConsoleStyle,
+	last: Mutex<(String,Option<usize>)>,//stage, percent
+}
+
+impl ConsoleProgress{
+	pub fn new(style:ConsoleStyle) -> ConsoleProgress{
+		ConsoleProgress{style: style, last: Mutex::new((String::new(), None))}
+	}
+}
+
+impl Progress for ConsoleProgress{
+	fn update(&self, stage:&str, done:usize, total:Option<usize>){
+		let percent = total.map(|t| if t == 0 {100} else {(done * 100 / t).min(100)});
+		let mut last = self.last.lock().unwrap();
+		if last.0 == stage && last.1 == percent && percent.is_some(){
+			return;
+		}
+		let mut err = std::io::stderr();
+		match self.style{
+			ConsoleStyle::Lines => {
+				let _ = writeln!(err, "{}", serde_json::json!({"stage": stage, "done": done, "total": total}));
+			}
+			ConsoleStyle::Bar => {
+				if last.0 != stage && last.0 != ""{
+					let _ = writeln!(err);
+				}
+				let _ = match percent{
+					Some(p) => write!(err, "\r{}: [{:<40}] {:>3}% {}/{}", stage, "#".repeat(p * 40 / 100), p, done, total.unwrap_or(0)),
+					None => write!(err, "\r{}: {}", stage, done),
+				};
+				if percent == Some(100){
+					let _ = writeln!(err);
+				}
+				let _ = err.flush();
+			}
+		}
+		//finished stage counts as new so next stage starts on new line only once
+		*last = if percent == Some(100) {(String::new(), None)} else {(stage.to_string(), percent)};
+	}
+}
+
+
+
+
+#[cfg(test)]
+mod progress_tests{
+	use super::*;
+
+	#[test]
+	fn test_cancel(){
+		let cancel = CancelToken::new();
+		let updates = Mutex::new(Vec::new());
+		let progress = ProgressCallback::new(|stage:&str, done, total| updates.lock().unwrap().push((stage.to_string(), done, total)), cancel.clone());
+		progress.update("stage", 1, Some(2));
+		assert!(progress.check().is_ok());
+		cancel.cancel();
+		assert!(matches!(progress.check(), Err(GprError::Cancelled)));
+		assert_eq!(*updates.lock().unwrap(), vec![("stage".to_string(), 1, Some(2))]);
+	}
+}
@@ -3,7 +3,8 @@ use std::io::BufReader;
 use std::io::BufRead;
 use regex::Regex;
 use std::fs::File;
-use anyhow::{Result,anyhow,bail};
+use std::path::{Path,PathBuf};
+use crate::error::{Result,GprError,InFile};
 use serde::{Serialize,Deserialize};
 //use proj::{Proj, Coord};
 use crate::core::Amplitude;
@@ -49,26 +50,40 @@ impl Trace{
 	
 	
 	pub fn from_line(line: String , transverse:usize , longitudinal:usize , samples:usize) -> Result<Trace> {
+		return Trace::parse(&line, transverse, longitudinal, samples).map_err(GprError::from);
+	}
+	
+	
+	/*
+	trace from tab separated x, y and amplitudes.
+	rows with < samples amplitudes give None for the rest. LineProblem says which column is wrong.
+	line of returned problem is 0 for caller to fill in.
+	*/
+	pub fn parse(line:&str , transverse:usize , longitudinal:usize , samples:usize) -> std::result::Result<Trace,LineProblem> {
 		
 		let mut r = Trace{proj_x : 0.0 , proj_y : 0.0 , transverse : transverse , longitudinal : longitudinal , amplitudes : vec![None; samples]};
+		let problem = |kind:ProblemKind, column:usize, reason:String| LineProblem{line: 0, column: Some(column), kind: kind, reason: reason};
 		
 		for (i, p) in line.split("\t").enumerate(){
 			match i {
 				
+				//empty field is missing value. empty x and y is no position, like 0 0.
+				_ if p.trim() == "" && i < samples+2 => {}
+				
 				0 => {
-						r.proj_x = p.parse::<f64>()?;
+						r.proj_x = p.trim().parse::<f64>().map_err(|_| problem(ProblemKind::NotNumber, 1, format!("x '{}' is not a number", p)))?;
 					}
 					
 				1 => {
-						r.proj_y = p.parse::<f64>()?;
+						r.proj_y = p.trim().parse::<f64>().map_err(|_| problem(ProblemKind::NotNumber, 2, format!("y '{}' is not a number", p)))?;
 					}	
 					
 				_other => {
-					if i <= samples+2{
-						r.amplitudes[i-2] = Some(p.parse::<Amplitude>()?);
+					if i < samples+2{
+						r.amplitudes[i-2] = Some(p.trim().parse::<Amplitude>().map_err(|_| problem(ProblemKind::NotNumber, i + 1, format!("amplitude '{}' is not a number", p)))?);
 					}
-					else{
-						bail!("Too many columns");
+					else if p.trim() != ""{
+						return Err(problem(ProblemKind::LongRow, i + 1, format!("Too many columns. Expected {}", samples + 2)));
 					}
 				}
 			}
@@ -79,8 +94,161 @@ impl Trace{
 		}
 		Ok(r)
 	}		
-			
 	
+	
+	//number of tab separated columns
+	fn columns(line:&str) -> usize{
+		line.trim_end_matches(|c| c == '\r' || c == ' ').split('\t').count()
+	}
+}
+
+
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProblemKind{
+	ShortRow,//fewer amplitudes than samples. rest are None.
+	LongRow,
+	NotNumber,
+	ExtraTrace,//more traces than X-lines * In-lines
+}
+
+
+//bad line in text file. line and column count from 1.
+#[derive(Debug,Clone,PartialEq,Serialize)]
+pub struct LineProblem{
+	pub line: usize,
+	pub column: Option<usize>,
+	pub kind: ProblemKind,
+	pub reason: String,
+}
+
+
+impl From<LineProblem> for GprError{
+	fn from(p:LineProblem) -> GprError{
+		let message = match p.column{
+			Some(c) => format!("column {}: {}", c, p.reason),
+			None => p.reason,
+		};
+		let e = GprError::parse(message);
+		return if p.line > 0 {e.at_line(p.line)} else {e};
+	}
+}
+
+
+
+/*
+order traces are in the file.
+X-lines is number of traces along the survey (longitudinal), In-lines number of channels across it (transverse)
+whichever order the file is in.
+*/
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Default,Serialize,Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TraceOrder{
+	#[default]
+	ChannelMajor,//every trace of channel 0 then every trace of channel 1... examiner exports like this.
+	LineMajor,//every channel of 1st trace then every channel of 2nd...
+}
+
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Default,Serialize,Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Direction{
+	#[default]
+	Forward,
+	Reverse,//file starts at last index
+}
+
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Default,Serialize,Deserialize)]
+pub struct TraceLayout{
+	#[serde(default)]
+	pub order: TraceOrder,
+	#[serde(default)]
+	pub longitudinal: Direction,
+	#[serde(default)]
+	pub transverse: Direction,
+}
+
+
+impl TraceLayout{
+	
+	//(longitudinal,transverse) of nth trace in file counting from 0. None past last trace.
+	pub fn index(&self, n:usize, x_lines:usize, in_lines:usize) -> Option<(usize,usize)>{
+		if n >= x_lines * in_lines{
+			return None;
+		}
+		let (longitudinal,transverse) = match self.order{
+			TraceOrder::ChannelMajor => (n % x_lines, n / x_lines),
+			TraceOrder::LineMajor => (n / in_lines, n % in_lines),
+		};
+		let longitudinal = if self.longitudinal == Direction::Reverse {x_lines - 1 - longitudinal} else {longitudinal};
+		let transverse = if self.transverse == Direction::Reverse {in_lines - 1 - transverse} else {transverse};
+		return Some((longitudinal,transverse));
+	}
+}
+
+
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Default,Serialize,Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ParseMode{
+	Strict,//error at first bad line and stop
+	#[default]
+	Lenient,//record bad line and carry on. short rows are kept.
+}
+
+
+pub const MAX_LISTED_PROBLEMS:usize = 1000;//problems kept in diagnostics. counts include the rest.
+
+
+//what TraceParser found wrong. bad lines before the 1st trace are included.
+#[derive(Debug,Clone,Default,Serialize)]
+pub struct ParseDiagnostics{
+	pub data_lines: usize,
+	pub traces: usize,
+	pub short_rows: usize,
+	pub long_rows: usize,
+	pub not_numbers: usize,
+	pub extra_traces: usize,
+	pub problems: Vec<LineProblem>,//1st MAX_LISTED_PROBLEMS
+}
+
+
+impl ParseDiagnostics{
+	
+	fn record(&mut self, problem:LineProblem){
+		match problem.kind{
+			ProblemKind::ShortRow => self.short_rows += 1,
+			ProblemKind::LongRow => self.long_rows += 1,
+			ProblemKind::NotNumber => self.not_numbers += 1,
+			ProblemKind::ExtraTrace => self.extra_traces += 1,
+		}
+		if self.problems.len() < MAX_LISTED_PROBLEMS{
+			self.problems.push(problem);
+		}
+	}
+	
+	
+	pub fn problem_count(&self) -> usize{
+		self.short_rows + self.long_rows + self.not_numbers + self.extra_traces
+	}
+	
+	
+	pub fn summary(&self) -> String{
+		format!("data lines:{} traces:{} short rows:{} long rows:{} not numbers:{} extra traces:{}", self.data_lines, self.traces, self.short_rows, self.long_rows, self.not_numbers, self.extra_traces)
+	}
+	
+	
+	//line, column, kind, reason of listed problems
+	pub fn write_csv(&self, output:&Path) -> Result<()>{
+		let mut wtr = csv::Writer::from_path(output).in_file(output)?;
+		for p in self.problems.iter(){
+			wtr.serialize(p).in_file(output)?;
+		}
+		wtr.flush().in_file(output)?;
+		Ok(())
+	}
 }
 
 
@@ -94,14 +262,14 @@ impl Trace{
 		//x
 		let x_expr = Regex::new(r"X-lines=(?<x>\d+)($|\D)").unwrap();
 		let Some(caps) = x_expr.captures(&line) else {
-			 anyhow::bail!("no x size(X-lines)");
+			 return Err(GprError::parse("no x size(X-lines)"));
 			};
 		let x_lines = usize::from_str(&caps["x"])?;
 		
 		//y
 		let y_expr = Regex::new(r"In-lines=(?<y>\d+)($|\D)").unwrap();
 		let Some(y_caps) = y_expr.captures(&line) else {
-			 anyhow::bail!("no y size(In-lines)");
+			 return Err(GprError::parse("no y size(In-lines)"));
 
 			};
 		let in_lines = usize::from_str(&y_caps["y"])?;
@@ -109,7 +277,7 @@ impl Trace{
 		//z
 		let z_expr = Regex::new(r"Samples=(?<z>\d+)($|\D)").unwrap();
 		let Some(z_caps) = z_expr.captures(&line) else {
-			 anyhow::bail!("no z size (Samples)");
+			 return Err(GprError::parse("no z size (Samples)"));
 
 			};
 		let samples = usize::from_str(&z_caps["z"])?;
@@ -129,9 +297,16 @@ impl Trace{
 		{
 			lines: std::io::Lines<BufReader<File>>,
 			current: usize,
-			start_line: usize,//1st line that sucessfully parses into trace
-			pub x_lines: usize,
-			pub in_lines: usize,
+			start_line: usize,//1st line that isn't header
+			blank_lines: usize,//whitespace only lines after start_line. skipped without taking a place
+			file: PathBuf,
+			mode: ParseMode,
+			layout: TraceLayout,
+			diagnostics: ParseDiagnostics,
+			stopped: bool,//after io error or strict error
+			pub header: Vec<String>,//lines up to and including sizes
+			pub x_lines: usize,//longitudinal size
+			pub in_lines: usize,//transverse size. channels.
 			pub samples: usize,
 		}
 
@@ -140,66 +315,128 @@ impl Trace{
 	impl TraceParser {
 		
 		
+		pub fn file(&self) -> &Path{
+			&self.file
+		}
+		
+		
 		//data starts on start line
 		pub fn new(filename : &str) -> Result<TraceParser>{
-			let f = File::open(filename)?;
+			let file = PathBuf::from(filename);
+			let f = File::open(&file).in_file(&file)?;
 			
 			let mut lines = BufReader::new(f).lines();		
+			let mut header:Vec<String> = Vec::new();
 			
 			//abort quickly if don't have sizes in 1st 100 lines instead of slowly searching every line.
 			for i in 0..= 100{
 				if let Some(res) = lines.next(){
 					if let Ok(line) = res{
+						header.push(line.clone());
 						if let Ok(s) = size_from_line(line){
-							return Ok(TraceParser {lines: lines, current:i , start_line:0 , x_lines:s.0 , in_lines:s.1 , samples:s.2});
+							return Ok(TraceParser {lines: lines, current:i , start_line:0 , blank_lines:0 , file:file , mode:ParseMode::default() , layout:TraceLayout::default() , diagnostics:ParseDiagnostics::default() , stopped:false , header:header , x_lines:s.0 , in_lines:s.1 , samples:s.2});
 						}
 					}
 				}
 			}
 			
-			Err(anyhow!("Check file. Could not find sizes(line like '#Volume: X-lines=11019, In-lines=25, Samples=255' within 1st 100 lines."))
+			Err(GprError::parse("Check file. Could not find sizes(line like '#Volume: X-lines=11019, In-lines=25, Samples=255' within 1st 100 lines.").in_file(&file))
 						
 		}
 		
+		
+		pub fn with_mode(mut self, mode:ParseMode) -> TraceParser{
+			self.mode = mode;
+			return self;
+		}
+		
+		
+		pub fn with_layout(mut self, layout:TraceLayout) -> TraceParser{
+			self.layout = layout;
+			return self;
+		}
+		
+		
+		//problems so far. iterate with by_ref() to read after.
+		pub fn diagnostics(&self) -> &ParseDiagnostics{
+			&self.diagnostics
+		}
+		
+		
+		//after sizes, lines starting with # or without tabs are header. anything else is a trace, good or bad.
+		fn is_header(line:&str) -> bool{
+			line.starts_with('#') || !line.contains('\t')
+		}
+		
 
 	}
 
 	impl Iterator for TraceParser {
 		type Item = Result<Trace>;
 		
+		/*
+		bad lines are recorded in diagnostics and skipped. in strict mode the 1st gives an error and ends iteration.
+		bad lines still take up their place so later traces get the right indices. blank lines don't.
+		*/
 		fn next(&mut self) -> Option<Result<Trace>> {
 			
-			if self.current>MAX_LINES{
-				return None
-			} 
-			
-			
-			if self.start_line ==0{
-				for _ in self.current..= MAX_LINES{
-					if let Ok(line) = self.lines.next()?{
-						self.current += 1;
-						if let Ok(t) = Trace::from_line(line , 0 , 0 , self.samples){
-							self.start_line = self.current;
-							return Some(Ok(t));
-						}
+			loop{
+				if self.stopped || self.current>MAX_LINES{
+					return None
+				} 
+				
+				let line = match self.lines.next()?{	//		self.lines.next() gives	Option<Result<String, std::io::Error> >
+					Ok(line) => line,
+					Err(e) => {
+						self.stopped = true;
+						return Some(Err(GprError::from(e).in_file(&self.file)));
+					}
+				};
+				self.current += 1;//current is 0 based
+				
+				if self.start_line == 0{
+					if TraceParser::is_header(&line){
+						continue;
 					}
+					self.start_line = self.current;
 				}
-			return None;
-			}
-			
-
-			if let Ok(line) = self.lines.next()?{	//		self.lines.next() gives	Option<Result<String, std::io::Error> >
-				self.current += 1;
+				if line.trim() == ""{
+					self.blank_lines += 1;
+					continue;
+				}
+				self.diagnostics.data_lines += 1;
 				
-				let tr: usize = (self.current - self.start_line) % self.x_lines;
-				let lon: usize = (self.current - self.start_line) / self.x_lines;
-				return Some(Trace::from_line(line , lon , tr , self.samples));
+				let index = self.layout.index(self.current - self.start_line - self.blank_lines, self.x_lines, self.in_lines);
+				
+				let mut problem = match index{
+					None => LineProblem{line: 0, column: None, kind: ProblemKind::ExtraTrace, reason: format!("More than {} traces (X-lines * In-lines)", self.x_lines * self.in_lines)},
+					Some((longitudinal,transverse)) => match Trace::parse(&line , transverse , longitudinal , self.samples){
+						Ok(trace) => {
+							let columns = Trace::columns(&line);
+							if columns >= self.samples + 2{
+								self.diagnostics.traces += 1;
+								return Some(Ok(trace));
+							}
+							let short = LineProblem{line: self.current + 1, column: None, kind: ProblemKind::ShortRow, reason: format!("{} columns. Expected {}", columns, self.samples + 2)};
+							if self.mode == ParseMode::Lenient{
+								self.diagnostics.traces += 1;
+								self.diagnostics.record(short);
+								return Some(Ok(trace));
+							}
+							short
+						}
+						Err(p) => p,
+					}
+				};
+				problem.line = self.current + 1;
+				self.diagnostics.record(problem.clone());
+				if self.mode == ParseMode::Strict{
+					self.stopped = true;
+					return Some(Err(GprError::from(problem).in_file(&self.file)));
+				}
 			}
-			
-			return None;
 		}
-		
-		
+	}
 		
 		
 
@@ -208,7 +445,6 @@ impl Trace{
 		
 		
 		
-	}
 	
 	
 //testing if serde faster than split string. doesn't seem to be.	
@@ -289,6 +525,104 @@ mod trace_tests {
 		let r = size_from_line("#Volume: X-lines=11019, In-lines=25, Samples=255".to_string()).unwrap();
 		assert_eq!(r, (11019,25,255));
 	}
+	
+	
+	#[test]
+	fn test_diagnostics(){
+		let file = std::env::temp_dir().join("trace_diagnostics_test.txt");
+		std::fs::write(&file, "#header\n#Volume: X-lines=2, In-lines=2, Samples=3\n0\t0\t1\t2\t3\n1\t0\t1\tx\t3\n0\t1\t1\t2\n1\t1\t1\t2\t3\t4\n").unwrap();
+		let filename = file.to_str().unwrap();
+		
+		let mut parser = TraceParser::new(filename).unwrap();
+		let traces:Vec<Trace> = parser.by_ref().map(|t| t.unwrap()).collect();
+		assert_eq!(traces.len(), 2);//good and short
+		let d = parser.diagnostics();
+		assert_eq!((d.data_lines, d.traces, d.short_rows, d.long_rows, d.not_numbers), (4, 2, 1, 1, 1));
+		assert_eq!(d.problems[0], LineProblem{line: 4, column: Some(4), kind: ProblemKind::NotNumber, reason: "amplitude 'x' is not a number".to_string()});
+		assert_eq!((d.problems[1].line, d.problems[1].kind), (5, ProblemKind::ShortRow));
+		assert_eq!((d.problems[2].line, d.problems[2].column), (6, Some(6)));
+		
+		let mut strict = TraceParser::new(filename).unwrap().with_mode(ParseMode::Strict);
+		assert!(strict.next().unwrap().is_ok());
+		let e = strict.next().unwrap().unwrap_err();
+		assert!(matches!(e, GprError::Parse{line: Some(4), ..}), "{e}");
+		assert!(strict.next().is_none());
+		
+		//bad 1st data row is a problem, not header, and keeps its place
+		std::fs::write(&file, "#Volume: X-lines=2, In-lines=1, Samples=2\n\n1,5\t0\t1\t2\n1\t0\t1\t2\n").unwrap();
+		let mut parser = TraceParser::new(filename).unwrap();
+		let traces:Vec<Trace> = parser.by_ref().map(|t| t.unwrap()).collect();
+		assert_eq!(traces.iter().map(|t| t.longitudinal).collect::<Vec<usize>>(), vec![1]);
+		assert_eq!((parser.diagnostics().problems[0].line, parser.diagnostics().problems[0].column), (3, Some(1)));
+		let e = TraceParser::new(filename).unwrap().with_mode(ParseMode::Strict).next().unwrap().unwrap_err();
+		assert!(matches!(e, GprError::Parse{line: Some(3), ..}), "{e}");
+		
+		//blank lines after data starts are skipped, even in strict mode
+		std::fs::write(&file, "#Volume: X-lines=2, In-lines=1, Samples=2\n0\t0\t1\t2\n \t\n\n1\t0\t1\t2\n\n").unwrap();
+		let traces:Vec<Trace> = TraceParser::new(filename).unwrap().with_mode(ParseMode::Strict).map(|t| t.unwrap()).collect();
+		assert_eq!(traces.iter().map(|t| t.longitudinal).collect::<Vec<usize>>(), vec![0,1]);
+		std::fs::remove_file(&file).unwrap();
+	}
+	
+	
+	//x and y of each trace are its longitudinal and transverse index. written in order of layout.
+	fn synthetic_file(layout:TraceLayout, x_lines:usize, in_lines:usize, extra:usize) -> PathBuf{
+		let longitudinal:Vec<usize> = if layout.longitudinal == Direction::Reverse {(0..x_lines).rev().collect()} else {(0..x_lines).collect()};
+		let transverse:Vec<usize> = if layout.transverse == Direction::Reverse {(0..in_lines).rev().collect()} else {(0..in_lines).collect()};
+		let mut order:Vec<(usize,usize)> = Vec::new();
+		match layout.order{
+			TraceOrder::ChannelMajor => for t in transverse.iter(){
+				for l in longitudinal.iter(){
+					order.push((*l,*t));
+				}
+			},
+			TraceOrder::LineMajor => for l in longitudinal.iter(){
+				for t in transverse.iter(){
+					order.push((*l,*t));
+				}
+			},
+		}
+		let mut text = format!("#Volume: X-lines={}, In-lines={}, Samples=2\n", x_lines, in_lines);
+		for (l,t) in order.iter().chain(std::iter::repeat(&(0,0)).take(extra)){
+			text.push_str(&format!("{}\t{}\t{}\t{}\n", l, t, l, t));
+		}
+		let file = std::env::temp_dir().join(format!("trace_layout_{:?}_{:?}_{:?}.txt", layout.order, layout.longitudinal, layout.transverse));
+		std::fs::write(&file, text).unwrap();
+		return file;
+	}
+	
+	
+	#[test]
+	fn test_trace_layouts(){
+		//13 channels so middle channel blanking in Trace::parse applies
+		let (x_lines,in_lines) = (7,13);
+		for order in [TraceOrder::ChannelMajor, TraceOrder::LineMajor]{
+			for longitudinal in [Direction::Forward, Direction::Reverse]{
+				for transverse in [Direction::Forward, Direction::Reverse]{
+					let layout = TraceLayout{order: order, longitudinal: longitudinal, transverse: transverse};
+					let file = synthetic_file(layout, x_lines, in_lines, 1);
+					let mut parser = TraceParser::new(file.to_str().unwrap()).unwrap().with_layout(layout);
+					let mut seen = vec![vec![false; in_lines]; x_lines];
+					//blanking is by channel after reversing. reversed file loses its 1st channel, not its 13th.
+					let first = parser.next().unwrap().unwrap();
+					let first_channel = if transverse == Direction::Reverse {in_lines - 1} else {0};
+					assert_eq!(first.transverse, first_channel, "{layout:?}");
+					assert_eq!(first.amplitudes[0].is_none(), transverse == Direction::Reverse, "{layout:?}");
+					for t in std::iter::once(Ok(first)).chain(parser.by_ref()){
+						let t = t.unwrap();
+						assert_eq!((t.longitudinal, t.transverse), (t.proj_x as usize, t.proj_y as usize), "{layout:?}");
+						let expected = if t.transverse == 12 {vec![None, None]} else {vec![Some(t.longitudinal as Amplitude), Some(t.transverse as Amplitude)]};
+						assert_eq!(t.amplitudes, expected, "{layout:?}");
+						assert!(!seen[t.longitudinal][t.transverse], "{layout:?} repeats {t:?}");
+						seen[t.longitudinal][t.transverse] = true;
+					}
+					assert!(seen.iter().flatten().all(|s| *s), "{layout:?}");
+					assert_eq!((parser.diagnostics().traces, parser.diagnostics().extra_traces), (x_lines * in_lines, 1));
+					std::fs::remove_file(&file).unwrap();
+				}
+			}
+		}
+	}
 		
 	
 }
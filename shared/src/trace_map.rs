@@ -0,0 +1,203 @@
+use rstar::RTree;
+use rstar::primitives::GeomWithData;
+use ndarray::Array2;
+use crate::amplitude_data::AmplitudeData;
+use crate::cluster_overlay::OverlayCell;
+use crate::core::Amplitude;
+use crate::plot::MapPlotData;
+use crate::error::{Result,GprError};
+use crate::position_data::ClusterFeature;
+use crate::merge::parse_geometry;
+use geo::{Geometry,LineString};
+
+
+/*
+plan of survey in real world coordinates from trace positions.
+indexed so points on map can be turned back into the nearest trace.
+features from extractor output files can be drawn over it as outlines.
+*/
+
+
+type TraceIndex = GeomWithData<[f64;2],(usize,usize)>;//longitudinal,transverse
+
+//neighbouring traces checked along each direction for working out spacing
+const SPACING_SAMPLES: usize = 1000;
+
+
+pub struct TraceMap{
+	index: RTree<TraceIndex>,
+	spacing: f64,//typical distance between neighbouring traces. larger of longitudinal and transverse.
+}
+
+
+impl TraceMap{
+
+	pub fn new(data:&AmplitudeData) -> TraceMap{
+		let mut points:Vec<TraceIndex> = Vec::new();
+		for (l,col) in data.points.iter().enumerate(){
+			for (t,p) in col.iter().enumerate(){
+				if let Some(p) = p{
+					points.push(GeomWithData::new([p.x(), p.y()], (l,t)));
+				}
+			}
+		}
+		let spacing = [(1,0), (0,1)].into_iter().filter_map(|step| TraceMap::median_step(data, step)).reduce(f64::max).unwrap_or(1.0);
+		return TraceMap{index: RTree::bulk_load(points), spacing: spacing};
+	}
+
+
+	//median distance between traces step (longitudinal,transverse) apart over sample of traces. None when all same position.
+	fn median_step(data:&AmplitudeData, step:(usize,usize)) -> Option<f64>{
+		let pairs:Vec<(usize,usize)> = (0..data.longitudinal_size.saturating_sub(step.0)).flat_map(|l| (0..data.transverse_size.saturating_sub(step.1)).map(move |t| (l,t))).collect();
+		let mut distances:Vec<f64> = pairs.iter().step_by((pairs.len() / SPACING_SAMPLES).max(1)).filter_map(|(l,t)| {
+			let p = data.points[*l][*t]?;
+			let q = data.points[l + step.0][t + step.1]?;
+			Some((q.x() - p.x()).hypot(q.y() - p.y())).filter(|d| *d > 0.0)
+		}).collect();
+		if distances.is_empty(){
+			return None;
+		}
+		distances.sort_by(|a,b| a.total_cmp(b));
+		return Some(distances[distances.len() / 2]);
+	}
+
+
+	pub fn is_empty(&self) -> bool{
+		return self.index.size() == 0;
+	}
+
+
+	pub fn spacing(&self) -> f64{
+		return self.spacing;
+	}
+
+
+	//(longitudinal,transverse) of trace nearest x,y
+	pub fn nearest(&self, x:f64, y:f64) -> Option<(usize,usize)>{
+		return self.index.nearest_neighbor(&[x,y]).map(|p| p.data);
+	}
+
+
+	/*
+	footprint of map of every trace. mark to add position.
+	values are longitudinal x transverse like depth slice plot data. features are cells of depth slice overlay.
+	*/
+	pub fn plot_data(&self, data:&AmplitudeData, values:Option<&Array2<Option<Amplitude>>>, features:&[OverlayCell]) -> Result<MapPlotData>{
+		if self.is_empty(){
+			return Err(GprError::plot("No trace positions"));
+		}
+		let value = |l:usize, t:usize| values.and_then(|v| v.get((l,t)).copied().flatten());
+		let points = self.index.iter().map(|p| (*p.geom(), value(p.data.0, p.data.1))).collect();
+		let features = features.iter().filter_map(|c| Some((position(data, c.x, c.y)?, c.group))).collect();
+
+		return Ok(MapPlotData{
+		points: points,
+		point_size: self.spacing,
+		sections: Vec::new(),
+		marker: None,
+		features: features,
+		outlines: Vec::new(),
+		x_label: "Easting".to_string(),
+		y_label: "Northing".to_string(),
+		});
+	}
+
+
+	//marker at longitudinal, transverse and lines along its longitudinal and transverse like cross hairs.
+	pub fn mark(data:&AmplitudeData, plot_data:&mut MapPlotData, longitudinal:usize, transverse:usize){
+		let transverse_line = (0..data.transverse_size).filter_map(|t| position(data, longitudinal, t)).collect();
+		let longitudinal_line = (0..data.longitudinal_size).filter_map(|l| position(data, l, transverse)).collect();
+		plot_data.sections = vec![transverse_line, longitudinal_line];
+		plot_data.marker = position(data, longitudinal, transverse);
+	}
+}
+
+
+fn position(data:&AmplitudeData, longitudinal:usize, transverse:usize) -> Option<[f64;2]>{
+	return data.points.get(longitudinal)?.get(transverse).copied().flatten().map(|p| [p.x(), p.y()]);
+}
+
+
+/*
+outlines of features from extractor output for map. rings of polygons and single points of point features.
+group is order depth band first appears in so features of same band share colour.
+*/
+pub fn feature_outlines(features:&[ClusterFeature]) -> Result<Vec<(Vec<[f64;2]>,usize)>>{
+	let mut bands:Vec<&str> = Vec::new();
+	let mut outlines = Vec::new();
+	for f in features.iter(){
+		let group = match bands.iter().position(|b| *b == f.depth_band){
+			Some(i) => i,
+			None => {
+				bands.push(&f.depth_band);
+				bands.len() - 1
+			}
+		};
+		let geometry = parse_geometry(&f.wkt).map_err(GprError::geometry)?;
+		add_outlines(&geometry, group, &mut outlines);
+	}
+	return Ok(outlines);
+}
+
+
+fn add_outlines(geometry:&Geometry<f64>, group:usize, outlines:&mut Vec<(Vec<[f64;2]>,usize)>){
+	let ring = |l:&LineString<f64>| (l.coords().map(|c| [c.x, c.y]).collect(), group);
+	match geometry{
+		Geometry::Point(p) => outlines.push((vec![[p.x(), p.y()]], group)),
+		Geometry::MultiPoint(m) => outlines.extend(m.iter().map(|p| (vec![[p.x(), p.y()]], group))),
+		Geometry::LineString(l) => outlines.push(ring(l)),
+		Geometry::MultiLineString(m) => outlines.extend(m.iter().map(ring)),
+		Geometry::Polygon(p) => outlines.extend(std::iter::once(p.exterior()).chain(p.interiors()).map(ring)),
+		Geometry::MultiPolygon(m) => outlines.extend(m.iter().flat_map(|p| std::iter::once(p.exterior()).chain(p.interiors())).map(ring)),
+		Geometry::GeometryCollection(c) => c.iter().for_each(|g| add_outlines(g, group, outlines)),
+		_ => {}
+	}
+}
+
+
+
+
+#[cfg(test)]
+mod trace_map_tests{
+	use super::*;
+	use geo::Point;
+
+	#[test]
+	fn test_trace_map(){
+		let mut data = AmplitudeData::from_size(4,3,2);
+		for l in 0..4{
+			for t in 0..3{
+				data.points[l][t] = Some(Point::new(100.0 + l as f64 * 0.5, 200.0 - t as f64 * 0.25));
+			}
+		}
+		data.points[3][2] = None;
+		let map = TraceMap::new(&data);
+		assert_eq!(map.spacing(), 0.5);
+		assert_eq!(map.nearest(101.1, 199.4), Some((2,2)));
+		assert_eq!(map.nearest(0.0, 0.0), Some((0,2)));
+
+		let features = [OverlayCell{x: 1, y: 1, group: 3}, OverlayCell{x: 3, y: 2, group: 0}];
+		let mut pd = map.plot_data(&data, None, &features).unwrap();
+		assert_eq!(pd.marker, None);
+		TraceMap::mark(&data, &mut pd, 1, 2);
+		assert_eq!(pd.points.len(), 11);
+		assert_eq!(pd.marker, Some([100.5, 199.5]));
+		assert_eq!(pd.sections[0].len(), 3);
+		assert_eq!(pd.sections[1].len(), 3);//no position at 3,2
+		assert_eq!(pd.features, vec![([100.5, 199.75], 3)]);
+
+		assert!(TraceMap::new(&AmplitudeData::from_size(2,2,2)).plot_data(&data, None, &[]).is_err());
+	}
+	
+	
+	#[test]
+	fn test_feature_outlines(){
+		let feature = |band:&str, wkt:&str| ClusterFeature{depth_band: band.to_string(), mean_amplitude: 0, start_chainage: None, end_chainage: None, min_offset: None, max_offset: None, wkt: wkt.to_string()};
+		let features = [feature("a", "MULTIPOLYGON(((0 0,1 0,1 1,0 0)),((5 5,6 5,6 6,5 5)))"), feature("b", "MULTIPOINT Z ((2 3 0.5))"), feature("a", "POLYGON Z ((0 0 1,1 0 1,1 1 1,0 0 1))")];
+		let outlines = feature_outlines(&features).unwrap();
+		assert_eq!(outlines.iter().map(|o| o.1).collect::<Vec<usize>>(), vec![0, 0, 1, 0]);
+		assert_eq!(outlines[1].0[0], [5.0, 5.0]);
+		assert_eq!(outlines[2].0, vec![[2.0, 3.0]]);
+		assert!(feature_outlines(&[feature("a", "")]).is_err());
+	}
+}
@@ -5,13 +5,28 @@ use std::error::Error;
 use rfd::FileDialog;//rust file dialog
 use slint::SharedString;
 
-use shared::amplitude_data::AmplitudeData;
-use shared::core::{usize_subtract};
+use shared::amplitude_data::{AmplitudeData,Aggregation};
+use shared::core::Amplitude;
 
-use viewer_slint::plot_slint::{plot_slint,plot_empty};
+use viewer_slint::plot_slint::{plot_slint,plot_line_slint,plot_map_slint,plot_empty,PlotMapping,MapImage,zoom_range,pan_range};
+use shared::plot::{PlotData,MapPlotData,Interpolation};
+use shared::colour_scale::{ColourScale,Colormap,Clip};
 use std::cmp::min;
+use std::ops::Range;
 use std::cell::RefCell;
 use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::mpsc::{self,Sender};
+use std::thread;
+use std::time::Duration;
+use slint::{Timer,TimerMode,ModelRc,VecModel,StandardListViewItem};
+use shared::error::GprError;
+use shared::progress::{CancelToken,ProgressCallback,NoProgress};
+use shared::cluster_overlay::{ClusterOverlay,ClusterSummary,OverlayCell,OverlayColouring};
+use shared::clustering::ClusterSettings;
+use shared::position_data::{Layer,ClusterFeature};
+use shared::geojson::is_geojson;
+use shared::trace_map::{TraceMap,feature_outlines};
 
 
 slint::include_modules!();
@@ -35,39 +50,274 @@ fn select_file() -> String{
 	return "".into();
 }
 
-struct AppData{
-	amplitudes: AmplitudeData,
-	longitudinal_position: usize
+//plot panels. same numbers as plot_clicked in app-window.slint
+#[derive(Debug,Clone,Copy,PartialEq)]
+enum Panel{
+	Transverse = 0,//transverse by depth
+	Longitudinal = 1,//longitudinal by depth
+	Horizontal = 2,//longitudinal by transverse
+	AScan = 3,//amplitude by depth. shares view of transverse panel so depths line up.
 }
 
 
-impl AppData{
+const PANELS: usize = 4;
+
+
+impl Panel{
+	fn from_index(i:i32) -> Option<Panel>{
+		match i{
+			0 => Some(Panel::Transverse),
+			1 => Some(Panel::Longitudinal),
+			2 => Some(Panel::Horizontal),
+			3 => Some(Panel::AScan),
+			_ => None,
+		}
+	}
 	
 	
-	fn load_file(&mut self , file:&str){
-		//self.amplitudes = AmplitudeData::from_text_file(file).unwrap_or(AmplitudeData::from_size(0,0,0))
-		
-		let data = AmplitudeData::from_text_file(file);
-		if data.is_ok(){
-			self.amplitudes = data.unwrap();
+	//false where x is value rather than index. zooming and panning only in y.
+	fn has_index_x(&self) -> bool{
+		return *self != Panel::AScan;
+	}
+	
+	
+	//(x size, y size) of data along axes of panel.
+	fn extent(&self, ui:&AppWindow) -> (usize,usize){
+		let (x,y) = match self{
+			Panel::Transverse | Panel::AScan => (ui.get_data_size_t(), ui.get_data_size_d()),
+			Panel::Longitudinal => (ui.get_data_size_l(), ui.get_data_size_d()),
+			Panel::Horizontal => (ui.get_data_size_l(), ui.get_data_size_t()),
+		};
+		return (x.max(0) as usize, y.max(0) as usize);
+	}
+	
+	
+	fn view(&self, ui:&AppWindow) -> PlotView{
+		match self{
+			Panel::Transverse | Panel::AScan => ui.get_transverse_view(),
+			Panel::Longitudinal => ui.get_longitudinal_view(),
+			Panel::Horizontal => ui.get_horizontal_view(),
 		}
-		else{
-			self.amplitudes = AmplitudeData::from_size(0,0,0);
-			println!("error{:#?}",data);
+	}
+	
+	
+	fn set_view(&self, ui:&AppWindow, view:PlotView){
+		match self{
+			Panel::Transverse | Panel::AScan => ui.set_transverse_view(view),
+			Panel::Longitudinal => ui.set_longitudinal_view(view),
+			Panel::Horizontal => ui.set_horizontal_view(view),
 		}
 	}
 	
 	
+	//(x index, y index) of current position in panel
+	fn position(&self, ui:&AppWindow) -> (usize,usize){
+		let (x,y) = match self{
+			Panel::Transverse | Panel::AScan => (ui.get_transverse_position(), ui.get_depth()),
+			Panel::Longitudinal => (ui.get_longitudinal_position(), ui.get_depth()),
+			Panel::Horizontal => (ui.get_longitudinal_position(), ui.get_transverse_position()),
+		};
+		return (x.max(0) as usize, y.max(0) as usize);
+	}
+	
+	
+	//pans view to centre on position when position is outside it
+	fn show_position(&self, ui:&AppWindow){
+		let view = self.view(ui);
+		let (x,y) = self.position(ui);
+		let (x_extent,y_extent) = self.extent(ui);
+		let centred = |range:Range<usize>, i:usize, extent:usize| {
+			if range.contains(&i) {range} else {pan_range(&range, i as f64 - (range.start + range.end) as f64 / 2.0, extent)}
+		};
+		self.set_view(ui, PlotView::from_ranges(centred(view.x_range(), x, x_extent), centred(view.y_range(), y, y_extent)));
+	}
+	
+	
+	//whole of data
+	fn reset_view(&self, ui:&AppWindow){
+		let (x,y) = self.extent(ui);
+		self.set_view(ui, PlotView::from_ranges(0..x, 0..y));
+	}
+}
+
+
+impl PlotView{
+	fn x_range(&self) -> Range<usize>{
+		return self.x_start.max(0) as usize .. self.x_end.max(0) as usize;
+	}
+	
+	fn y_range(&self) -> Range<usize>{
+		return self.y_start.max(0) as usize .. self.y_end.max(0) as usize;
+	}
+	
+	fn from_ranges(x:Range<usize>, y:Range<usize>) -> PlotView{
+		return PlotView{x_start: x.start as i32, x_end: x.end as i32, y_start: y.start as i32, y_end: y.end as i32};
+	}
 }
 
 
+impl ColourSettings{
+	fn scale(&self) -> ColourScale{
+		let clip = match self.clip{
+			1 => Clip::Percentile(self.percentile),
+			2 => Clip::Range{min: self.min, max: self.max},
+			_ => Clip::Full,
+		};
+		return ColourScale{
+			colormap: Colormap::ALL.get(self.colormap as usize).copied().unwrap_or_default(),
+			clip: clip,
+			symmetric: self.symmetric,
+			gamma: self.gamma,
+		};
+	}
+	
+	fn interpolation(&self) -> Interpolation{
+		if self.smooth {Interpolation::Bilinear} else {Interpolation::Nearest}
+	}
+}
+
+
+//scroll step
+const ZOOM_FACTOR: f64 = 1.25;
+
+
+//image of plot data or empty plot. remembers mapping of panel for clicks.
+fn plot_panel(plot_data:shared::error::Result<PlotData>, overlay:&[OverlayCell], colours:&ColourSettings, panel:Panel, mappings:&RefCell<[Option<PlotMapping>;PANELS]>, width:i32, height:i32) -> slint::Image{
+	let plotted = plot_data.ok().and_then(|pd| plot_slint(&pd, overlay, &colours.scale(), colours.interpolation(), width as u32, height as u32).ok());
+	return show_plot(plotted, panel, mappings);
+}
 
 
+fn show_plot(plotted:Option<(slint::Image,PlotMapping)>, panel:Panel, mappings:&RefCell<[Option<PlotMapping>;PANELS]>) -> slint::Image{
+	mappings.borrow_mut()[panel as usize] = plotted.as_ref().map(|p| p.1);
+	match plotted{
+		Some((image,_)) => image,
+		None => plot_empty().unwrap(),
+	}
+}
+
+
+
+//0 hidden, 1 by cluster, 2 by depth band
+fn overlay_colouring(overlay:i32) -> Option<OverlayColouring>{
+	match overlay{
+		1 => Some(OverlayColouring::Cluster),
+		2 => Some(OverlayColouring::DepthBand),
+		_ => None,
+	}
+}
+
+
+//0 mean, 1 max, 2 rms
+fn aggregation_from_index(aggregation:i32) -> Aggregation{
+	match aggregation{
+		1 => Aggregation::Max,
+		2 => Aggregation::Rms,
+		_ => Aggregation::Mean,
+	}
+}
+
+
+
+enum LoadMessage{
+	Progress(i32),//percent
+	Loaded(String,AmplitudeData,TraceMap),
+	Failed(String,GprError),
+}
+
+
+//reads file on new thread. messages tagged with load. cancel with returned token.
+fn start_load(load:usize, file:String, sender:Sender<(usize,LoadMessage)>) -> CancelToken{
+	let cancel = CancelToken::new();
+	let worker_cancel = cancel.clone();
+	thread::spawn(move || {
+		let progress_sender = sender.clone();
+		let progress = ProgressCallback::new(move |_stage:&str, done, total:Option<usize>| {
+			if let Some(total) = total.filter(|t| *t > 0){
+				let _ = progress_sender.send((load, LoadMessage::Progress((done * 100 / total) as i32)));
+			}
+		}, worker_cancel);
+		let message = match AmplitudeData::from_text_file_with_progress(&file, &progress){
+			Ok(amplitudes) => {
+				let trace_map = TraceMap::new(&amplitudes);
+				LoadMessage::Loaded(file, amplitudes, trace_map)
+			}
+			Err(e) => LoadMessage::Failed(file, e),
+		};
+		let _ = sender.send((load, message));
+	});
+	return cancel;
+}
+
+
+
+//found clusters. status describes result of export when output given.
+enum ExtractMessage{
+	Found(ClusterOverlay, String),
+	Failed(GprError),
+}
+
+
+//finds clusters on new thread. writes features to output when given. messages tagged with load the data came from.
+fn start_extract(load:usize, amplitudes:Arc<AmplitudeData>, settings:ClusterSettings, output:Option<String>, sender:Sender<(usize,ExtractMessage)>){
+	thread::spawn(move || {
+		let message = match ClusterOverlay::find(&amplitudes, settings, Layer::defaults(), &NoProgress){
+			Err(e) => ExtractMessage::Failed(e),
+			Ok(overlay) => {
+				let status = match output{
+					None => format!("{} clusters", overlay.clusters.len()),
+					Some(output) => match write_features(&amplitudes, &overlay, &output){
+						Ok(count) => format!("{} clusters. {} features written to {}", overlay.clusters.len(), count, output),
+						Err(e) => e.to_string(),
+					},
+				};
+				ExtractMessage::Found(overlay, status)
+			}
+		};
+		let _ = sender.send((load, message));
+	});
+}
+
+
+//multipolygon per cluster and depth band like extractor. geojson or csv by extension.
+fn write_features(amplitudes:&AmplitudeData, overlay:&ClusterOverlay, output:&str) -> shared::error::Result<usize>{
+	let features = amplitudes.position_data().multipolygon_features_from_clusters(overlay.clusters.clone(), overlay.settings.size_threshold, overlay.layers.clone(), &NoProgress)?;
+	let count = features.len();
+	if is_geojson(output){
+		ClusterFeature::write_geojson(&features, output, None)?;
+	}
+	else{
+		ClusterFeature::write_csv(features, output)?;
+	}
+	return Ok(count);
+}
+
+
+
+struct AppData{
+	amplitudes: Arc<AmplitudeData>,
+	clusters: Option<ClusterOverlay>,
+	cluster_summaries: Vec<ClusterSummary>,//list in ui
+	trace_map: TraceMap,
+	map_features: Vec<(Vec<[f64;2]>,usize)>,//outlines of loaded feature file
+}
+
+
+//map footprint and what it was drawn for. depth, aggregation and colours are None when not draped.
+struct MapCache{
+	key: (i32, Option<(i32,i32,i32,ColourSettings)>, i32, i32, i32),//data version, drape, overlay, width, height
+	plot_data: MapPlotData,
+	image: MapImage,
+}
+
 
 fn main() -> Result<(), Box<dyn Error>> {
 	let app_data = Rc::new(RefCell::new(AppData{
-		amplitudes: AmplitudeData::from_size(0,0,0),
-		longitudinal_position:0,
+		amplitudes: Arc::new(AmplitudeData::from_size(0,0,0)),
+		clusters: None,
+		cluster_summaries: Vec::new(),
+		trace_map: TraceMap::new(&AmplitudeData::from_size(0,0,0)),
+		map_features: Vec::new(),
 		}));
 	
     let ui = AppWindow::new()?;
@@ -81,18 +331,192 @@ fn main() -> Result<(), Box<dyn Error>> {
         }
     });
 	*/
-	//(longitudinal:int , transverse: int , transverse_size:int , depth: int ,depth_size:int)
+	let mappings:Rc<RefCell<[Option<PlotMapping>;PANELS]>> = Rc::new(RefCell::new([None;PANELS]));
+	
 	let state_copy = app_data.clone();
-	ui.on_plot_transverse(move |lon, trans, trans_size, depth, depth_size,width,height| {
+	let mappings_copy = mappings.clone();
+	ui.on_plot_transverse(move |_version, lon, trans, depth, view, overlay, colours, width, height| {
 		let data = state_copy.borrow();
-		if let Ok(pd) = data.amplitudes.transverse_plot_data(lon as usize ,trans as usize, trans_size as usize, depth as usize, depth_size as usize){
-			return plot_slint(&pd, width as u32, height as u32).unwrap();
+		let cells = match (&data.clusters, overlay_colouring(overlay)){
+			(Some(clusters), Some(colouring)) => clusters.transverse_cells(lon as usize, &view.x_range(), &view.y_range(), colouring),
+			_ => Vec::new(),
+		};
+		return plot_panel(data.amplitudes.transverse_plot_data(lon as usize ,trans as usize, view.x_range(), depth as usize, view.y_range()), &cells, &colours, Panel::Transverse, &mappings_copy, width, height);
+    });
+		
+		
+		
+	let state_copy = app_data.clone();
+	let mappings_copy = mappings.clone();
+	ui.on_plot_horizontal(move |_version, lon, trans, depth, depth_window, aggregation, view, overlay, colours, width, height| {
+		let data = state_copy.borrow();
+		let depth_window = depth_window.max(1) as usize;
+		let min_depth = (depth as usize).saturating_sub((depth_window - 1) / 2);
+		let cells = match (&data.clusters, overlay_colouring(overlay)){
+			(Some(clusters), Some(colouring)) => clusters.depth_slice_cells(&(min_depth .. min_depth + depth_window), &view.x_range(), &view.y_range(), colouring),
+			_ => Vec::new(),
+		};
+		return plot_panel(data.amplitudes.depth_slice_plot_data(lon as usize ,trans as usize, view.x_range(), view.y_range(), depth as usize, depth_window, aggregation_from_index(aggregation)), &cells, &colours, Panel::Horizontal, &mappings_copy, width, height);
+    });
+	
+	
+	//whole survey. drape is depth slice of horizontal plot over all traces. features from every depth.
+	//footprint only redrawn when something other than position changes.
+	let map_cache:Rc<RefCell<Option<MapCache>>> = Rc::new(RefCell::new(None));
+	let state_copy = app_data.clone();
+	let map_cache_copy = map_cache.clone();
+	ui.on_plot_map(move |version, lon, trans, depth, depth_window, aggregation, drape, overlay, colours, width, height| {
+		let data = state_copy.borrow();
+		let amplitudes = &data.amplitudes;
+		let key = (version, drape.then(|| (depth, depth_window, aggregation, colours.clone())), overlay, width, height);
+		let mut cache = map_cache_copy.borrow_mut();
+		if cache.as_ref().map_or(true, |c| c.key != key){
+			let (l_range, t_range) = (0..amplitudes.longitudinal_size, 0..amplitudes.transverse_size);
+			let cells = match (&data.clusters, overlay_colouring(overlay)){
+				(Some(clusters), Some(colouring)) => clusters.depth_slice_cells(&(0..amplitudes.depth_size), &l_range, &t_range, colouring),
+				_ => Vec::new(),
+			};
+			let slice = if drape {amplitudes.depth_slice_plot_data(lon as usize, trans as usize, l_range, t_range, depth as usize, depth_window.max(1) as usize, aggregation_from_index(aggregation)).ok()} else {None};
+			let colouring = slice.as_ref().map(|s| colours.scale().colouring(&s.amplitudes));
+			*cache = data.trace_map.plot_data(amplitudes, slice.as_ref().map(|s| &s.amplitudes), &cells).ok().and_then(|mut plot_data| {
+				plot_data.outlines = data.map_features.clone();
+				let image = plot_map_slint(&plot_data, colouring.as_ref(), width as u32, height as u32).ok()?;
+				Some(MapCache{key: key, plot_data: plot_data, image: image})
+			});
 		}
-		else{
+		let Some(cache) = cache.as_mut() else {
 			return plot_empty().unwrap();
+		};
+		TraceMap::mark(amplitudes, &mut cache.plot_data, lon as usize, trans as usize);
+		return cache.image.with_position(&cache.plot_data).unwrap_or_else(|_| plot_empty().unwrap());
+	});
+	
+	
+	//click on map moves to nearest trace
+	let state_copy = app_data.clone();
+	let ui_handle = ui.as_weak();
+	ui.on_map_clicked(move |x, y, width, height| {
+		let Some(ui_h) = ui_handle.upgrade() else {return};
+		let Some(mapping) = map_cache.borrow().as_ref().map(|c| c.image.mapping) else {return};
+		let Some((px,py)) = mapping.position_in_element(x as f64, y as f64, width as f64, height as f64) else {return};
+		let Some((l,t)) = state_copy.borrow().trace_map.nearest(px, py) else {return};
+		ui_h.set_longitudinal_position(l as i32);
+		ui_h.set_transverse_position(t as i32);
+		for panel in [Panel::Transverse, Panel::Longitudinal, Panel::Horizontal]{
+			panel.show_position(&ui_h);
 		}
+	});
+	
+	
+	//extractor output drawn on map. replaces previous file.
+	let state_copy = app_data.clone();
+	let ui_handle = ui.as_weak();
+	ui.on_load_features(move || {
+		let Some(ui_h) = ui_handle.upgrade() else {return};
+		let file = FileDialog::new()
+			.add_filter("Features", &["geojson", "json", "csv"])
+			.pick_file();
+		let Some(file) = file else {return};
+		let file = file.display().to_string();
+		match ClusterFeature::read(&file).and_then(|features| feature_outlines(&features)){
+			Ok(outlines) => {
+				state_copy.borrow_mut().map_features = outlines;
+				ui_h.set_status(format!("Features from {} shown on map", file).into());
+				ui_h.set_data_version(ui_h.get_data_version() + 1);
+			}
+			Err(e) => ui_h.set_status(e.to_string().into()),
+		}
+	});
+	
+	let state_copy = app_data.clone();
+	let mappings_copy = mappings.clone();
+	ui.on_plot_longitudinal(move |_version, lon, trans, depth, view, overlay, colours, width, height| {
+		let data = state_copy.borrow();
+		let cells = match (&data.clusters, overlay_colouring(overlay)){
+			(Some(clusters), Some(colouring)) => clusters.longitudinal_cells(trans as usize, &view.x_range(), &view.y_range(), colouring),
+			_ => Vec::new(),
+		};
+		return plot_panel(data.amplitudes.longitudinal_plot_data(lon as usize ,trans as usize, view.x_range(), depth as usize, view.y_range()), &cells, &colours, Panel::Longitudinal, &mappings_copy, width, height);
     });
-		
+	
+	
+	let state_copy = app_data.clone();
+	let mappings_copy = mappings.clone();
+	ui.on_plot_ascan(move |_version, lon, trans, depth, view, neighbours, threshold, show_threshold, width, height| {
+		let data = state_copy.borrow();
+		let threshold = show_threshold.then_some(threshold.clamp(Amplitude::MIN as i32, Amplitude::MAX as i32) as Amplitude);
+		let plotted = data.amplitudes.trace_plot_data(lon as usize, trans as usize, neighbours.max(0) as usize, depth as usize, view.y_range(), threshold).ok()
+			.and_then(|pd| plot_line_slint(&pd, width as u32, height as u32).ok());
+		return show_plot(plotted, Panel::AScan, &mappings_copy);
+	});
+	
+	
+	//scroll zooms about mouse. axis: 0 both, 1 x only, 2 y only.
+	let ui_handle = ui.as_weak();
+	let mappings_copy = mappings.clone();
+	ui.on_plot_zoomed(move |panel, x, y, width, height, zoom_in, axis| {
+		let Some(ui_h) = ui_handle.upgrade() else {return};
+		let Some(panel) = Panel::from_index(panel) else {return};
+		let Some(mapping) = mappings_copy.borrow()[panel as usize] else {return};
+		let Some((centre_x,centre_y)) = mapping.position_in_element(x as f64, y as f64, width as f64, height as f64) else {return};
+		let factor = if zoom_in {1.0 / ZOOM_FACTOR} else {ZOOM_FACTOR};
+		let view = panel.view(&ui_h);
+		let (x_extent,y_extent) = panel.extent(&ui_h);
+		let x_range = if axis == 2 || !panel.has_index_x() {view.x_range()} else {zoom_range(&view.x_range(), centre_x, factor, x_extent)};
+		let y_range = if axis == 1 {view.y_range()} else {zoom_range(&view.y_range(), centre_y, factor, y_extent)};
+		panel.set_view(&ui_h, PlotView::from_ranges(x_range, y_range));
+	});
+	
+	
+	//drag pans from view when drag started. (dx,dy) total movement.
+	let ui_handle = ui.as_weak();
+	let mappings_copy = mappings.clone();
+	ui.on_plot_dragged(move |panel, start_view, dx, dy, width, height| {
+		let Some(ui_h) = ui_handle.upgrade() else {return};
+		let Some(panel) = Panel::from_index(panel) else {return};
+		let Some(mapping) = mappings_copy.borrow()[panel as usize] else {return};
+		let Some((x_per,y_per)) = mapping.indices_per_length(width as f64, height as f64) else {return};
+		let (x_extent,y_extent) = panel.extent(&ui_h);
+		let dx = if panel.has_index_x() {dx as f64} else {0.0};
+		let x_range = pan_range(&start_view.x_range(), -dx * x_per, x_extent);
+		let y_range = pan_range(&start_view.y_range(), -dy as f64 * y_per, y_extent);
+		panel.set_view(&ui_h, PlotView::from_ranges(x_range, y_range));
+	});
+	
+	
+	let ui_handle = ui.as_weak();
+	ui.on_reset_view(move |panel| {
+		let Some(ui_h) = ui_handle.upgrade() else {return};
+		if let Some(panel) = Panel::from_index(panel){
+			panel.reset_view(&ui_h);
+		}
+	});
+	
+	
+	//click on plot moves position. every panel follows because they share the position properties.
+	let ui_handle = ui.as_weak();
+	ui.on_plot_clicked(move |panel, x, y, width, height| {
+		let Some(ui_h) = ui_handle.upgrade() else {return};
+		let Some(panel) = Panel::from_index(panel) else {return};
+		let Some(mapping) = mappings.borrow()[panel as usize] else {return};
+		let Some((i,j)) = mapping.index_in_element(x as f64, y as f64, width as f64, height as f64) else {return};
+		let clamp = |v:usize, size:i32| min(v as i32, size - 1).max(0);
+		match panel{
+			Panel::Transverse => {
+				ui_h.set_transverse_position(clamp(i, ui_h.get_data_size_t()));
+				ui_h.set_depth(clamp(j, ui_h.get_data_size_d()));
+			}
+			Panel::Longitudinal => {
+				ui_h.set_longitudinal_position(clamp(i, ui_h.get_data_size_l()));
+				ui_h.set_depth(clamp(j, ui_h.get_data_size_d()));
+			}
+			Panel::Horizontal => {
+				ui_h.set_longitudinal_position(clamp(i, ui_h.get_data_size_l()));
+				ui_h.set_transverse_position(clamp(j, ui_h.get_data_size_t()));
+			}
+			Panel::AScan => ui_h.set_depth(clamp(j, ui_h.get_data_size_d())),
+		}
+	});
 		
 		
 		//transverse_position_changed
@@ -100,37 +524,160 @@ fn main() -> Result<(), Box<dyn Error>> {
 	//longitudinal:usize , min_depth: usize , max_depth: usize , min_transverse:usize , max_transverse:usize
 	
 	
-	let state_copy = app_data.clone();
-
-	ui.on_select_file(
-	{
-		let ui_handle = ui.as_weak();
-		let ui_h = ui_handle.unwrap();
-
-		move || {
-		let mut data = state_copy.borrow_mut();
+	//loading on worker thread. messages polled on ui thread so AppData can stay Rc<RefCell>.
+	let (sender,receiver) = mpsc::channel::<(usize,LoadMessage)>();
+	let loading:Rc<RefCell<Option<(usize,CancelToken)>>> = Rc::new(RefCell::new(None));//current load
+	let load_count = Rc::new(RefCell::new(0usize));
+	let loaded = Rc::new(RefCell::new(0usize));//load of shown data. 0 before 1st. data_version also changes on redraws.
+	
+	let loading_copy = loading.clone();
+	ui.on_select_file(move || {
 		let file:String = select_file();
 		if file.len() > 0{
-			ui_h.set_load_progress(50);
-			let r = AmplitudeData::from_text_file(&file);
-			if r.is_ok(){
-				data.amplitudes = r.unwrap();
-				ui_h.set_file(file.into());
+			*load_count.borrow_mut() += 1;
+			let load = *load_count.borrow();
+			//only newest load matters
+			if let Some((_,previous)) = loading_copy.borrow_mut().replace((load, start_load(load, file, sender.clone()))){
+				previous.cancel();
 			}
-			else{
-				data.amplitudes = AmplitudeData::from_size(0,0,0);
-				ui_h.set_file(r.unwrap_err().to_string().into());
+		}
+	});
+	
+	let loading_copy = loading.clone();
+	ui.on_cancel_load(move || {
+		if let Some((_,cancel)) = loading_copy.borrow().as_ref(){
+			cancel.cancel();
+		}
+	});
+	
+	let state_copy = app_data.clone();
+	let ui_handle = ui.as_weak();
+	let loaded_copy = loaded.clone();
+	let poll_timer = Timer::default();
+	poll_timer.start(TimerMode::Repeated, Duration::from_millis(100), move || {
+		let Some(ui_h) = ui_handle.upgrade() else {return};
+		while let Ok((load,message)) = receiver.try_recv(){
+			//messages from replaced loads are ignored
+			if loading.borrow().as_ref().map(|l| l.0) != Some(load){
+				continue;
+			}
+			match message{
+				LoadMessage::Progress(percent) => ui_h.set_load_progress(percent.min(99)),
+				LoadMessage::Loaded(file, amplitudes, trace_map) => {
+					let mut data = state_copy.borrow_mut();
+					data.amplitudes = Arc::new(amplitudes);
+					data.trace_map = trace_map;
+					data.clusters = None;
+					data.cluster_summaries.clear();
+					ui_h.set_cluster_items(ModelRc::new(VecModel::<StandardListViewItem>::default()));
+					ui_h.set_data_size_l(data.amplitudes.longitudinal_size as i32);
+					ui_h.set_data_size_t(data.amplitudes.transverse_size as i32);
+					ui_h.set_data_size_d(data.amplitudes.depth_size as i32);
+					for panel in [Panel::Transverse, Panel::Longitudinal, Panel::Horizontal]{
+						panel.reset_view(&ui_h);
+					}
+					ui_h.set_file(file.into());
+					ui_h.set_status("".into());
+					ui_h.set_data_version(ui_h.get_data_version() + 1);
+					*loaded_copy.borrow_mut() = load;
+					loading.borrow_mut().take();
+				}
+				LoadMessage::Failed(file, e) => {
+					//previous data kept
+					let message = match e{
+						GprError::Cancelled => format!("Loading {} cancelled", file),
+						e => e.to_string(),
+					};
+					ui_h.set_status(message.into());
+					loading.borrow_mut().take();
+				}
 			}
-			
-			ui_h.set_data_size_l(data.amplitudes.longitudinal_size as i32);
-			ui_h.set_data_size_t(data.amplitudes.transverse_size as i32);
-			ui_h.set_data_size_d(data.amplitudes.depth_size as i32);
-
 		}
+		if loading.borrow().is_none(){
+			ui_h.set_load_progress(-1);
 		}
-	}
-	);
+	});
 
+	
+	
+	//cluster finding on worker thread like loading. overlay replaced when done.
+	let (extract_sender,extract_receiver) = mpsc::channel::<(usize,ExtractMessage)>();
+	let extracting = Rc::new(RefCell::new(false));
+	
+	let state_copy = app_data.clone();
+	let ui_handle = ui.as_weak();
+	let extracting_copy = extracting.clone();
+	let loaded_copy = loaded.clone();
+	let extract = move |output:Option<String>| {
+		let Some(ui_h) = ui_handle.upgrade() else {return};
+		if *extracting_copy.borrow(){
+			return;
+		}
+		let settings = ClusterSettings{
+			amplitude_threshold: ui_h.get_amplitude_threshold().clamp(Amplitude::MIN as i32, Amplitude::MAX as i32) as Amplitude,
+			size_threshold: ui_h.get_size_threshold().max(0) as usize,
+			max_gap: ui_h.get_max_gap().max(0) as usize,
+		};
+		*extracting_copy.borrow_mut() = true;
+		ui_h.set_status("Finding clusters...".into());
+		start_extract(*loaded_copy.borrow(), state_copy.borrow().amplitudes.clone(), settings, output, extract_sender.clone());
+	};
+	
+	let extract_copy = extract.clone();
+	ui.on_find_clusters(move || extract_copy(None));
+	
+	ui.on_export_clusters(move || {
+		let output = FileDialog::new()
+			.add_filter("GeoJSON", &["geojson", "json"])
+			.add_filter("csv", &["csv"])
+			.save_file();
+		if let Some(output) = output{
+			extract(Some(output.display().to_string()));
+		}
+	});
+	
+	let state_copy = app_data.clone();
+	let ui_handle = ui.as_weak();
+	let extract_timer = Timer::default();
+	extract_timer.start(TimerMode::Repeated, Duration::from_millis(100), move || {
+		let Some(ui_h) = ui_handle.upgrade() else {return};
+		while let Ok((load,message)) = extract_receiver.try_recv(){
+			*extracting.borrow_mut() = false;
+			//clusters of data replaced by load while finding are ignored
+			if load != *loaded.borrow(){
+				continue;
+			}
+			match message{
+				ExtractMessage::Found(overlay, status) => {
+					let mut data = state_copy.borrow_mut();
+					data.cluster_summaries = overlay.summaries();
+					let items:Vec<StandardListViewItem> = data.cluster_summaries.iter().map(|c| StandardListViewItem::from(SharedString::from(c.description()))).collect();
+					ui_h.set_cluster_items(ModelRc::new(VecModel::from(items)));
+					data.clusters = Some(overlay);
+					ui_h.set_status(status.into());
+					ui_h.set_data_version(ui_h.get_data_version() + 1);
+				}
+				ExtractMessage::Failed(e) => ui_h.set_status(e.to_string().into()),
+			}
+		}
+	});
+	
+	
+	//jump to highest amplitude of cluster
+	let state_copy = app_data.clone();
+	let ui_handle = ui.as_weak();
+	ui.on_cluster_selected(move |index| {
+		let Some(ui_h) = ui_handle.upgrade() else {return};
+		let data = state_copy.borrow();
+		let Some(summary) = data.cluster_summaries.get(index.max(0) as usize) else {return};
+		ui_h.set_longitudinal_position(summary.peak.x as i32);
+		ui_h.set_transverse_position(summary.peak.y as i32);
+		ui_h.set_depth(summary.peak.z as i32);
+		for panel in [Panel::Transverse, Panel::Longitudinal, Panel::Horizontal]{
+			panel.show_position(&ui_h);
+		}
+	});
+	
 
     ui.run()?;
 
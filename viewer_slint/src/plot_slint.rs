@@ -2,23 +2,16 @@
 
 
 use plotters::prelude::*;
-use colorgrad::{Gradient,LinearGradient};
 use std::error::Error;
-use slint::SharedPixelBuffer;
-use shared::plot::PlotData;
-use std::cmp::max;
+use slint::{SharedPixelBuffer,Rgb8Pixel};
+use shared::plot::{PlotData,LinePlotData,MapPlotData,Interpolation};
+use shared::colour_scale::{ColourScale,Colouring,COLOURBAR_WIDTH};
+use shared::cluster_overlay::OverlayCell;
+use std::cmp::min;
+use std::ops::Range;
 
 
 
-fn make_rectangle(gradient: &LinearGradient, x: usize , x_size:f64 , y: usize ,y_size:f64, value:i16) -> Rectangle<(f64, f64)> {
-	let c = gradient.at(value as f32).to_rgba8();
-	let color = RGBColor(c[0] , c[1] , c[2]);
-	println!("color:{:?}",color);
-
-	return Rectangle::new([(x as f64 * x_size, y as f64 * y_size), ((x + 1) as f64 * x_size, (y + 1) as f64 *y_size)] , color.filled());
-}
-
-
 /*
 data for plotting heatmap.
 
@@ -45,7 +38,116 @@ pub fn plot_empty() -> Result<slint::Image, Box<dyn Error>>{
 }
 
 
-pub fn plot_slint(data: &PlotData, pixels:u32, lines:u32) -> Result<slint::Image, Box<dyn Error>>{
+/*
+where data is in plotted image. for turning clicks back into indices.
+pixel range is plotting area inside axes. y increases down the image as it does in the data.
+*/
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct PlotMapping{
+	pub image_width: u32,
+	pub image_height: u32,
+	pub left: f64,
+	pub right: f64,
+	pub top: f64,
+	pub bottom: f64,
+	pub min_x: f64,
+	pub max_x: f64,
+	pub min_y: f64,
+	pub max_y: f64,
+	pub x_scale: f64,
+	pub y_scale: f64,
+}
+
+
+impl PlotMapping{
+	
+	//(x, y) in fractional indices of image pixel. None outside plotting area.
+	pub fn position(&self, px:f64, py:f64) -> Option<(f64,f64)>{
+		if px < self.left || px >= self.right || py < self.top || py >= self.bottom{
+			return None;
+		}
+		let x = self.min_x + (px - self.left) / (self.right - self.left) * (self.max_x - self.min_x);
+		let y = self.min_y + (py - self.top) / (self.bottom - self.top) * (self.max_y - self.min_y);
+		return Some((x / self.x_scale, y / self.y_scale));
+	}
+	
+	
+	//(x index, y index) of image pixel. None outside plotting area.
+	pub fn index(&self, px:f64, py:f64) -> Option<(usize,usize)>{
+		let (x,y) = self.position(px,py)?;
+		return Some((x.floor().max(0.0) as usize, y.floor().max(0.0) as usize));
+	}
+	
+	
+	//image pixel of point in element of width x height showing image with image-fit: contain.
+	fn image_pixel(&self, x:f64, y:f64, width:f64, height:f64) -> Option<(f64,f64)>{
+		let scale = self.element_scale(width,height)?;
+		let offset_x = (width - self.image_width as f64 * scale) / 2.0;
+		let offset_y = (height - self.image_height as f64 * scale) / 2.0;
+		return Some(((x - offset_x) / scale, (y - offset_y) / scale));
+	}
+	
+	
+	//element length per image pixel.
+	fn element_scale(&self, width:f64, height:f64) -> Option<f64>{
+		let scale = (width / self.image_width as f64).min(height / self.image_height as f64);
+		if scale <= 0.0 || !scale.is_finite(){
+			return None;
+		}
+		return Some(scale);
+	}
+	
+	
+	pub fn position_in_element(&self, x:f64, y:f64, width:f64, height:f64) -> Option<(f64,f64)>{
+		let (px,py) = self.image_pixel(x,y,width,height)?;
+		return self.position(px,py);
+	}
+	
+	
+	pub fn index_in_element(&self, x:f64, y:f64, width:f64, height:f64) -> Option<(usize,usize)>{
+		let (px,py) = self.image_pixel(x,y,width,height)?;
+		return self.index(px,py);
+	}
+	
+	
+	//(x indices, y indices) per element length. for turning drags into pans.
+	pub fn indices_per_length(&self, width:f64, height:f64) -> Option<(f64,f64)>{
+		let scale = self.element_scale(width,height)?;
+		let x = (self.max_x - self.min_x) / self.x_scale / ((self.right - self.left) * scale);
+		let y = (self.max_y - self.min_y) / self.y_scale / ((self.bottom - self.top) * scale);
+		return Some((x,y));
+	}
+}
+
+
+
+//range scaled by factor about centre staying within 0..extent. at least 1 index long.
+pub fn zoom_range(range:&Range<usize>, centre:f64, factor:f64, extent:usize) -> Range<usize>{
+	if extent == 0{
+		return 0..0;
+	}
+	let len = range.len().max(1) as f64;
+	let new_len = (len * factor).round().clamp(1.0, extent as f64);
+	let start = centre - (centre - range.start as f64) * new_len / len;
+	let start = start.round().clamp(0.0, extent as f64 - new_len) as usize;
+	return start..start + new_len as usize;
+}
+
+
+//range moved by shift indices staying within 0..extent.
+pub fn pan_range(range:&Range<usize>, shift:f64, extent:usize) -> Range<usize>{
+	let len = min(range.len(), extent);
+	let start = (range.start as f64 + shift).round().clamp(0.0, (extent - len) as f64) as usize;
+	return start..start + len;
+}
+
+
+
+/*
+with colourbar at right. amplitudes rasterised straight into plotting area then overlay and cross hairs drawn over.
+overlay cells are shaded in colour of their group.
+*/
+pub fn plot_slint(data: &PlotData, overlay:&[OverlayCell], scale:&ColourScale, interpolation:Interpolation, pixels:u32, lines:u32) -> Result<(slint::Image,PlotMapping), Box<dyn Error>>{
 	
 	let mut pixel_buffer = SharedPixelBuffer::new(pixels, lines);
 	let size = (pixel_buffer.width(), pixel_buffer.height());		
@@ -61,15 +163,13 @@ pub fn plot_slint(data: &PlotData, pixels:u32, lines:u32) -> Result<slint::Image
 	let max_x_val = (data.min_x_index+data.amplitudes.shape()[0]) as f64 * data.x_scale;
 	let max_y_val = (data.min_y_index+data.amplitudes.shape()[1]) as f64 * data.y_scale;
 
-	let gradient = colorgrad::GradientBuilder::new()
-	.html_colors(&["green", "red"])
-	.domain(&[i16::MIN as f32, i16::MAX as f32])
-	.build::<colorgrad::LinearGradient>()?;
-	
+	let colouring = scale.colouring(&data.amplitudes);
 	
 	root_drawing_area.fill(&WHITE)?;
+	let (plot_area, colourbar_area) = root_drawing_area.split_horizontally(pixels.saturating_sub(COLOURBAR_WIDTH));
+	colouring.draw_colourbar(&colourbar_area)?;
 
-	let mut chart = ChartBuilder::on(&root_drawing_area)
+	let mut chart = ChartBuilder::on(&plot_area)
 		.margin(5)
 		.x_label_area_size(40)
 		.y_label_area_size(60)
@@ -84,31 +184,39 @@ pub fn plot_slint(data: &PlotData, pixels:u32, lines:u32) -> Result<slint::Image
 	.label_style(("sans-serif", 20))
 	.draw()?;
 	
-	let mut rects: Vec<Rectangle<(f64, f64)>> = Vec::new();
-	
-	for (ind,a) in data.amplitudes.indexed_iter(){
-		if let Some(v) = a{
-			let c = gradient.at(*v as f32).to_rgba8();
-			let color = RGBColor(c[0] , c[1] , c[2]);
-			let x = ind.0;
-			let y = ind.1;
-			rects.push(
-				Rectangle::new([(min_x_val + data.x_scale * x as f64, min_y_val + data.y_scale * y as f64), (min_x_val + data.x_scale * (x+1) as f64, min_y_val + data.y_scale * (y+1) as f64)] ,
-				color.filled())
-			);
-		}
+	let (x_pixels,y_pixels) = chart.plotting_area().get_pixel_range();
+	let raster_size = ((x_pixels.end - x_pixels.start).max(0) as u32, (y_pixels.end - y_pixels.start).max(0) as u32);
+	let raster = data.rasterise(&colouring, raster_size.0 as usize, raster_size.1 as usize, interpolation, WHITE);
+	if let Some(element) = BitMapElement::with_owned_buffer((x_pixels.start, y_pixels.start), raster_size, raster){
+		root_drawing_area.draw(&element)?;
 	}
-	chart.draw_series(rects)?;
 	
-	//cross hairs
-	chart.draw_series(LineSeries::new(vec![(0.0 , data.marker_y as f64) , (max_x_val , data.marker_y as f64)] , BLUE))?;
-	chart.draw_series(LineSeries::new(vec![(data.marker_x as f64 , min_y_val) , (data.marker_x as f64 , max_y_val as f64)] , BLUE))?;
+	chart.draw_series(overlay.iter().map(|c| {
+		let (x,y) = (c.x as f64 * data.x_scale, c.y as f64 * data.y_scale);
+		Rectangle::new([(x, y), (x + data.x_scale, y + data.y_scale)], Palette99::pick(c.group).mix(0.6).filled())
+	}))?;
+	
+	//cross hairs. through middle of marked sample.
+	let marker_x = (data.marker_x as f64 + 0.5) * data.x_scale;
+	let marker_y = (data.marker_y as f64 + 0.5) * data.y_scale;
+	if marker_y > min_y_val && marker_y < max_y_val{
+		chart.draw_series(LineSeries::new(vec![(min_x_val , marker_y) , (max_x_val , marker_y)] , BLUE))?;
+	}
+	if marker_x > min_x_val && marker_x < max_x_val{
+		chart.draw_series(LineSeries::new(vec![(marker_x , min_y_val) , (marker_x , max_y_val)] , BLUE))?;
+	}
+	
+	let mapping = PlotMapping{image_width: pixels, image_height: lines,
+		left: x_pixels.start as f64, right: x_pixels.end as f64, top: y_pixels.start as f64, bottom: y_pixels.end as f64,
+		min_x: min_x_val, max_x: max_x_val, min_y: min_y_val, max_y: max_y_val, x_scale: data.x_scale, y_scale: data.y_scale};
 
 	root_drawing_area.present()?;
 	drop(chart);
+	drop(plot_area);
+	drop(colourbar_area);
 	drop(root_drawing_area);
 
-	return Ok(slint::Image::from_rgb8(pixel_buffer));
+	return Ok((slint::Image::from_rgb8(pixel_buffer), mapping));
 		
 }
 
@@ -121,32 +229,139 @@ pub fn plot_slint(data: &PlotData, pixels:u32, lines:u32) -> Result<slint::Image
 
 
 
+//line plot. x of mapping is value so only y index means anything.
+pub fn plot_line_slint(data: &LinePlotData, pixels:u32, lines:u32) -> Result<(slint::Image,PlotMapping), Box<dyn Error>>{
+	let mut pixel_buffer = SharedPixelBuffer::new(pixels, lines);
+	let size = (pixel_buffer.width(), pixel_buffer.height());
+	let backend = BitMapBackend::with_buffer(pixel_buffer.make_mut_bytes(), size);
+	let root_drawing_area = backend.into_drawing_area();
+	root_drawing_area.fill(&WHITE)?;
+	
+	let drawn = data.draw(&root_drawing_area)?;
+	let mapping = PlotMapping{image_width: pixels, image_height: lines,
+		left: drawn.x_pixels.start as f64, right: drawn.x_pixels.end as f64, top: drawn.y_pixels.start as f64, bottom: drawn.y_pixels.end as f64,
+		min_x: drawn.x_values.start, max_x: drawn.x_values.end, min_y: drawn.y_values.start, max_y: drawn.y_values.end, x_scale: 1.0, y_scale: data.y_scale};
+	
+	root_drawing_area.present()?;
+	drop(root_drawing_area);
+	return Ok((slint::Image::from_rgb8(pixel_buffer), mapping));
+}
+
+
+
+/*
+map footprint in real world coordinates. mapping gives coordinates, y decreasing down the image.
+footprint is kept and position drawn over a copy of it so moving doesn't redraw every trace.
+*/
+pub struct MapImage{
+	footprint: SharedPixelBuffer<Rgb8Pixel>,
+	pub mapping: PlotMapping,
+}
+
+
+pub fn plot_map_slint(data: &MapPlotData, colouring:Option<&Colouring>, pixels:u32, lines:u32) -> Result<MapImage, Box<dyn Error>>{
+	let mut pixel_buffer = SharedPixelBuffer::new(pixels, lines);
+	let size = (pixel_buffer.width(), pixel_buffer.height());
+	let backend = BitMapBackend::with_buffer(pixel_buffer.make_mut_bytes(), size);
+	let root_drawing_area = backend.into_drawing_area();
+	root_drawing_area.fill(&WHITE)?;
+	
+	let drawn = data.draw_footprint(&root_drawing_area, colouring)?;
+	let mapping = PlotMapping{image_width: pixels, image_height: lines,
+		left: drawn.x_pixels.start as f64, right: drawn.x_pixels.end as f64, top: drawn.y_pixels.start as f64, bottom: drawn.y_pixels.end as f64,
+		min_x: drawn.x_values.start, max_x: drawn.x_values.end, min_y: drawn.y_values.start, max_y: drawn.y_values.end, x_scale: 1.0, y_scale: 1.0};
+	
+	root_drawing_area.present()?;
+	drop(root_drawing_area);
+	return Ok(MapImage{footprint: pixel_buffer, mapping: mapping});
+}
+
+
+impl MapImage{
+	
+	//sections and marker of data over footprint. data is what footprint was drawn from.
+	pub fn with_position(&self, data: &MapPlotData) -> Result<slint::Image, Box<dyn Error>>{
+		let mut pixel_buffer = self.footprint.clone();
+		let size = (pixel_buffer.width(), pixel_buffer.height());
+		let backend = BitMapBackend::with_buffer(pixel_buffer.make_mut_bytes(), size);
+		let root_drawing_area = backend.into_drawing_area();
+		data.draw_position(&root_drawing_area)?;
+		root_drawing_area.present()?;
+		drop(root_drawing_area);
+		return Ok(slint::Image::from_rgb8(pixel_buffer));
+	}
+}
+
+
+
 #[cfg(test)]
 mod plot_tests{
 	use super::*;
-	const X_SPACING: f64 = 0.072;
-	const Y_SPACING: f64 = 0.0762;
-	const Z_SPACING:f64 = 0.01309289678;// TIME_INTERVAL*C/EPSILON.sqrt();
-
+	use shared::amplitude_data::AmplitudeData;
 
 
 	#[test]
 	fn test_plot(){
-		const image: &str = r"C:\Users\drew.bennett\Documents\3d_gpr_poi_finder\test\outputs\test_plot.svg";
-	
-		let pd = PlotData{min_x_index: 6,
-		min_y_index: 2,
-		marker_x:8,
-		marker_y:3,
-		x_scale:Y_SPACING, //meters per index
-		y_scale:Z_SPACING, //meters per index
-		x_label: "Transverse(m)".to_string(),
-		y_label: "Depth(m)".to_string(),
-		amplitudes: vec![vec![Some(0),Some(1000),Some(2000),Some(3000)],vec![Some(3000),Some(4000),Some(5000),None]],
-		};
-		
-		let _ = pd.plot(image);
-
-		
+		let data = AmplitudeData::from_size(2,4,4).transverse_plot_data(0, 1, 0..4, 2, 1..4).unwrap();
+		let (image,mapping) = plot_slint(&data, &[], &ColourScale::default(), Interpolation::Nearest, 400, 300).unwrap();
+		assert_eq!((image.size().width, image.size().height), (400, 300));
+		assert!(mapping.left > 0.0 && mapping.right < 400.0 && mapping.top >= 0.0 && mapping.bottom < 300.0, "{mapping:?}");
+		assert_eq!(mapping.index(mapping.left, mapping.top), Some((0,1)));
+		assert_eq!(mapping.index(mapping.right - 0.5, mapping.bottom - 0.5), Some((3,3)));
+	}
+	
+	
+	//200 x 100 image. plotting area 20..120 x 10..60 showing 10 x 5 indices.
+	fn mapping() -> PlotMapping{
+		return PlotMapping{image_width: 200, image_height: 100, left: 20.0, right: 120.0, top: 10.0, bottom: 60.0,
+			min_x: 0.0, max_x: 10.0, min_y: 0.0, max_y: 5.0, x_scale: 1.0, y_scale: 1.0};
+	}
+	
+	
+	#[test]
+	fn test_mapping(){
+		let m = mapping();
+		assert_eq!(m.position(20.0, 10.0), Some((0.0,0.0)));
+		assert_eq!(m.position(70.0, 35.0), Some((5.0,2.5)));
+		assert_eq!(m.index(119.9, 59.9), Some((9,4)), "last pixel is last index");
+		assert_eq!(m.index(120.0, 30.0), None, "right of plotting area");
+		assert_eq!(m.index(19.9, 30.0), None, "on axis labels");
+		assert_eq!(m.index(70.0, 60.0), None, "below plotting area");
 	}
-}
\ No newline at end of file
+	
+	
+	#[test]
+	fn test_mapping_in_element(){
+		//400 x 400 element shows image at scale 2 with 100 high bands above and below
+		let m = mapping();
+		assert_eq!(m.index_in_element(140.0, 50.0, 400.0, 400.0), None, "letterbox");
+		assert_eq!(m.index_in_element(140.0, 350.0, 400.0, 400.0), None, "letterbox");
+		assert_eq!(m.position_in_element(40.0, 120.0, 400.0, 400.0), Some((0.0,0.0)));
+		assert_eq!(m.index_in_element(239.8, 219.8, 400.0, 400.0), Some((9,4)), "last pixel is last index");
+		assert_eq!(m.indices_per_length(400.0, 400.0), Some((0.05,0.05)));
+		assert_eq!(m.index_in_element(10.0, 10.0, 0.0, 0.0), None, "not laid out");
+	}
+	
+	
+	#[test]
+	fn test_zoom_range(){
+		//centre stays at same place in range
+		assert_eq!(zoom_range(&(10..20), 14.0, 0.5, 100), 12..17);
+		assert_eq!(zoom_range(&(10..20), 14.0, 2.0, 100), 6..26);
+		//within 0..extent
+		assert_eq!(zoom_range(&(0..10), 1.0, 2.0, 100), 0..20);
+		assert_eq!(zoom_range(&(90..100), 99.0, 2.0, 100), 80..100);
+		assert_eq!(zoom_range(&(10..20), 14.0, 10.0, 30), 0..30);
+		assert_eq!(zoom_range(&(10..11), 10.0, 0.1, 30), 10..11, "at least 1 index");
+		assert_eq!(zoom_range(&(0..0), 0.0, 2.0, 0), 0..0);
+	}
+	
+	
+	#[test]
+	fn test_pan_range(){
+		assert_eq!(pan_range(&(10..20), 5.0, 50), 15..25);
+		assert_eq!(pan_range(&(10..20), -15.0, 50), 0..10);
+		assert_eq!(pan_range(&(10..20), 100.0, 50), 40..50);
+		assert_eq!(pan_range(&(0..80), 10.0, 50), 0..50, "longer than extent");
+	}
+}